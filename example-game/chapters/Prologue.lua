@@ -0,0 +1,9 @@
+local yield = coroutine.yield
+
+function signal.pre_battle()
+	yield(say("Mittens has wandered into the dogs' territory."))
+end
+
+function signal.post_battle()
+	yield(say("The dogs have been driven off, for now."))
+end
@@ -0,0 +1,16 @@
+local yield = coroutine.yield
+
+function signal.turn_start(turn, phase)
+	if turn == 1 and phase == "Player1" then
+		yield(pan_camera(13, 5))
+		yield(say("Defeat the boss!"))
+		yield(pan_camera(2, 5))
+	end
+end
+
+function signal.unit_defeated(unit)
+	if unit == "example_game:first_boss" then
+		set_flag("example_game:boss_defeated")
+		yield(say("The boss has been defeated."))
+	end
+end
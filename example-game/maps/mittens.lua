@@ -0,0 +1,8 @@
+local yield = coroutine.yield
+
+function signal.trigger(trigger, unit)
+	if trigger == "village" then
+		yield(say("Mittens", "This village looks friendly."))
+		yield(give_item(unit, "Iron Sword"))
+	end
+end
@@ -0,0 +1,69 @@
+use crate::{DefeatCondition, MapReference, Objective, Shop};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+make_reference!(chapters::Chapter => ChapterReference);
+
+/// A single battle of a campaign, along with the story told before and after it.
+#[cfg_attr(feature = "sucrose", derive(Resource))]
+#[derive(Clone, Default, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Chapter {
+	pub name: String,
+	/// The map which this chapter's battle is fought on.
+	pub map: MapReference,
+	/// Replaces the map's objectives, if present.
+	pub objectives: Option<Vec<Objective>>,
+	/// Replaces the map's defeat conditions, if present.
+	pub defeat: Option<Vec<DefeatCondition>>,
+	/// Lua script whose `signal.pre_battle` is run once the map is loaded, relative to the chapter file.
+	pub pre_script: Option<String>,
+	/// Lua script whose `signal.post_battle` is run once the map is won, relative to the chapter file.
+	pub post_script: Option<String>,
+	/// Tiles which the player's units are placed on,
+	/// if they are in the roster but are not placed by the map.
+	pub deployment: Vec<Deployment>,
+	/// Shops opened on the map for this chapter, alongside the map's own.
+	pub shops: Vec<Shop>,
+	/// Music played throughout the chapter, relative to the chapter file.
+	pub music: Option<String>,
+	/// The chapter played after this one.
+	///
+	/// If absent, the next chapter in the module's campaign is played instead.
+	pub next: Option<ChapterReference>,
+	/// Chapters which are played instead of `next` if a flag is set.
+	/// The first branch whose flag is set is taken.
+	pub branches: Vec<Branch>,
+}
+
+impl Chapter {
+	/// Returns the chapter which follows this one, given the flags set by scripts.
+	///
+	/// `None` means that the chapter after this one in the campaign should be played.
+	#[must_use]
+	pub fn next(&self, flags: &BTreeSet<String>) -> Option<&ChapterReference> {
+		self.branches
+			.iter()
+			.find(|branch| flags.contains(&branch.flag))
+			.map(|branch| &branch.chapter)
+			.or(self.next.as_ref())
+	}
+}
+
+/// A tile which one of the player's units may be deployed to.
+#[cfg_attr(feature = "sucrose", derive(Resource))]
+#[derive(Clone, Default, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Deployment {
+	pub x: u32,
+	pub y: u32,
+}
+
+/// Leads to another chapter if a flag has been set.
+#[cfg_attr(feature = "sucrose", derive(Resource))]
+#[derive(Clone, Default, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Branch {
+	pub flag: String,
+	pub chapter: ChapterReference,
+}
@@ -43,6 +43,8 @@ pub struct Class {
 	pub ferry: bool,
 	/// Enemy units cannot enter 1-tile gaps around this unit.
 	pub zone_of_control: bool,
+	/// Extra distance this unit can see in fog of war.
+	pub vision: u32,
 
 	// Weaknesses
 	pub armored: bool,
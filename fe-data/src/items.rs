@@ -14,6 +14,8 @@ make_reference!(items::Item => ItemReference);
 pub struct WeaponItem {
 	pub damage: u32,
 	pub weight: u32,
+	/// Remaining uses before the weapon breaks.
+	/// A durability of 0 marks an unbreakable weapon.
 	pub durability: u32,
 }
 
@@ -21,7 +23,10 @@ pub struct WeaponItem {
 #[derive(Clone, Default, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(default)]
 pub struct HealItem {
+	/// Hit points restored by each use.
 	pub amount: u32,
+	/// Remaining uses before the item is used up.
+	/// An item with 0 uses never runs out.
 	pub uses: u32,
 }
 
@@ -98,6 +103,8 @@ pub struct Item {
 	pub description: String,
 	pub icon: Image,
 	pub value: Option<NonZeroU32>,
+	/// Extra distance the unit carrying this item can see in fog of war.
+	pub vision: u32,
 	#[serde(rename = "type")]
 	pub ty: ItemType,
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 pub mod containers;
@@ -39,6 +40,7 @@ macro_rules! import {
 }
 
 import! {
+	chapter,
 	items,
 	map,
 	class,
@@ -46,6 +48,128 @@ import! {
 	unit,
 }
 
+/// How a module's game is drawn.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Display {
+	/// Width of the canvas which the game is drawn to, in pixels.
+	pub width: u32,
+	/// Height of the canvas which the game is drawn to, in pixels.
+	pub height: u32,
+	/// How the canvas is scaled up to fit the window.
+	pub scaling: Scaling,
+}
+
+impl Default for Display {
+	fn default() -> Self {
+		// The resolution of the GBA.
+		Self {
+			width: 240,
+			height: 160,
+			scaling: Scaling::default(),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Scaling {
+	/// Scales by the largest whole number that fits, so that every pixel is the same size.
+	#[default]
+	Integer,
+	/// Scales to fill as much of the window as possible, keeping the canvas's aspect ratio.
+	Fill,
+}
+
+/// How units hold and share items.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Inventory {
+	/// Number of items each unit can hold, including its equipped weapon.
+	pub slots: u32,
+	/// Who may use the convoy, which holds items shared by every unit.
+	pub convoy: ConvoyAccess,
+}
+
+impl Default for Inventory {
+	fn default() -> Self {
+		Self {
+			slots: 5,
+			convoy: ConvoyAccess::default(),
+		}
+	}
+}
+
+/// How gold is earned and spent.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Economy {
+	/// Gold the player starts the campaign with.
+	pub gold: u32,
+	/// Percentage of an item's value which shops pay for it.
+	pub sell_ratio: u32,
+}
+
+impl Default for Economy {
+	fn default() -> Self {
+		Self {
+			gold: 0,
+			sell_ratio: 50,
+		}
+	}
+}
+
+/// How units earn experience and gain levels.
+///
+/// Experience for a battle is `battle`, plus `level_difference` for each level the opponent is above the unit,
+/// or minus it for each level below.
+/// Defeating the opponent earns `kill` on top of this, and defeating a leader earns `boss` as well.
+/// Units which deal no damage earn only `minimum`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Experience {
+	/// Experience needed to gain a level.
+	pub threshold: u32,
+	/// Level at which units stop earning experience.
+	pub max_level: u32,
+	pub battle: u32,
+	pub level_difference: u32,
+	pub kill: u32,
+	pub boss: u32,
+	/// The least experience earned for a battle.
+	pub minimum: u32,
+	/// Experience earned for healing a unit.
+	pub heal: u32,
+}
+
+impl Default for Experience {
+	fn default() -> Self {
+		Self {
+			threshold: 100,
+			max_level: 20,
+			battle: 10,
+			level_difference: 1,
+			kill: 10,
+			boss: 40,
+			minimum: 1,
+			heal: 10,
+		}
+	}
+}
+
+/// Which units can reach the convoy.
+///
+/// Units standing next to one of these may also use it.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ConvoyAccess {
+	/// There is no convoy.
+	None,
+	/// Units placed as leaders.
+	#[default]
+	Leader,
+	/// Units of a class.
+	Class(ClassReference),
+}
+
 #[derive(Clone, Default, Debug, Deserialize, Serialize)]
 pub struct Module {
 	// Required fields
@@ -56,6 +180,21 @@ pub struct Module {
 	pub primary: bool,
 	#[serde(default)]
 	pub icon_path: Option<PathBuf>,
+	/// Terrain types, referenced by name from tilesets.
+	#[serde(default)]
+	pub terrain: HashMap<String, Terrain>,
+	#[serde(default)]
+	pub display: Display,
+	/// Chapters played in order, starting with the first.
+	/// Chapters may skip ahead or branch by naming the chapter which follows them.
+	#[serde(default)]
+	pub campaign: Vec<ChapterReference>,
+	#[serde(default)]
+	pub inventory: Inventory,
+	#[serde(default)]
+	pub economy: Economy,
+	#[serde(default)]
+	pub experience: Experience,
 
 	// Runtime info
 	#[serde(skip)]
@@ -77,7 +216,9 @@ impl PartialEq for Module {
 				)&&+
 			}
 		}
-		compare!(name, primary, icon_path)
+		compare!(
+			name, primary, icon_path, terrain, display, campaign, inventory, economy, experience
+		)
 	}
 }
 
@@ -1,7 +1,10 @@
 use crate::containers::Image;
+use crate::{Alignment, ClassReference, ItemReference, UnitReference};
 use grid::Grid;
 use serde::{Deserialize, Serialize};
 
+make_reference!(maps::Map => MapReference);
+
 /// Integer representing tile IDs.
 /// Implicitly determines tile limit;
 /// increase if more are needed.
@@ -14,6 +17,19 @@ pub struct Map {
 	pub name: String,
 	pub tiles: Grid<Tile>,
 	pub tileset: TilesetReference,
+	/// Units placed on the map when it is loaded.
+	pub units: Vec<UnitPlacement>,
+	/// Lua script run when the map is loaded, relative to the map file.
+	pub script: Option<String>,
+	pub triggers: Vec<Trigger>,
+	/// Enables fog of war, hiding tiles which no unit on a side can see.
+	pub fog: Option<Fog>,
+	/// Ways to win this map. Meeting any one of them wins.
+	pub objectives: Vec<Objective>,
+	/// Ways to lose this map, besides losing every one of the player's units.
+	pub defeat: Vec<DefeatCondition>,
+	/// Shops which the player's units can visit.
+	pub shops: Vec<Shop>,
 }
 
 impl Default for Map {
@@ -22,6 +38,186 @@ impl Default for Map {
 			name: String::new(),
 			tiles: Grid::new(10, 15),
 			tileset: TilesetReference::default(),
+			units: Vec::new(),
+			script: None,
+			triggers: Vec::new(),
+			fog: None,
+			objectives: Vec::new(),
+			defeat: vec![DefeatCondition::LeaderDefeated],
+			shops: Vec::new(),
+		}
+	}
+}
+
+/// A goal which wins a map once it is met.
+///
+/// "Leaders" are the first player's units placed with `leader` set.
+/// On maps without any, `Seize` and `Escape` accept any of the first player's units instead.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Objective {
+	/// Defeat every unit hostile to the first player.
+	Rout,
+	/// Move a leader onto a tile.
+	Seize { x: u32, y: u32 },
+	/// Defeat a specific unit.
+	Defeat { unit: String },
+	/// Last until the given number of turns have passed.
+	Survive { turns: u32 },
+	/// Last until the given number of turns have passed, without letting an enemy onto a tile.
+	Defend { x: u32, y: u32, turns: u32 },
+	/// Move every leader onto a tile, where each of the player's units escapes the map.
+	Escape { x: u32, y: u32 },
+}
+
+/// A condition which loses a map once it occurs.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum DefeatCondition {
+	/// Any of the first player's leaders is defeated.
+	LeaderDefeated,
+	/// A specific unit is defeated.
+	UnitDefeated { unit: String },
+	/// The map has not been won after the given number of turns.
+	TurnLimit { turns: u32 },
+}
+
+/// Settings for a map's fog of war.
+#[cfg_attr(feature = "sucrose", derive(Resource))]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Fog {
+	/// Distance every unit can see, before bonuses from its class, items, and terrain.
+	pub vision: u32,
+}
+
+impl Default for Fog {
+	fn default() -> Self {
+		Self { vision: 3 }
+	}
+}
+
+/// How a computer-controlled unit decides where to move and who to attack.
+#[derive(Clone, Default, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Behaviour {
+	/// Approach and attack the closest enemy.
+	#[default]
+	Charge,
+	/// Stay in place until an enemy comes within reach.
+	Hold,
+	/// Only attack enemies that can be reached from near a tile, and return to it otherwise.
+	Guard { x: u32, y: u32, range: u32 },
+	/// Go after the enemy with the lowest hp.
+	Weakest,
+	/// Charge, but retreat to a healing tile once hp falls below `threshold` percent.
+	Retreat { threshold: u32 },
+}
+
+/// Describes a unit to be spawned when a map is loaded.
+///
+/// Named units reference their `Unit` data,
+/// while generic units only need a class.
+#[cfg_attr(feature = "sucrose", derive(Resource))]
+#[derive(Clone, Default, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct UnitPlacement {
+	/// Unique identifier used by scripts to refer to this unit.
+	pub identifier: String,
+	pub unit: Option<UnitReference>,
+	/// Class of a generic unit. Ignored if `unit` is present.
+	pub class: ClassReference,
+	pub alignment: Alignment,
+	pub level: u32,
+	pub x: u32,
+	pub y: u32,
+	pub weapon: Option<ItemReference>,
+	/// Used when the unit is not controlled by a player.
+	pub behaviour: Behaviour,
+	/// Lua script attached to this unit, relative to the map file.
+	pub script: Option<String>,
+	/// Identifiers of the triggers which this unit's script listens to.
+	pub triggers: Vec<String>,
+	/// Marks a unit which must survive, and which may seize objectives.
+	///
+	/// Mirrors the Spawnpoint `Leader` flag of the LDtk map prototype.
+	pub leader: bool,
+}
+
+/// A region of the map which notifies scripts when a player's unit first enters it.
+#[cfg_attr(feature = "sucrose", derive(Resource))]
+#[derive(Clone, Default, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Trigger {
+	pub identifier: String,
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Trigger {
+	#[must_use]
+	pub fn contains(&self, x: u32, y: u32) -> bool {
+		(self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+	}
+}
+
+/// A shop, armory, or vendor on a map, where the player's units can buy and sell items.
+#[cfg_attr(feature = "sucrose", derive(Resource))]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Shop {
+	/// Shown in the unit menu, such as "Armory" or "Vendor".
+	pub name: String,
+	/// Tile which a unit must stand on to visit the shop.
+	pub x: u32,
+	pub y: u32,
+	/// Items for sale, each at its `value`.
+	pub items: Vec<ItemReference>,
+}
+
+impl Default for Shop {
+	fn default() -> Self {
+		Self {
+			name: "Shop".into(),
+			x: 0,
+			y: 0,
+			items: Vec::new(),
+		}
+	}
+}
+
+/// Gameplay effects of a type of terrain.
+///
+/// Terrain is defined by the module and referenced by name from `TileInfo`.
+#[cfg_attr(feature = "sucrose", derive(Resource))]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Terrain {
+	/// Movement points required to enter this tile.
+	pub cost: u32,
+	/// Prevents all units from entering this tile.
+	pub impassable: bool,
+	/// Bonus to the avoid of a unit standing on this tile.
+	pub avoid: i32,
+	/// Bonus to the defense of a unit standing on this tile.
+	pub defense: i32,
+	/// Percentage of max hp restored at the start of a unit's phase.
+	pub heal: i32,
+	/// Bonus to the vision of a unit standing on this tile, in fog of war.
+	pub vision: i32,
+	/// Prevents units from seeing past this tile in fog of war.
+	pub blocks_vision: bool,
+}
+
+impl Default for Terrain {
+	fn default() -> Self {
+		Self {
+			cost: 1,
+			impassable: false,
+			avoid: 0,
+			defense: 0,
+			heal: 0,
+			vision: 0,
+			blocks_vision: false,
 		}
 	}
 }
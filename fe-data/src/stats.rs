@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::ops::Add;
 
 #[cfg_attr(feature = "sucrose", derive(sucrose::Resource))]
 #[derive(Clone, Default, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -17,3 +18,21 @@ pub struct Stats {
 	pub constitution: i32,
 	pub reflexes: i32,
 }
+
+impl Add for &Stats {
+	type Output = Stats;
+
+	/// Combines two sets of stats, such as class bases and a unit's offsets.
+	fn add(self, other: &Stats) -> Stats {
+		macro_rules! sum {
+			($($ident:ident),+) => {
+				Stats {
+					$(
+						$ident: self.$ident + other.$ident,
+					)+
+				}
+			}
+		}
+		sum!(hp, power, defense, resistance, dexterity, movement, constitution, reflexes)
+	}
+}
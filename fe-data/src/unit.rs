@@ -18,6 +18,35 @@ pub struct Personality {
 
 make_reference!(personality::Personality => PersonalityReference);
 
+/// Which side a unit fights for.
+///
+/// Mirrors the `Alignment` enum of the LDtk map prototype.
+#[derive(Copy, Clone, Default, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Alignment {
+	#[default]
+	Player1,
+	Player2,
+	Ally,
+	Enemy,
+}
+
+impl Alignment {
+	/// Returns true if units of these alignments may attack each other.
+	///
+	/// Allies fight alongside the first player; every other alignment is on its own side.
+	#[must_use]
+	pub fn is_hostile(self, other: Self) -> bool {
+		use Alignment::*;
+
+		let side = |alignment| match alignment {
+			Player1 | Ally => 0,
+			Player2 => 1,
+			Enemy => 2,
+		};
+		side(self) != side(other)
+	}
+}
+
 /// Everything that makes up a (named) character.
 ///
 /// There may be other variants of this type with less information, to imply certain fields and variations.
@@ -42,4 +71,8 @@ pub struct Unit {
 	// Growths offset. Applied on top of class growths.
 	pub growths: Stats,
 	pub personality: Option<PersonalityReference>,
+	/// Shown beside the unit's lines of dialogue.
+	pub portrait: Option<Image>,
 }
+
+make_reference!(units::Unit => UnitReference);
@@ -107,6 +107,7 @@ impl Editor for ItemEditor {
 					}
 					show_type!("None", || ItemType::None);
 					show_type!("Weapon", || ItemType::Weapon(WeaponItem::default()));
+					show_type!("Heal", || ItemType::Heal(HealItem::default()));
 				});
 				ui.end_row();
 
@@ -0,0 +1,1469 @@
+use crate::ai::{Actor, Ai};
+use crate::battle::{forecast, resolve_battles, Attack};
+use crate::campaign::Campaign;
+use crate::controls;
+use crate::cursor::{Cursor, UiAction};
+use crate::dialogue;
+use crate::experience::{self, GainExperience};
+use crate::fog::FogOfWar;
+use crate::map::{Board, MapEntity, MoveUnit, Position, Walking, TILE_SIZE};
+use crate::module::Content;
+use crate::objectives::PLAYER;
+use crate::ppcanvas::CanvasCamera;
+use crate::script::{
+	Command, PendingTriggers, ScriptEventFinished, ScriptRequest, ScriptRuntime, Variable,
+};
+use crate::shop;
+use crate::skills::{canter, displace, may_canter, Skill};
+use crate::title;
+use crate::turn::{Acted, Turn};
+use crate::unit::Unit;
+use bevy::ecs::query::Has;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use fe_data::{ConvoyAccess, Item, ItemType, Shop};
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Space between the menu and the edges of the canvas.
+const MARGIN: f32 = 4.0;
+const PADDING: f32 = 4.0;
+const FONT_SIZE: f32 = 8.0;
+const MENU_WIDTH: f32 = 96.0;
+/// Longest list shown at once, which scrolls to follow the highlighted row.
+const MENU_ROWS: usize = 8;
+const MENU_COLOR: Color = Color::rgba(0.05, 0.05, 0.15, 0.9);
+/// Tint of the tiles which the selected unit can move to.
+const RANGE_COLOR: Color = Color::rgba(0.3, 0.5, 1.0, 0.5);
+/// Tint of the tiles which an action can be aimed at.
+const TARGET_COLOR: Color = Color::rgba(1.0, 0.3, 0.3, 0.5);
+/// Size of the flag shown on units which are carrying another.
+const CARRY_MARKER_SIZE: f32 = 5.0;
+const CARRY_MARKER_COLOR: Color = Color::rgb(1.0, 0.85, 0.2);
+
+/// Something a unit can do once it has moved.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UnitAction {
+	/// Fights an adjacent enemy.
+	Attack,
+	/// Opens the unit's inventory, where another weapon can be equipped or an item used.
+	Item,
+	/// Exchanges items with an adjacent ally.
+	Trade,
+	/// Stores items in the convoy, or takes them out.
+	Convoy,
+	/// Buys and sells items at the shop the unit is standing on, identified by its name.
+	Shop(String),
+	/// Picks up an adjacent ally, who is carried off the map until they are dropped.
+	Rescue,
+	/// Puts the carried unit down on an adjacent tile.
+	Drop,
+	/// Takes the unit carried by an adjacent ally.
+	Take,
+	/// Hands the carried unit to an adjacent ally.
+	Give,
+	/// Moves the unit or its target using one of its class's skills.
+	Skill(Skill),
+	/// Ends the unit's turn.
+	Wait,
+	/// An action added by a script, identified by its name.
+	Custom(String),
+}
+
+impl UnitAction {
+	#[must_use]
+	pub fn name(&self) -> &str {
+		match self {
+			Self::Attack => "Attack",
+			Self::Item => "Item",
+			Self::Trade => "Trade",
+			Self::Convoy => "Convoy",
+			Self::Shop(name) => name,
+			Self::Rescue => "Rescue",
+			Self::Drop => "Drop",
+			Self::Take => "Take",
+			Self::Give => "Give",
+			Self::Skill(skill) => skill.name(),
+			Self::Wait => "Wait",
+			Self::Custom(name) => name,
+		}
+	}
+}
+
+/// What an action is aimed at.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Target {
+	Unit(Entity),
+	Tile(Position),
+}
+
+/// An action offered by the unit menu, and what it may be aimed at.
+///
+/// Actions without any targets, such as `Wait`, are taken as soon as they are chosen.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Choice {
+	pub action: UnitAction,
+	pub targets: Vec<Target>,
+}
+
+/// An action added to the unit menu by a script.
+///
+/// Choosing it sends `signal.action` to every script.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CustomAction {
+	pub name: String,
+	/// Only this unit is offered the action, if set.
+	pub unit: Option<String>,
+	/// The action is only offered next to this unit, if set.
+	pub target: Option<String>,
+	/// The action is only offered on this tile, if set.
+	pub tile: Option<Position>,
+	/// Whether taking the action ends the unit's turn.
+	pub ends_turn: bool,
+}
+
+/// What a unit can see around it when choosing an action.
+pub struct Surroundings<'a> {
+	/// Every unit on the map, which may include the acting unit itself.
+	pub units: &'a [Actor<'a>],
+	pub board: &'a Board,
+	/// Units which are being carried, and so are not on the map.
+	pub carried: &'a [(Entity, &'a Unit)],
+	pub custom: &'a [CustomAction],
+	/// Returns true for units who can reach the convoy, which lets those next to them use it too.
+	pub keeps_convoy: &'a dyn Fn(&Unit) -> bool,
+	/// Shops open on the map.
+	pub shops: &'a [Shop],
+}
+
+impl Surroundings<'_> {
+	/// Returns the unit which `carrier` is carrying, if any.
+	#[must_use]
+	pub fn cargo(&self, carrier: &Unit) -> Option<&Unit> {
+		let cargo = carrier.carrying?;
+		self.carried
+			.iter()
+			.find(|(entity, _)| *entity == cargo)
+			.map(|(_, unit)| *unit)
+	}
+}
+
+/// Returns every tile which a unit can move to with `movement` points, including the one it stands on,
+/// along with the movement needed to reach each one.
+///
+/// Like the AI, units may walk through their allies, but not through enemies, even those hidden by fog.
+#[must_use]
+pub fn destinations(
+	actor: &Actor,
+	movement: u32,
+	units: &[Actor],
+	board: &Board,
+) -> HashMap<Position, u32> {
+	board
+		.reachable(
+			actor.position,
+			movement,
+			&actor.unit.class,
+			passable(actor, units),
+		)
+		.into_iter()
+		.filter(|(position, _)| {
+			!units
+				.iter()
+				.any(|other| other.entity != actor.entity && other.position == *position)
+		})
+		.collect()
+}
+
+/// Returns the tiles a unit walks through on its cheapest way to `destination`,
+/// which is one of its `destinations` with the same `movement`.
+#[must_use]
+pub fn path(
+	actor: &Actor,
+	destination: Position,
+	movement: u32,
+	units: &[Actor],
+	board: &Board,
+) -> Vec<Position> {
+	board
+		.path(
+			actor.position,
+			destination,
+			movement,
+			&actor.unit.class,
+			passable(actor, units),
+		)
+		.unwrap_or_default()
+}
+
+/// Returns whether a unit may walk through a tile, which it can't if an enemy stands there.
+fn passable<'a>(actor: &'a Actor, units: &'a [Actor]) -> impl Fn(Position) -> bool + 'a {
+	move |position| {
+		actor.unit.class.pass
+			|| !units.iter().any(|other| {
+				other.entity != actor.entity
+					&& other.position == position
+					&& actor.unit.alignment.is_hostile(other.unit.alignment)
+			})
+	}
+}
+
+/// Lists the actions a unit can take from where it stands, in the order they are shown.
+#[must_use]
+pub fn available(actor: &Actor, surroundings: &Surroundings) -> Vec<Choice> {
+	let adjacent = |filter: &dyn Fn(&Actor) -> bool| -> Vec<Target> {
+		surroundings
+			.units
+			.iter()
+			.filter(|other| {
+				other.entity != actor.entity
+					&& other.position.distance(actor.position) == 1
+					&& filter(other)
+			})
+			.map(|other| Target::Unit(other.entity))
+			.collect()
+	};
+	let has_items = |unit: &Unit| unit.weapon.is_some() || !unit.items.is_empty();
+	let ally = |other: &Actor| other.unit.alignment == actor.unit.alignment;
+
+	let mut choices = Vec::new();
+	let mut offer = |action, targets: Vec<Target>, needs_target: bool| {
+		if !needs_target || !targets.is_empty() {
+			choices.push(Choice { action, targets });
+		}
+	};
+
+	let enemies = if actor.unit.weapon().is_some() {
+		adjacent(&|other| other.visible && actor.unit.alignment.is_hostile(other.unit.alignment))
+	} else {
+		Vec::new()
+	};
+	offer(UnitAction::Attack, enemies, true);
+	if has_items(actor.unit) {
+		offer(UnitAction::Item, Vec::new(), false);
+	}
+	offer(
+		UnitAction::Trade,
+		adjacent(&|other| ally(other) && (has_items(actor.unit) || has_items(other.unit))),
+		true,
+	);
+	if (surroundings.keeps_convoy)(actor.unit)
+		|| !adjacent(&|other| ally(other) && (surroundings.keeps_convoy)(other.unit)).is_empty()
+	{
+		offer(UnitAction::Convoy, Vec::new(), false);
+	}
+	for shop in surroundings.shops {
+		if shop::is_at(shop, actor.position) {
+			offer(UnitAction::Shop(shop.name.clone()), Vec::new(), false);
+		}
+	}
+	if let Some(carried) = surroundings.cargo(actor.unit) {
+		let tiles = actor
+			.position
+			.neighbours()
+			.into_iter()
+			.filter(|position| {
+				surroundings.board.cost(*position, &carried.class).is_some()
+					&& !surroundings
+						.units
+						.iter()
+						.any(|other| other.position == *position)
+			})
+			.map(Target::Tile)
+			.collect();
+		offer(UnitAction::Drop, tiles, true);
+		offer(
+			UnitAction::Give,
+			adjacent(&|other| {
+				ally(other) && other.unit.carrying.is_none() && other.unit.can_carry(carried)
+			}),
+			true,
+		);
+	} else {
+		offer(
+			UnitAction::Rescue,
+			adjacent(&|other| {
+				ally(other) && other.unit.carrying.is_none() && actor.unit.can_carry(other.unit)
+			}),
+			true,
+		);
+		offer(
+			UnitAction::Take,
+			adjacent(&|other| {
+				ally(other)
+					&& surroundings
+						.cargo(other.unit)
+						.is_some_and(|carried| actor.unit.can_carry(carried))
+			}),
+			true,
+		);
+	}
+
+	for skill in Skill::of(&actor.unit.class) {
+		let targets = surroundings
+			.units
+			.iter()
+			.filter(|other| {
+				other.entity != actor.entity
+					&& other.visible
+					&& displace(skill, actor, other, surroundings.units, surroundings.board)
+						.is_some()
+			})
+			.map(|other| Target::Unit(other.entity))
+			.collect();
+		offer(UnitAction::Skill(skill), targets, true);
+	}
+
+	for custom in surroundings.custom {
+		if custom
+			.unit
+			.as_ref()
+			.is_some_and(|unit| *unit != actor.unit.identifier)
+			|| custom.tile.is_some_and(|tile| tile != actor.position)
+		{
+			continue;
+		}
+		let action = UnitAction::Custom(custom.name.clone());
+		match &custom.target {
+			Some(target) => offer(
+				action,
+				adjacent(&|other| other.unit.identifier == *target),
+				true,
+			),
+			None => offer(action, Vec::new(), false),
+		}
+	}
+
+	offer(UnitAction::Wait, Vec::new(), false);
+	choices
+}
+
+/// Returns a unit's equipped weapon, if any, followed by the rest of its items.
+#[must_use]
+pub fn inventory(unit: &Unit) -> Vec<&Item> {
+	unit.weapon.iter().chain(&unit.items).collect()
+}
+
+/// Removes an item from a unit by its index within `inventory`.
+pub fn take(unit: &mut Unit, index: usize) -> Option<Item> {
+	match (index, unit.weapon.is_some()) {
+		(0, true) => unit.weapon.take(),
+		(index, true) => (index <= unit.items.len()).then(|| unit.items.remove(index - 1)),
+		(index, false) => (index < unit.items.len()).then(|| unit.items.remove(index)),
+	}
+}
+
+/// Equips a weapon by its index within `inventory`, putting the previous weapon in its place.
+///
+/// Returns false if the item is not a weapon.
+pub fn equip(unit: &mut Unit, index: usize) -> bool {
+	let offset = usize::from(unit.weapon.is_some());
+	let Some(item) = index.checked_sub(offset).and_then(|i| unit.items.get(i)) else {
+		return false;
+	};
+	if !matches!(item.ty, ItemType::Weapon(_)) {
+		return false;
+	}
+	let item = unit.items.remove(index - offset);
+	if let Some(previous) = unit.weapon.replace(item) {
+		unit.items.insert(index - offset, previous);
+	}
+	true
+}
+
+/// Uses a healing item by its index within `inventory`, discarding it once it has no uses left.
+///
+/// Returns false if the item cannot be used, including when the unit is already at full health.
+pub fn use_item(unit: &mut Unit, index: usize) -> bool {
+	let offset = usize::from(unit.weapon.is_some());
+	let Some(slot) = index.checked_sub(offset) else {
+		return false;
+	};
+	let Some(Item {
+		ty: ItemType::Heal(heal),
+		..
+	}) = unit.items.get_mut(slot)
+	else {
+		return false;
+	};
+	if unit.hp >= unit.stats.hp {
+		return false;
+	}
+	unit.hp = (unit.hp + heal.amount as i32).min(unit.stats.hp);
+	match heal.uses {
+		0 => {}
+		1 => {
+			unit.items.remove(slot);
+		}
+		_ => heal.uses -= 1,
+	}
+	true
+}
+
+/// What the player is currently doing with the selected unit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Stage {
+	/// Choosing where the unit moves, from the tiles it can reach and what each costs.
+	Moving(HashMap<Position, u32>),
+	/// Walking to the chosen tile.
+	/// Once a unit arrives after cantering, its turn is over.
+	Walking { destination: Position, canter: bool },
+	/// Choosing an action.
+	Menu(Vec<Choice>),
+	/// Choosing what an action is aimed at.
+	Targeting(Choice),
+	/// Looking through the unit's items.
+	Items,
+	/// Exchanging items with another unit.
+	/// `column` is 0 for the selected unit's items, and 1 for its partner's.
+	Trading { partner: Entity, column: usize },
+	/// Moving items between the unit and the convoy.
+	/// `column` is 0 for the unit's items, and 1 for the convoy's.
+	Convoy { column: usize },
+	/// Buying and selling items at a shop.
+	/// `column` is 0 for the shop's stock, and 1 for the unit's items.
+	/// Once the unit has `traded`, leaving the shop ends its action.
+	Shop {
+		shop: Shop,
+		column: usize,
+		traded: bool,
+	},
+	/// Waiting a frame for the unit's action to take effect, before it moves again.
+	/// `attacked` is whether the action was an attack.
+	Acting { attacked: bool },
+	/// Choosing where the unit moves after acting, with whatever movement it has left.
+	Canter(HashMap<Position, u32>),
+}
+
+/// A unit chosen by the player.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Selected {
+	pub unit: Entity,
+	/// Where the unit stood before it moved, which it returns to if the move is cancelled.
+	pub origin: Position,
+	pub stage: Stage,
+	/// Index of the highlighted row or target.
+	pub index: usize,
+	/// Movement used to reach the unit's current tile.
+	pub spent: u32,
+}
+
+/// The unit which the player is giving orders to, if any.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Selection(pub Option<Selected>);
+
+impl Selection {
+	/// Returns true if a menu or target is being chosen, which the cursor should not move during.
+	#[must_use]
+	pub fn is_choosing(&self) -> bool {
+		self.0.as_ref().is_some_and(|selected| {
+			!matches!(
+				selected.stage,
+				Stage::Moving(_) | Stage::Walking { .. } | Stage::Canter(_)
+			)
+		})
+	}
+}
+
+/// Run condition which prevents the cursor from moving while the unit menu is open.
+#[must_use]
+pub fn cursor_is_free(selection: Res<Selection>) -> bool {
+	!selection.is_choosing()
+}
+
+/// The components of each unit which the unit menu reads.
+type MenuUnits<'w, 's> = Query<
+	'w,
+	's,
+	(
+		Entity,
+		&'static Unit,
+		Option<&'static Position>,
+		Has<Acted>,
+		Has<Ai>,
+		Has<Walking>,
+	),
+>;
+
+/// Selects the player's units, moves them, and carries out the actions chosen from the unit menu.
+#[allow(clippy::too_many_arguments)]
+fn control(
+	mut commands: Commands,
+	mut runtime: NonSendMut<ScriptRuntime>,
+	actions: Query<&ActionState<UiAction>>,
+	mut selection: ResMut<Selection>,
+	content: Res<Content>,
+	mut campaign: ResMut<Campaign>,
+	turn: Res<Turn>,
+	board: Option<Res<Board>>,
+	fog: Option<Res<FogOfWar>>,
+	units: MenuUnits,
+	mut cursors: Query<&mut Transform, With<Cursor>>,
+	mut moves: EventWriter<MoveUnit>,
+	mut attacks: EventWriter<Attack>,
+	mut experience: EventWriter<GainExperience>,
+) {
+	let Some(board) = board else {
+		selection.0 = None;
+		return;
+	};
+	if board.is_added() || turn.phase != PLAYER {
+		selection.0 = None;
+		return;
+	}
+	let pressed = |action| actions.iter().any(|state| state.just_pressed(action));
+	let slots = content.module.inventory.slots;
+	let shops = shop::shops(&content, &campaign, &board.map);
+	let keeps_convoy = |unit: &Unit| match &content.module.inventory.convoy {
+		ConvoyAccess::None => false,
+		ConvoyAccess::Leader => unit.leader,
+		ConvoyAccess::Class(class) => unit.class_identifier.as_ref() == Some(&class.identifier),
+	};
+
+	let actors: Vec<Actor> = units
+		.iter()
+		.filter_map(|(entity, unit, position, ..)| {
+			let position = *position?;
+			Some(Actor {
+				entity,
+				unit,
+				position,
+				visible: fog
+					.as_ref()
+					.is_none_or(|fog| fog.is_visible(PLAYER, position)),
+			})
+		})
+		.collect();
+	let carried: Vec<(Entity, &Unit)> = units
+		.iter()
+		.filter(|(_, _, position, ..)| position.is_none())
+		.map(|(entity, unit, ..)| (entity, unit))
+		.collect();
+	let menu = |entity: Entity| {
+		let actor = actors.iter().find(|actor| actor.entity == entity)?;
+		let surroundings = Surroundings {
+			units: &actors,
+			board: &board,
+			carried: &carried,
+			custom: &board.actions,
+			keeps_convoy: &keeps_convoy,
+			shops: &shops,
+		};
+		Some(Stage::Menu(available(actor, &surroundings)))
+	};
+	let Ok(mut cursor) = cursors.get_single_mut() else {
+		return;
+	};
+	let mut point_at = |position: Position| {
+		let world = board.to_world(position);
+		cursor.translation.x = world.x;
+		cursor.translation.y = world.y;
+	};
+
+	let Some(selected) = &mut selection.0 else {
+		if !pressed(UiAction::Select) {
+			return;
+		}
+		let tile = board.tile_at(cursor.translation.truncate());
+		let Some(actor) = actors.iter().find(|actor| actor.position == tile) else {
+			return;
+		};
+		let controllable = units
+			.get(actor.entity)
+			.is_ok_and(|(_, unit, _, acted, ai, ..)| unit.alignment == PLAYER && !acted && !ai);
+		if controllable {
+			commands
+				.entity(actor.entity)
+				.insert(PendingTriggers::default());
+			selection.0 = Some(Selected {
+				unit: actor.entity,
+				origin: actor.position,
+				stage: Stage::Moving(destinations(actor, movement(actor.unit), &actors, &board)),
+				index: 0,
+				spent: 0,
+			});
+		}
+		return;
+	};
+	let Ok((_, unit, position, ..)) = units.get(selected.unit) else {
+		selection.0 = None;
+		return;
+	};
+	let position = position.copied().unwrap_or(selected.origin);
+
+	match selected.stage.clone() {
+		Stage::Moving(destinations) => {
+			if pressed(UiAction::Back) {
+				selection.0 = None;
+			} else if pressed(UiAction::Select) {
+				let tile = board.tile_at(cursor.translation.truncate());
+				if tile == position {
+					if let Some(stage) = menu(selected.unit) {
+						selected.stage = stage;
+						selected.index = 0;
+					}
+				} else if let Some(cost) = destinations.get(&tile) {
+					let path = actors
+						.iter()
+						.find(|actor| actor.entity == selected.unit)
+						.map(|actor| path(actor, tile, *cost, &actors, &board))
+						.unwrap_or_default();
+					moves.send(MoveUnit {
+						entity: selected.unit,
+						destination: tile,
+						path,
+					});
+					selected.spent = *cost;
+					selected.stage = Stage::Walking {
+						destination: tile,
+						canter: false,
+					};
+				}
+			}
+		}
+		Stage::Walking {
+			destination,
+			canter,
+		} => {
+			let walking = units
+				.get(selected.unit)
+				.is_ok_and(|(.., walking, _)| walking);
+			if position != destination || walking {
+				return;
+			}
+			if canter {
+				commands.entity(selected.unit).insert(Acted);
+				selection.0 = None;
+			} else if let Some(stage) = menu(selected.unit) {
+				selected.stage = stage;
+				selected.index = 0;
+			}
+		}
+		Stage::Acting { attacked } => {
+			let destinations = actors
+				.iter()
+				.find(|actor| actor.entity == selected.unit)
+				.map(|actor| canter(actor, selected.spent, attacked, &actors, &board))
+				.unwrap_or_default();
+			if destinations.len() > 1 {
+				selected.stage = Stage::Canter(destinations);
+			} else {
+				commands.entity(selected.unit).insert(Acted);
+				selection.0 = None;
+			}
+		}
+		Stage::Canter(destinations) => {
+			let tile = board.tile_at(cursor.translation.truncate());
+			if pressed(UiAction::Back) || (pressed(UiAction::Select) && tile == position) {
+				commands.entity(selected.unit).insert(Acted);
+				selection.0 = None;
+			} else if let Some(cost) = destinations
+				.get(&tile)
+				.filter(|_| pressed(UiAction::Select))
+			{
+				let path = actors
+					.iter()
+					.find(|actor| actor.entity == selected.unit)
+					.map(|actor| path(actor, tile, *cost, &actors, &board))
+					.unwrap_or_default();
+				moves.send(MoveUnit {
+					entity: selected.unit,
+					destination: tile,
+					path,
+				});
+				selected.stage = Stage::Walking {
+					destination: tile,
+					canter: true,
+				};
+			}
+		}
+		Stage::Menu(choices) => {
+			if pressed(UiAction::Back) {
+				// Cancels the move, letting the player choose another tile,
+				// along with any triggers the unit walked into.
+				commands
+					.entity(selected.unit)
+					.insert((selected.origin, PendingTriggers::default()));
+				point_at(selected.origin);
+				let actor = actors
+					.iter()
+					.find(|actor| actor.entity == selected.unit)
+					.map(|actor| Actor {
+						position: selected.origin,
+						..*actor
+					});
+				selected.spent = 0;
+				selected.stage = Stage::Moving(actor.map_or_else(HashMap::new, |actor| {
+					destinations(&actor, movement(actor.unit), &actors, &board)
+				}));
+				return;
+			}
+			selected.index = navigate(selected.index, choices.len(), &pressed);
+			if !pressed(UiAction::Select) {
+				return;
+			}
+			let Some(choice) = choices.get(selected.index).cloned() else {
+				return;
+			};
+			match (&choice.action, choice.targets.first()) {
+				(UnitAction::Item, _) => {
+					selected.stage = Stage::Items;
+					selected.index = 0;
+				}
+				(UnitAction::Convoy, _) => {
+					selected.origin = position;
+					selected.stage = Stage::Convoy { column: 0 };
+					selected.index = 0;
+				}
+				(UnitAction::Shop(name), _) => {
+					let shop = shops
+						.iter()
+						.find(|shop| shop.name == *name && shop::is_at(shop, position));
+					if let Some(shop) = shop {
+						selected.stage = Stage::Shop {
+							shop: shop.clone(),
+							column: 0,
+							traded: false,
+						};
+						selected.index = 0;
+					}
+				}
+				(_, Some(target)) => {
+					if let Target::Unit(entity) = target {
+						if let Some(actor) = actors.iter().find(|actor| actor.entity == *entity) {
+							point_at(actor.position);
+						}
+					} else if let Target::Tile(tile) = target {
+						point_at(*tile);
+					}
+					selected.stage = Stage::Targeting(choice);
+					selected.index = 0;
+				}
+				(action, None) => {
+					let ends_turn = match action {
+						UnitAction::Custom(name) => {
+							dispatch_action(&mut runtime, name, unit, None);
+							board
+								.actions
+								.iter()
+								.find(|custom| custom.name == *name)
+								.is_none_or(|custom| custom.ends_turn)
+						}
+						_ => true,
+					};
+					if ends_turn {
+						if *action == UnitAction::Wait {
+							commands.entity(selected.unit).insert(Acted);
+							selection.0 = None;
+						} else {
+							finish(&mut commands, &mut selection, unit, false);
+						}
+					} else if let Some(stage) = menu(selected.unit) {
+						// The move can no longer be taken back.
+						selected.origin = position;
+						selected.stage = stage;
+						selected.index = 0;
+					}
+				}
+			}
+		}
+		Stage::Targeting(choice) => {
+			let targets = choice.targets.len();
+			let previous = selected.index;
+			selected.index = navigate(selected.index, targets, &pressed);
+			let target = choice.targets.get(selected.index).copied();
+			let target_position = |target: Target| match target {
+				Target::Unit(entity) => actors
+					.iter()
+					.find(|actor| actor.entity == entity)
+					.map(|actor| actor.position),
+				Target::Tile(tile) => Some(tile),
+			};
+			if selected.index != previous {
+				if let Some(tile) = target.and_then(target_position) {
+					point_at(tile);
+				}
+			}
+			if pressed(UiAction::Back) {
+				point_at(position);
+				if let Some(stage) = menu(selected.unit) {
+					selected.stage = stage;
+					selected.index = 0;
+				}
+				return;
+			}
+			if !pressed(UiAction::Select) {
+				return;
+			}
+			let Some(target) = target else {
+				return;
+			};
+			point_at(position);
+			let mut attacked = false;
+			match (&choice.action, target) {
+				(UnitAction::Attack, Target::Unit(defender)) => {
+					attacks.send(Attack {
+						attacker: selected.unit,
+						defender,
+					});
+					attacked = true;
+				}
+				(UnitAction::Trade, Target::Unit(partner)) => {
+					selected.origin = position;
+					selected.stage = Stage::Trading { partner, column: 0 };
+					selected.index = 0;
+					return;
+				}
+				(UnitAction::Rescue, Target::Unit(rescued)) => {
+					let mut carrier = unit.clone();
+					carrier.carrying = Some(rescued);
+					commands.entity(selected.unit).insert(carrier);
+					commands
+						.entity(rescued)
+						.remove::<Position>()
+						.insert(Visibility::Hidden);
+				}
+				(UnitAction::Drop, Target::Tile(tile)) => {
+					if let Some(dropped) = unit.carrying {
+						let mut carrier = unit.clone();
+						carrier.carrying = None;
+						commands.entity(selected.unit).insert(carrier);
+						commands
+							.entity(dropped)
+							.insert((tile, Visibility::Inherited, Acted));
+					}
+				}
+				(UnitAction::Take | UnitAction::Give, Target::Unit(entity)) => {
+					if let Ok((_, other, ..)) = units.get(entity) {
+						let (mut unit, mut other) = (unit.clone(), other.clone());
+						std::mem::swap(&mut unit.carrying, &mut other.carrying);
+						commands.entity(selected.unit).insert(unit);
+						commands.entity(entity).insert(other);
+					}
+				}
+				(UnitAction::Skill(skill), Target::Unit(entity)) => {
+					let find = |entity| actors.iter().find(|actor| actor.entity == entity);
+					let displacement =
+						find(selected.unit)
+							.zip(find(entity))
+							.and_then(|(actor, target)| {
+								displace(*skill, actor, target, &actors, &board)
+							});
+					if let Some(displacement) = displacement {
+						commands.entity(selected.unit).insert(displacement.actor);
+						commands.entity(entity).insert(displacement.target);
+					}
+				}
+				(UnitAction::Custom(name), Target::Unit(entity)) => {
+					let target = units.get(entity).ok().map(|(_, unit, ..)| unit);
+					dispatch_action(&mut runtime, name, unit, target);
+					let ends_turn = board
+						.actions
+						.iter()
+						.find(|custom| custom.name == *name)
+						.is_none_or(|custom| custom.ends_turn);
+					if !ends_turn {
+						selected.origin = position;
+						if let Some(stage) = menu(selected.unit) {
+							selected.stage = stage;
+							selected.index = 0;
+						}
+						return;
+					}
+				}
+				_ => {}
+			}
+			finish(&mut commands, &mut selection, unit, attacked);
+		}
+		Stage::Items => {
+			if pressed(UiAction::Back) {
+				if let Some(stage) = menu(selected.unit) {
+					selected.stage = stage;
+					selected.index = 0;
+				}
+				return;
+			}
+			selected.index = navigate(selected.index, inventory(unit).len(), &pressed);
+			if pressed(UiAction::Select) {
+				let mut unit = unit.clone();
+				if equip(&mut unit, selected.index) {
+					selected.index = 0;
+					commands.entity(selected.unit).insert(unit);
+				} else if use_item(&mut unit, selected.index) {
+					experience.send(GainExperience {
+						entity: selected.unit,
+						amount: content.module.experience.heal,
+					});
+					commands.entity(selected.unit).insert(unit.clone());
+					finish(&mut commands, &mut selection, &unit, false);
+				}
+			}
+		}
+		Stage::Trading { partner, column } => {
+			let other = units.get(partner).ok().map(|(_, unit, ..)| unit);
+			if pressed(UiAction::Back) || other.is_none() {
+				if let Some(stage) = menu(selected.unit) {
+					selected.stage = stage;
+					selected.index = 0;
+				}
+				return;
+			}
+			if pressed(UiAction::Left) || pressed(UiAction::Right) {
+				selected.stage = Stage::Trading {
+					partner,
+					column: 1 - column,
+				};
+				selected.index = 0;
+				return;
+			}
+			let Some(other) = other else {
+				return;
+			};
+			let (mut from, mut to) = (unit.clone(), other.clone());
+			if column == 1 {
+				std::mem::swap(&mut from, &mut to);
+			}
+			selected.index = navigate(selected.index, inventory(&from).len(), &pressed);
+			if pressed(UiAction::Select) && to.has_room(slots) {
+				if let Some(item) = take(&mut from, selected.index) {
+					to.items.push(item);
+					selected.index = selected.index.min(inventory(&from).len().saturating_sub(1));
+					if column == 1 {
+						std::mem::swap(&mut from, &mut to);
+					}
+					commands.entity(selected.unit).insert(from);
+					commands.entity(partner).insert(to);
+				}
+			}
+		}
+		Stage::Convoy { column } => {
+			if pressed(UiAction::Back) {
+				if let Some(stage) = menu(selected.unit) {
+					selected.stage = stage;
+					selected.index = 0;
+				}
+				return;
+			}
+			if pressed(UiAction::Left) || pressed(UiAction::Right) {
+				selected.stage = Stage::Convoy { column: 1 - column };
+				selected.index = 0;
+				return;
+			}
+			let mut unit = unit.clone();
+			let length = |unit: &Unit, convoy: &[Item]| {
+				if column == 0 {
+					inventory(unit).len()
+				} else {
+					convoy.len()
+				}
+			};
+			selected.index = navigate(selected.index, length(&unit, &campaign.convoy), &pressed);
+			if !pressed(UiAction::Select) {
+				return;
+			}
+			if column == 0 {
+				let Some(item) = take(&mut unit, selected.index) else {
+					return;
+				};
+				campaign.convoy.push(item);
+			} else if unit.has_room(slots) && selected.index < campaign.convoy.len() {
+				unit.items.push(campaign.convoy.remove(selected.index));
+			} else {
+				return;
+			}
+			selected.index = selected
+				.index
+				.min(length(&unit, &campaign.convoy).saturating_sub(1));
+			commands.entity(selected.unit).insert(unit);
+		}
+		Stage::Shop {
+			shop,
+			column,
+			traded,
+		} => {
+			if pressed(UiAction::Back) {
+				if traded {
+					finish(&mut commands, &mut selection, unit, false);
+				} else if let Some(stage) = menu(selected.unit) {
+					selected.stage = stage;
+					selected.index = 0;
+				}
+				return;
+			}
+			if pressed(UiAction::Left) || pressed(UiAction::Right) {
+				selected.stage = Stage::Shop {
+					shop,
+					column: 1 - column,
+					traded,
+				};
+				selected.index = 0;
+				return;
+			}
+			let stock = shop::stock(&shop, &content);
+			let mut unit = unit.clone();
+			let length = |unit: &Unit| {
+				if column == 0 {
+					stock.len()
+				} else {
+					inventory(unit).len()
+				}
+			};
+			selected.index = navigate(selected.index, length(&unit), &pressed);
+			if !pressed(UiAction::Select) {
+				return;
+			}
+			if column == 0 {
+				let Some(item) = stock.get(selected.index) else {
+					return;
+				};
+				let Some(price) = shop::price(item).filter(|price| *price <= campaign.gold) else {
+					return;
+				};
+				// Purchases which the unit has no room for are sent to the convoy.
+				if unit.has_room(slots) {
+					unit.items.push((*item).clone());
+				} else if content.module.inventory.convoy != ConvoyAccess::None {
+					campaign.convoy.push((*item).clone());
+				} else {
+					return;
+				}
+				campaign.gold -= price;
+			} else {
+				let price = inventory(&unit)
+					.get(selected.index)
+					.and_then(|item| shop::sell_price(item, &content.module.economy));
+				let Some(price) = price else {
+					return;
+				};
+				take(&mut unit, selected.index);
+				campaign.gold = campaign.gold.saturating_add(price);
+				selected.index = selected.index.min(length(&unit).saturating_sub(1));
+			}
+			commands.entity(selected.unit).insert(unit);
+			// The move can no longer be taken back.
+			selected.origin = position;
+			selected.stage = Stage::Shop {
+				shop,
+				column,
+				traded: true,
+			};
+		}
+	}
+}
+
+/// Ends the selected unit's action, letting it move again first if its class allows.
+fn finish(commands: &mut Commands, selection: &mut Selection, unit: &Unit, attacked: bool) {
+	let Some(selected) = &mut selection.0 else {
+		return;
+	};
+	if may_canter(&unit.class, attacked) {
+		// Where the unit can go depends on where everyone stands once the action is over.
+		selected.stage = Stage::Acting { attacked };
+	} else {
+		commands.entity(selected.unit).insert(Acted);
+		selection.0 = None;
+	}
+}
+
+/// Movement points a unit has each turn.
+fn movement(unit: &Unit) -> u32 {
+	unit.stats.movement.max(0) as u32
+}
+
+/// Moves a highlight up or down a list of `length` rows, wrapping around at either end.
+fn navigate(index: usize, length: usize, pressed: &dyn Fn(UiAction) -> bool) -> usize {
+	if length == 0 {
+		return 0;
+	}
+	if pressed(UiAction::Up) || pressed(UiAction::Left) {
+		(index + length - 1) % length
+	} else if pressed(UiAction::Down) || pressed(UiAction::Right) {
+		(index + 1) % length
+	} else {
+		index.min(length - 1)
+	}
+}
+
+/// Sends `signal.action` to every script.
+fn dispatch_action(runtime: &mut ScriptRuntime, name: &str, unit: &Unit, target: Option<&Unit>) {
+	let mut arguments = vec![
+		Variable::String(name.to_string()),
+		Variable::String(unit.identifier.clone()),
+	];
+	if let Some(target) = target {
+		arguments.push(Variable::String(target.identifier.clone()));
+	}
+	runtime.dispatch("action", None, arguments);
+}
+
+/// Adds and removes the custom actions requested by scripts.
+fn script_actions(
+	mut requests: EventReader<ScriptRequest>,
+	mut board: Option<ResMut<Board>>,
+	mut finished: EventWriter<ScriptEventFinished>,
+) {
+	for request in requests.iter() {
+		let actions = board.as_mut().map(|board| &mut board.actions);
+		match (&request.command, actions) {
+			(Command::AddAction(action), Some(actions)) => {
+				actions.retain(|other| other.name != action.name);
+				actions.push(action.clone());
+			}
+			(Command::RemoveAction { name }, Some(actions)) => {
+				actions.retain(|other| other.name != *name);
+			}
+			(Command::AddAction(_) | Command::RemoveAction { .. }, None) => {
+				warn!("Script tried to change the unit menu without a map");
+			}
+			_ => continue,
+		}
+		finished.send(ScriptEventFinished(request.handle));
+	}
+}
+
+/// Marks a tile which the selected unit can move to or aim at.
+#[derive(Component)]
+struct Highlight;
+
+/// Tints the tiles which the selected unit can move to, or which its action can be aimed at.
+fn draw_range(
+	mut commands: Commands,
+	selection: Res<Selection>,
+	board: Option<Res<Board>>,
+	highlights: Query<Entity, With<Highlight>>,
+	positions: Query<&Position>,
+) {
+	if !selection.is_changed() {
+		return;
+	}
+	for entity in &highlights {
+		commands.entity(entity).despawn_recursive();
+	}
+	let (Some(selected), Some(board)) = (&selection.0, board) else {
+		return;
+	};
+	let (tiles, color): (Vec<Position>, Color) = match &selected.stage {
+		Stage::Moving(destinations) | Stage::Canter(destinations) => {
+			(destinations.keys().copied().collect(), RANGE_COLOR)
+		}
+		Stage::Targeting(choice) => (
+			choice
+				.targets
+				.iter()
+				.filter_map(|target| match target {
+					Target::Unit(entity) => positions.get(*entity).ok().copied(),
+					Target::Tile(tile) => Some(*tile),
+				})
+				.collect(),
+			TARGET_COLOR,
+		),
+		_ => return,
+	};
+	for tile in tiles {
+		commands.spawn((
+			Highlight,
+			MapEntity,
+			SpriteBundle {
+				sprite: Sprite {
+					color,
+					custom_size: Some(Vec2::splat(TILE_SIZE)),
+					..Default::default()
+				},
+				// Above the tilemap, but beneath units.
+				transform: Transform::from_translation(board.to_world(tile).extend(0.5)),
+				..Default::default()
+			},
+		));
+	}
+}
+
+/// Marks a unit which is carrying another.
+#[derive(Component)]
+struct CarryMarker;
+
+/// Shows a small flag in the corner of each unit which is carrying another.
+fn mark_carriers(
+	mut commands: Commands,
+	units: Query<(Entity, &Unit), Changed<Unit>>,
+	markers: Query<(Entity, &Parent), With<CarryMarker>>,
+) {
+	for (entity, unit) in &units {
+		let marker = markers
+			.iter()
+			.find(|(_, parent)| parent.get() == entity)
+			.map(|(marker, _)| marker);
+		match (&unit.carrying, marker) {
+			(Some(_), None) => {
+				let offset = (TILE_SIZE - CARRY_MARKER_SIZE) / 2.0;
+				let marker = commands
+					.spawn((
+						CarryMarker,
+						SpriteBundle {
+							sprite: Sprite {
+								color: CARRY_MARKER_COLOR,
+								custom_size: Some(Vec2::splat(CARRY_MARKER_SIZE)),
+								..Default::default()
+							},
+							transform: Transform::from_xyz(offset, -offset, 0.1),
+							..Default::default()
+						},
+					))
+					.id();
+				commands.entity(entity).add_child(marker);
+			}
+			(None, Some(marker)) => commands.entity(marker).despawn_recursive(),
+			_ => {}
+		}
+	}
+}
+
+/// Lists the rows of a menu, marking the highlighted one.
+fn rows<'a>(names: impl IntoIterator<Item = &'a str>, highlighted: Option<usize>) -> String {
+	let first = highlighted.map_or(0, |i| (i + 1).saturating_sub(MENU_ROWS));
+	names
+		.into_iter()
+		.enumerate()
+		.skip(first)
+		.take(MENU_ROWS)
+		.map(|(i, name)| {
+			let marker = if Some(i) == highlighted { '>' } else { ' ' };
+			format!("{marker} {name}")
+		})
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+fn item_names(unit: &Unit) -> Vec<String> {
+	inventory(unit)
+		.into_iter()
+		.enumerate()
+		.map(|(i, item)| {
+			if i == 0 && unit.weapon.is_some() {
+				format!("{} (E)", item.name)
+			} else {
+				item.name.clone()
+			}
+		})
+		.collect()
+}
+
+/// Describes the selected unit's menu, or `None` if no menu should be shown.
+fn describe(
+	selected: &Selected,
+	units: &Query<(&Unit, Option<&Position>)>,
+	board: &Board,
+	campaign: &Campaign,
+	content: &Content,
+) -> Option<String> {
+	let (unit, position) = units.get(selected.unit).ok()?;
+	match &selected.stage {
+		Stage::Moving(_) | Stage::Walking { .. } | Stage::Acting { .. } | Stage::Canter(_) => None,
+		Stage::Menu(choices) => {
+			let menu = rows(
+				choices.iter().map(|choice| choice.action.name()),
+				Some(selected.index),
+			);
+			let carried = unit.carrying.and_then(|cargo| units.get(cargo).ok());
+			Some(match carried {
+				Some((carried, _)) => format!("Carrying {}\n{menu}", carried.name),
+				None => menu,
+			})
+		}
+		Stage::Items => {
+			let names = item_names(unit);
+			Some(format!(
+				"{}\n{}",
+				unit.name,
+				rows(names.iter().map(String::as_str), Some(selected.index))
+			))
+		}
+		Stage::Trading { partner, column } => {
+			let (other, _) = units.get(*partner).ok()?;
+			let list = |unit: &Unit, side| {
+				let names = item_names(unit);
+				let highlighted = (side == *column).then_some(selected.index);
+				format!(
+					"{}\n{}",
+					unit.name,
+					rows(names.iter().map(String::as_str), highlighted)
+				)
+			};
+			Some(format!("{}\n\n{}", list(unit, 0), list(other, 1)))
+		}
+		Stage::Convoy { column } => {
+			let names = item_names(unit);
+			let highlighted = |side| (side == *column).then_some(selected.index);
+			Some(format!(
+				"{}\n{}\n\nConvoy ({})\n{}",
+				unit.name,
+				rows(names.iter().map(String::as_str), highlighted(0)),
+				campaign.convoy.len(),
+				rows(
+					campaign.convoy.iter().map(|item| item.name.as_str()),
+					highlighted(1)
+				)
+			))
+		}
+		Stage::Shop { shop, column, .. } => {
+			let priced = |item: &Item, price: Option<u32>| match price {
+				Some(price) => format!("{} {price}", item.name),
+				None => format!("{} -", item.name),
+			};
+			let stock: Vec<String> = shop::stock(shop, content)
+				.into_iter()
+				.map(|item| priced(item, shop::price(item)))
+				.collect();
+			let items: Vec<String> = inventory(unit)
+				.into_iter()
+				.map(|item| priced(item, shop::sell_price(item, &content.module.economy)))
+				.collect();
+			let highlighted = |side| (side == *column).then_some(selected.index);
+			Some(format!(
+				"{} Gold {}\n{}\n\nSell\n{}",
+				shop.name,
+				campaign.gold,
+				rows(stock.iter().map(String::as_str), highlighted(0)),
+				rows(items.iter().map(String::as_str), highlighted(1))
+			))
+		}
+		Stage::Targeting(choice) => match choice.targets.get(selected.index)? {
+			Target::Unit(entity) => {
+				let (target, target_position) = units.get(*entity).ok()?;
+				if choice.action != UnitAction::Attack {
+					return Some(format!("{}: {}", choice.action.name(), target.name));
+				}
+				let terrain = |position: Option<&Position>| {
+					position
+						.and_then(|position| board.terrain(*position))
+						.cloned()
+						.unwrap_or_default()
+				};
+				let ours = forecast(unit, target, &terrain(target_position));
+				let theirs = forecast(target, unit, &terrain(position));
+				let line = |unit: &Unit, forecast: &crate::battle::Forecast| {
+					let strikes = if forecast.strikes > 1 { " x2" } else { "" };
+					format!(
+						"{} {}/{}\nDmg {}{strikes} Hit {} Crt {}",
+						unit.name,
+						unit.hp,
+						unit.stats.hp,
+						forecast.damage,
+						forecast.hit,
+						forecast.crit
+					)
+				};
+				Some(format!("{}\n{}", line(unit, &ours), line(target, &theirs)))
+			}
+			Target::Tile(tile) => Some(format!(
+				"{} at {}, {}",
+				choice.action.name(),
+				tile.x,
+				tile.y
+			)),
+		},
+	}
+}
+
+#[derive(Component)]
+struct MenuBox;
+
+/// Shows the unit menu in the corner of the canvas.
+#[allow(clippy::too_many_arguments)]
+fn draw_menu(
+	mut commands: Commands,
+	selection: Res<Selection>,
+	board: Option<Res<Board>>,
+	campaign: Res<Campaign>,
+	content: Res<Content>,
+	units: Query<(&Unit, Option<&Position>)>,
+	changed: Query<(), Changed<Unit>>,
+	cameras: Query<(Entity, &Camera), With<CanvasCamera>>,
+	boxes: Query<Entity, With<MenuBox>>,
+) {
+	if !selection.is_changed() && !campaign.is_changed() && changed.is_empty() {
+		return;
+	}
+	for entity in &boxes {
+		commands.entity(entity).despawn_recursive();
+	}
+	let (Some(selected), Some(board)) = (&selection.0, board) else {
+		return;
+	};
+	let Some(text) = describe(selected, &units, &board, &campaign, &content) else {
+		return;
+	};
+	let Ok((camera, properties)) = cameras.get_single() else {
+		return;
+	};
+	let Some(size) = properties.logical_viewport_size() else {
+		return;
+	};
+
+	let lines = text.lines().count() as f32;
+	let menu_size = Vec2::new(MENU_WIDTH, lines * FONT_SIZE + PADDING * 2.0);
+	let menu_box = commands
+		.spawn((
+			MenuBox,
+			SpriteBundle {
+				sprite: Sprite {
+					color: MENU_COLOR,
+					custom_size: Some(menu_size),
+					anchor: Anchor::TopLeft,
+					..Default::default()
+				},
+				// Positions are relative to the camera, so that the menu stays in place while it pans.
+				transform: Transform::from_xyz(
+					size.x / 2.0 - MARGIN - menu_size.x,
+					size.y / 2.0 - MARGIN,
+					-10.0,
+				),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(camera).add_child(menu_box);
+
+	let text = commands
+		.spawn(Text2dBundle {
+			text: Text::from_section(
+				text,
+				TextStyle {
+					font_size: FONT_SIZE,
+					color: Color::WHITE,
+					..Default::default()
+				},
+			),
+			text_anchor: Anchor::TopLeft,
+			transform: Transform::from_xyz(PADDING, -PADDING, 1.0),
+			..Default::default()
+		})
+		.id();
+	commands.entity(menu_box).add_child(text);
+}
+
+/// Lets the player move their units and choose their actions from a menu.
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<Selection>()
+			.add_systems(
+				Update,
+				(
+					script_actions,
+					control
+						.run_if(dialogue::is_closed)
+						.run_if(experience::is_closed)
+						.run_if(title::is_closed)
+						.run_if(controls::menu_is_closed),
+					draw_range,
+					draw_menu,
+				)
+					.chain()
+					// Lets battles finish before a unit which attacked moves again.
+					.before(resolve_battles),
+			)
+			.add_systems(Update, mark_carriers);
+	}
+}
@@ -0,0 +1,429 @@
+use crate::battle::{forecast, Attack};
+use crate::dialogue;
+use crate::experience;
+use crate::fog::FogOfWar;
+use crate::map::{Board, Position};
+use crate::script::ScriptRuntime;
+use crate::title;
+use crate::turn::{Acted, Turn};
+use crate::unit::Unit;
+use bevy::prelude::*;
+use fe_data::Behaviour;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Time between each computer-controlled unit's action.
+pub const AI_DELAY: Duration = Duration::from_millis(400);
+
+/// Score bonus for an attack that may defeat its target.
+const KILL_BONUS: i32 = 5000;
+
+/// Marks a unit as computer-controlled.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Ai(pub Behaviour);
+
+/// Paces computer-controlled units so that the player can follow their actions.
+#[derive(Resource, Clone, Debug)]
+pub struct AiTimer(pub Timer);
+
+impl Default for AiTimer {
+	fn default() -> Self {
+		Self(Timer::new(AI_DELAY, TimerMode::Repeating))
+	}
+}
+
+/// A unit as seen by the AI when making decisions.
+#[derive(Clone, Copy, Debug)]
+pub struct Actor<'a> {
+	pub entity: Entity,
+	pub unit: &'a Unit,
+	pub position: Position,
+	/// Whether the deciding unit's side can see this unit.
+	/// Hidden enemies are never targeted, but still stand in the way of units which cannot pass them.
+	pub visible: bool,
+}
+
+/// Where a unit has chosen to move, and who it will attack once it arrives.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Decision {
+	pub destination: Position,
+	pub target: Option<Entity>,
+}
+
+/// Chooses the best action for `actor`.
+///
+/// `units` should contain every unit on the map, and may include `actor` itself.
+/// The result only depends on its inputs,
+/// so a chapter plays out identically given the same seed.
+#[must_use]
+pub fn decide(actor: Actor, behaviour: &Behaviour, units: &[Actor], board: &Board) -> Decision {
+	let stay = Decision {
+		destination: actor.position,
+		target: None,
+	};
+
+	let others: Vec<&Actor> = units
+		.iter()
+		.filter(|other| other.entity != actor.entity)
+		.collect();
+	let mut hostiles: Vec<&Actor> = others
+		.iter()
+		.copied()
+		.filter(|other| other.visible && actor.unit.alignment.is_hostile(other.unit.alignment))
+		.collect();
+	hostiles.sort_by_key(|other| (actor.position.distance(other.position), other.position));
+
+	let occupied: HashMap<Position, &Actor> = others
+		.iter()
+		.map(|other| (other.position, *other))
+		.collect();
+	let passable = |position: Position| {
+		actor.unit.class.pass
+			|| !occupied
+				.get(&position)
+				.is_some_and(|other| actor.unit.alignment.is_hostile(other.unit.alignment))
+	};
+
+	let reachable = board.reachable(
+		actor.position,
+		actor.unit.stats.movement.max(0) as u32,
+		&actor.unit.class,
+		passable,
+	);
+	let mut destinations: Vec<(Position, u32)> = reachable
+		.into_iter()
+		.filter(|(position, _)| !occupied.contains_key(position))
+		.collect();
+	// Cheaper moves are preferred when two options are otherwise equal.
+	destinations.sort_by_key(|(position, cost)| (*cost, *position));
+
+	// Walks as far as possible towards a tile, even if it is far out of reach.
+	let approach = |goal: Position| {
+		let distances = board.reachable(goal, u32::MAX, &actor.unit.class, passable);
+		destinations
+			.iter()
+			.filter_map(|(position, _)| Some((*distances.get(position)?, *position)))
+			.min_by_key(|(distance, _)| *distance)
+			.map_or(stay, |(_, destination)| Decision {
+				destination,
+				target: None,
+			})
+	};
+
+	// Finds the attack with the highest score, if any are possible.
+	let best_attack = |allowed: &dyn Fn(Position) -> bool, weakest: bool| {
+		let mut best: Option<(i32, Decision)> = None;
+		for (destination, _) in destinations
+			.iter()
+			.filter(|(position, _)| allowed(*position))
+		{
+			for target in hostiles
+				.iter()
+				.filter(|target| target.position.distance(*destination) == 1)
+			{
+				let mut score = score_attack(actor.unit, *destination, target, board);
+				if weakest {
+					score -= target.unit.hp * 100;
+				}
+				if best.is_none_or(|(best, _)| score > best) {
+					best = Some((
+						score,
+						Decision {
+							destination: *destination,
+							target: Some(target.entity),
+						},
+					));
+				}
+			}
+		}
+		best.map(|(_, decision)| decision)
+	};
+
+	let anywhere = |_| true;
+
+	let charge = || {
+		best_attack(&anywhere, false)
+			.or_else(|| hostiles.first().map(|target| approach(target.position)))
+			.unwrap_or(stay)
+	};
+
+	match behaviour {
+		Behaviour::Charge => charge(),
+		Behaviour::Hold => best_attack(&anywhere, false).unwrap_or(stay),
+		Behaviour::Guard { x, y, range } => {
+			let guarded = Position::new(*x as i32, *y as i32);
+			best_attack(&|position| position.distance(guarded) <= *range, false)
+				.unwrap_or_else(|| approach(guarded))
+		}
+		Behaviour::Weakest => best_attack(&anywhere, true)
+			.or_else(|| {
+				hostiles
+					.iter()
+					.min_by_key(|target| target.unit.hp)
+					.map(|target| approach(target.position))
+			})
+			.unwrap_or(stay),
+		Behaviour::Retreat { threshold } => {
+			if actor.unit.hp * 100 >= actor.unit.stats.hp * *threshold as i32 {
+				return charge();
+			}
+			let heals = |position| {
+				board
+					.terrain(position)
+					.is_some_and(|terrain| terrain.heal > 0)
+			};
+			let danger = |position: Position| {
+				hostiles
+					.iter()
+					.map(|hostile| hostile.position.distance(position))
+					.min()
+					.unwrap_or(u32::MAX)
+			};
+			// Rest on the safest healing tile in reach, or head for the closest one.
+			if let Some((destination, _)) = destinations
+				.iter()
+				.filter(|(position, _)| heals(*position))
+				.max_by_key(|(position, _)| (danger(*position), std::cmp::Reverse(*position)))
+			{
+				return Decision {
+					destination: *destination,
+					target: None,
+				};
+			}
+			let closest = (0..board.height)
+				.flat_map(|y| (0..board.width).map(move |x| Position::new(x, y)))
+				.filter(|position| heals(*position) && !occupied.contains_key(position))
+				.min_by_key(|position| actor.position.distance(*position));
+			closest.map_or_else(charge, approach)
+		}
+	}
+}
+
+/// Rates an attack by its expected damage, the chance of a kill, and the danger of a counter.
+fn score_attack(unit: &Unit, destination: Position, target: &Actor, board: &Board) -> i32 {
+	let target_terrain = board.terrain(target.position).cloned().unwrap_or_default();
+	let own_terrain = board.terrain(destination).cloned().unwrap_or_default();
+	let ours = forecast(unit, target.unit, &target_terrain);
+	let theirs = forecast(target.unit, unit, &own_terrain);
+
+	let mut score = ours.damage * ours.hit * ours.strikes as i32;
+	if ours.damage * ours.strikes as i32 >= target.unit.hp {
+		score += KILL_BONUS;
+	}
+	score - theirs.damage * theirs.hit * theirs.strikes as i32 / 2
+}
+
+/// Moves one computer-controlled unit at a time during its phase.
+#[allow(clippy::too_many_arguments)]
+fn take_turns(
+	mut commands: Commands,
+	time: Res<Time>,
+	mut timer: ResMut<AiTimer>,
+	turn: Res<Turn>,
+	board: Option<Res<Board>>,
+	fog: Option<Res<FogOfWar>>,
+	waiting: Query<(Entity, &Ai, &Unit), Without<Acted>>,
+	mut units: Query<(Entity, &Unit, &mut Position)>,
+	mut attacks: EventWriter<Attack>,
+	runtime: NonSend<ScriptRuntime>,
+) {
+	// Wait for scripts, such as reinforcements arriving at the start of the phase.
+	if !runtime.is_idle() {
+		return;
+	}
+	let Some(board) = board else {
+		return;
+	};
+	if !timer.0.tick(time.delta()).finished() {
+		return;
+	}
+
+	// Units act in a fixed order so that results are reproducible.
+	let Some((entity, ai, acting)) = waiting
+		.iter()
+		.filter(|(_, _, unit)| unit.alignment == turn.phase)
+		.min_by(|(a, _, a_unit), (b, _, b_unit)| {
+			(&a_unit.identifier, a).cmp(&(&b_unit.identifier, b))
+		})
+	else {
+		return;
+	};
+
+	let actors: Vec<Actor> = units
+		.iter()
+		.map(|(entity, unit, position)| Actor {
+			entity,
+			unit,
+			position: *position,
+			visible: fog
+				.as_ref()
+				.is_none_or(|fog| fog.is_visible(acting.alignment, *position)),
+		})
+		.collect();
+	let Some(actor) = actors.iter().find(|actor| actor.entity == entity) else {
+		return;
+	};
+
+	let decision = decide(*actor, &ai.0, &actors, &board);
+	if let Ok((_, _, mut position)) = units.get_mut(entity) {
+		*position = decision.destination;
+	}
+	commands.entity(entity).insert(Acted);
+	if let Some(target) = decision.target {
+		attacks.send(Attack {
+			attacker: entity,
+			defender: target,
+		});
+	}
+}
+
+/// Controls units with an `Ai` component during their phase.
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<AiTimer>().add_systems(
+			Update,
+			take_turns
+				.run_if(dialogue::is_closed)
+				.run_if(experience::is_closed)
+				.run_if(title::is_closed),
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{actor, weapon};
+	use fe_data::{Alignment, Class, Stats};
+
+	fn unit(alignment: Alignment, hp: i32) -> Unit {
+		let class = Class {
+			bases: Stats {
+				hp,
+				power: 5,
+				movement: 4,
+				..Default::default()
+			},
+			..Default::default()
+		};
+		let mut unit = crate::testing::unit(class, alignment);
+		unit.weapon = Some(weapon(5, 0));
+		unit
+	}
+
+	#[test]
+	fn decisions_do_not_depend_on_unit_order() {
+		let board = Board::clear(9, 9);
+		let enemy = unit(Alignment::Enemy, 20);
+		let first = unit(Alignment::Player1, 20);
+		let second = unit(Alignment::Player1, 20);
+		let units = [
+			actor(0, &enemy, 4, 4),
+			actor(1, &first, 4, 1),
+			actor(2, &second, 1, 4),
+		];
+		let mut reversed = units;
+		reversed.reverse();
+
+		for behaviour in [Behaviour::Charge, Behaviour::Weakest] {
+			let decision = decide(units[0], &behaviour, &units, &board);
+			assert_eq!(decision, decide(units[0], &behaviour, &reversed, &board));
+			assert_eq!(decision, decide(units[0], &behaviour, &units, &board));
+		}
+	}
+
+	#[test]
+	fn charge_attacks_a_target_in_reach() {
+		let board = Board::clear(9, 9);
+		let enemy = unit(Alignment::Enemy, 20);
+		let player = unit(Alignment::Player1, 20);
+		let units = [actor(0, &enemy, 0, 0), actor(1, &player, 3, 0)];
+
+		let decision = decide(units[0], &Behaviour::Charge, &units, &board);
+		assert_eq!(decision.target, Some(units[1].entity));
+		assert_eq!(decision.destination.distance(units[1].position), 1);
+	}
+
+	#[test]
+	fn charge_approaches_a_distant_target() {
+		let board = Board::clear(20, 1);
+		let enemy = unit(Alignment::Enemy, 20);
+		let player = unit(Alignment::Player1, 20);
+		let units = [actor(0, &enemy, 0, 0), actor(1, &player, 19, 0)];
+
+		let decision = decide(units[0], &Behaviour::Charge, &units, &board);
+		assert_eq!(
+			decision,
+			Decision {
+				destination: Position::new(4, 0),
+				target: None,
+			}
+		);
+	}
+
+	#[test]
+	fn hold_waits_for_targets_to_come_within_reach() {
+		let board = Board::clear(20, 1);
+		let enemy = unit(Alignment::Enemy, 20);
+		let player = unit(Alignment::Player1, 20);
+		let units = [actor(0, &enemy, 0, 0), actor(1, &player, 19, 0)];
+
+		let decision = decide(units[0], &Behaviour::Hold, &units, &board);
+		assert_eq!(
+			decision,
+			Decision {
+				destination: Position::new(0, 0),
+				target: None,
+			}
+		);
+	}
+
+	#[test]
+	fn weakest_prefers_the_lowest_hp() {
+		let board = Board::clear(9, 9);
+		let enemy = unit(Alignment::Enemy, 20);
+		let healthy = unit(Alignment::Player1, 20);
+		let mut wounded = unit(Alignment::Player1, 20);
+		wounded.hp = 15;
+		let units = [
+			actor(0, &enemy, 4, 4),
+			actor(1, &healthy, 4, 2),
+			actor(2, &wounded, 4, 6),
+		];
+
+		let decision = decide(units[0], &Behaviour::Weakest, &units, &board);
+		assert_eq!(decision.target, Some(units[2].entity));
+	}
+
+	#[test]
+	fn hidden_units_are_not_targeted() {
+		let board = Board::clear(9, 9);
+		let enemy = unit(Alignment::Enemy, 20);
+		let player = unit(Alignment::Player1, 20);
+		let mut units = [actor(0, &enemy, 0, 0), actor(1, &player, 2, 0)];
+		units[1].visible = false;
+
+		let decision = decide(units[0], &Behaviour::Charge, &units, &board);
+		assert_eq!(decision.target, None);
+	}
+
+	#[test]
+	fn hidden_units_still_block_movement() {
+		// A corridor which the hidden unit fills.
+		let board = Board::clear(9, 1);
+		let enemy = unit(Alignment::Enemy, 20);
+		let player = unit(Alignment::Player1, 20);
+		let mut units = [
+			actor(0, &enemy, 0, 0),
+			actor(1, &player, 1, 0),
+			actor(2, &player, 3, 0),
+		];
+		units[1].visible = false;
+
+		let decision = decide(units[0], &Behaviour::Charge, &units, &board);
+		assert_eq!(decision.destination, Position::new(0, 0));
+		assert_eq!(decision.target, None);
+	}
+}
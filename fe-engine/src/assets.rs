@@ -0,0 +1,143 @@
+use crate::module::Content;
+use bevy::asset::{AssetIo, AssetIoError, ChangeWatcher, FileType, Metadata};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Files provided by the engine, which modules may override by providing a file at the same path.
+const EMBEDDED: &[(&str, &[u8])] = &[
+	("background.png", include_bytes!("../background.png")),
+	("cursor.png", include_bytes!("../cursor.png")),
+];
+
+fn embedded(path: &Path) -> Option<&'static [u8]> {
+	EMBEDDED
+		.iter()
+		.find(|(name, _)| Path::new(name) == path)
+		.map(|(_, bytes)| *bytes)
+}
+
+/// Loads assets relative to the active module's directory,
+/// falling back to the engine's embedded files.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleAssetIo {
+	root: Arc<RwLock<PathBuf>>,
+}
+
+impl ModuleAssetIo {
+	#[must_use]
+	pub fn new(root: impl Into<PathBuf>) -> Self {
+		Self {
+			root: Arc::new(RwLock::new(root.into())),
+		}
+	}
+
+	/// Changes the directory which assets are loaded from, returning true if it is a different one.
+	///
+	/// Assets which have already been loaded are not affected.
+	pub fn set_root(&self, root: impl Into<PathBuf>) -> bool {
+		let root = root.into();
+		match self.root.write() {
+			Ok(mut current) if *current != root => {
+				*current = root;
+				true
+			}
+			_ => false,
+		}
+	}
+
+	fn full_path(&self, path: &Path) -> PathBuf {
+		self.root
+			.read()
+			.map(|root| root.join(path))
+			.unwrap_or_else(|_| path.to_path_buf())
+	}
+}
+
+impl AssetIo for ModuleAssetIo {
+	fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
+		Box::pin(async move {
+			let full_path = self.full_path(path);
+			match fs::read(&full_path) {
+				Ok(bytes) => Ok(bytes),
+				Err(e) if e.kind() == ErrorKind::NotFound => embedded(path)
+					.map(<[u8]>::to_vec)
+					.ok_or(AssetIoError::NotFound(full_path)),
+				Err(e) => Err(e.into()),
+			}
+		})
+	}
+
+	fn read_directory(
+		&self,
+		path: &Path,
+	) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
+		let root = self.full_path(Path::new(""));
+		let entries = fs::read_dir(root.join(path))?;
+		Ok(Box::new(entries.filter_map(move |entry| {
+			Some(entry.ok()?.path().strip_prefix(&root).ok()?.to_owned())
+		})))
+	}
+
+	fn get_metadata(&self, path: &Path) -> Result<Metadata, AssetIoError> {
+		let full_path = self.full_path(path);
+		match full_path.metadata() {
+			Ok(metadata) => Ok(Metadata::try_from(metadata)?),
+			Err(e) if e.kind() == ErrorKind::NotFound => embedded(path)
+				.map(|_| Metadata::new(FileType::File))
+				.ok_or(AssetIoError::NotFound(full_path)),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	fn watch_path_for_changes(
+		&self,
+		_to_watch: &Path,
+		_to_reload: Option<PathBuf>,
+	) -> Result<(), AssetIoError> {
+		Ok(())
+	}
+
+	fn watch_for_changes(&self, _configuration: &ChangeWatcher) -> Result<(), AssetIoError> {
+		Ok(())
+	}
+}
+
+/// Moves the asset source along with the active module.
+///
+/// Files the engine embeds may have been loaded before the module was chosen,
+/// so they are reloaded in case the module overrides them.
+fn follow_module(content: Res<Content>, asset_server: Res<AssetServer>) {
+	if !content.is_changed() {
+		return;
+	}
+	let Some(asset_io) = asset_server.asset_io().downcast_ref::<ModuleAssetIo>() else {
+		return;
+	};
+	if asset_io.set_root(&content.module.path) {
+		for (path, _) in EMBEDDED {
+			asset_server.reload_asset(*path);
+		}
+	}
+}
+
+/// Loads assets from the active module's directory, or from the engine itself.
+///
+/// This replaces the `AssetServer`, so it must be added before `AssetPlugin`.
+/// If `Content` has already been inserted, its module is used as the initial root.
+pub struct ModuleAssetsPlugin;
+
+impl Plugin for ModuleAssetsPlugin {
+	fn build(&self, app: &mut App) {
+		let root = app
+			.world
+			.get_resource::<Content>()
+			.map(|content| content.module.path.clone())
+			.unwrap_or_default();
+		app.insert_resource(AssetServer::new(ModuleAssetIo::new(root)))
+			.add_systems(PreUpdate, follow_module);
+	}
+}
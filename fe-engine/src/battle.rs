@@ -0,0 +1,500 @@
+use crate::experience::GainExperience;
+use crate::fog::FogOfWar;
+use crate::map::{Board, Position};
+use crate::module::Content;
+use crate::rng::GameRng;
+use crate::unit::{drop_cargo, Unit};
+use bevy::prelude::*;
+use fe_data::{Alignment, Experience, Item, ItemType, Terrain};
+
+/// Attack speed advantage required to strike twice.
+pub const DOUBLING_THRESHOLD: i32 = 4;
+/// Damage multiplier of a critical hit.
+pub const CRIT_MULTIPLIER: i32 = 3;
+/// Hit rate granted before any stats are considered.
+pub const BASE_HIT: i32 = 70;
+/// Critical rate granted by `Class::crit_boost`.
+pub const CRIT_BOOST: i32 = 20;
+
+/// Requests a battle between two units.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Attack {
+	pub attacker: Entity,
+	pub defender: Entity,
+}
+
+/// Sent once a battle has been resolved, so that it can be animated or reported.
+#[derive(Event, Clone, Debug)]
+pub struct BattleFinished {
+	pub attacker: Entity,
+	pub defender: Entity,
+	pub result: BattleResult,
+}
+
+/// Sent when a unit is defeated and removed from the map.
+#[derive(Event, Clone, Debug)]
+pub struct UnitDefeated {
+	pub entity: Entity,
+	pub identifier: String,
+	pub alignment: Alignment,
+	pub leader: bool,
+	/// Where the unit stood when it was defeated.
+	pub position: Position,
+}
+
+/// The numbers shown to the player before they commit to an attack.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Forecast {
+	pub damage: i32,
+	pub hit: i32,
+	pub crit: i32,
+	/// Number of times this side will attack. 0 if it cannot attack at all.
+	pub strikes: u32,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Side {
+	Attacker,
+	Defender,
+}
+
+/// A single attack within a battle.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Strike {
+	pub side: Side,
+	pub hit: bool,
+	pub crit: bool,
+	/// Damage actually dealt, after `focus` has been applied.
+	pub damage: i32,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BattleResult {
+	pub strikes: Vec<Strike>,
+	pub attacker_experience: u32,
+	pub defender_experience: u32,
+}
+
+/// Calculates how `unit` would fare when attacking `target`,
+/// who is standing on `terrain`.
+#[must_use]
+pub fn forecast(unit: &Unit, target: &Unit, terrain: &Terrain) -> Forecast {
+	let Some(weapon) = unit.weapon() else {
+		return Forecast::default();
+	};
+
+	let crit_boost = if unit.class.crit_boost { CRIT_BOOST } else { 0 };
+	let (stats, target_stats) = (unit.battle_stats(), target.battle_stats());
+
+	Forecast {
+		damage: (stats.power + weapon.damage as i32 - target_stats.defense - terrain.defense)
+			.max(0),
+		hit: (BASE_HIT + stats.dexterity * 2 - target.attack_speed() * 2 - terrain.avoid)
+			.clamp(0, 100),
+		crit: (stats.dexterity / 2 + crit_boost - target_stats.reflexes / 2).clamp(0, 100),
+		strikes: if unit.attack_speed() - target.attack_speed() >= DOUBLING_THRESHOLD {
+			2
+		} else {
+			1
+		},
+	}
+}
+
+/// Experience earned by `unit` for fighting `target`, which is never more than a level's worth.
+#[must_use]
+pub fn experience(unit: &Unit, target: &Unit, dealt_damage: bool, config: &Experience) -> u32 {
+	if !dealt_damage && target.is_alive() {
+		return config.minimum;
+	}
+	let difference = target.level as i32 - unit.level as i32;
+	let mut experience = config.battle as i32 + difference * config.level_difference as i32;
+	if !target.is_alive() {
+		experience += config.kill as i32;
+		if target.leader {
+			experience += config.boss as i32;
+		}
+	}
+	(experience.max(config.minimum as i32) as u32).min(config.threshold)
+}
+
+/// Runs the full attack sequence between two units, modifying them in place.
+///
+/// The attacker strikes first, then the defender counters,
+/// and finally whichever unit is fast enough strikes again.
+/// The battle ends early if either unit is defeated.
+/// The experience each survivor earned is included in the result, but not yet given to them.
+pub fn resolve(
+	attacker: &mut Unit,
+	attacker_terrain: &Terrain,
+	defender: &mut Unit,
+	defender_terrain: &Terrain,
+	config: &Experience,
+	rng: &mut GameRng,
+) -> BattleResult {
+	let attacker_forecast = forecast(attacker, defender, defender_terrain);
+	let defender_forecast = forecast(defender, attacker, attacker_terrain);
+
+	let mut order = vec![Side::Attacker, Side::Defender];
+	if attacker_forecast.strikes > 1 {
+		order.push(Side::Attacker);
+	} else if defender_forecast.strikes > 1 {
+		order.push(Side::Defender);
+	}
+
+	let mut result = BattleResult::default();
+
+	for side in order {
+		if !attacker.is_alive() || !defender.is_alive() {
+			break;
+		}
+		let (unit, target, forecast) = match side {
+			Side::Attacker => (&mut *attacker, &mut *defender, &attacker_forecast),
+			Side::Defender => (&mut *defender, &mut *attacker, &defender_forecast),
+		};
+		// Weapons may break partway through a battle.
+		if unit.weapon().is_none() {
+			continue;
+		}
+		result
+			.strikes
+			.push(strike(side, unit, target, forecast, rng));
+	}
+
+	let dealt_damage = |side| {
+		result
+			.strikes
+			.iter()
+			.any(|strike| strike.side == side && strike.damage > 0)
+	};
+	let attacker_dealt_damage = dealt_damage(Side::Attacker);
+	let defender_dealt_damage = dealt_damage(Side::Defender);
+
+	if attacker.is_alive() {
+		result.attacker_experience = experience(attacker, defender, attacker_dealt_damage, config);
+	}
+	if defender.is_alive() {
+		result.defender_experience = experience(defender, attacker, defender_dealt_damage, config);
+	}
+
+	result
+}
+
+fn strike(
+	side: Side,
+	unit: &mut Unit,
+	target: &mut Unit,
+	forecast: &Forecast,
+	rng: &mut GameRng,
+) -> Strike {
+	let hit = rng.roll_2rn(forecast.hit);
+	let crit = hit && rng.roll(forecast.crit);
+
+	let mut damage = 0;
+	if hit {
+		damage = if crit {
+			forecast.damage * CRIT_MULTIPLIER
+		} else {
+			forecast.damage
+		};
+		// Focus leaves the target at 1 hp, unless they were already there.
+		if target.class.focus && !crit && target.hp > 1 && damage >= target.hp {
+			damage = target.hp - 1;
+		}
+		target.hp = (target.hp - damage).max(0);
+	}
+
+	use_weapon(unit);
+
+	Strike {
+		side,
+		hit,
+		crit,
+		damage,
+	}
+}
+
+/// Reduces the durability of a unit's weapon, breaking it once it runs out.
+fn use_weapon(unit: &mut Unit) {
+	let broken = match &mut unit.weapon {
+		Some(Item {
+			ty: ItemType::Weapon(weapon),
+			..
+		}) if weapon.durability > 0 => {
+			weapon.durability -= 1;
+			weapon.durability == 0
+		}
+		_ => false,
+	};
+	if broken {
+		info!("{}'s weapon broke!", unit.name);
+		unit.weapon = None;
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn resolve_battles(
+	mut commands: Commands,
+	mut requests: EventReader<Attack>,
+	mut units: Query<(&mut Unit, &Position)>,
+	content: Res<Content>,
+	board: Option<Res<Board>>,
+	fog: Option<Res<FogOfWar>>,
+	mut rng: ResMut<GameRng>,
+	mut finished: EventWriter<BattleFinished>,
+	mut defeated: EventWriter<UnitDefeated>,
+	mut experience: EventWriter<GainExperience>,
+) {
+	for request in requests.iter() {
+		let Ok([(mut attacker, attacker_position), (mut defender, defender_position)]) =
+			units.get_many_mut([request.attacker, request.defender])
+		else {
+			warn!("Invalid battle request: {request:?}");
+			continue;
+		};
+		// A unit may have been defeated by an earlier battle this frame.
+		if !attacker.is_alive() || !defender.is_alive() {
+			continue;
+		}
+		if fog
+			.as_ref()
+			.is_some_and(|fog| !fog.is_visible(attacker.alignment, *defender_position))
+		{
+			warn!(
+				"{} cannot see {} to attack them",
+				attacker.name, defender.name
+			);
+			continue;
+		}
+
+		let terrain = |position| {
+			board
+				.as_ref()
+				.and_then(|board| board.terrain(position))
+				.cloned()
+				.unwrap_or_default()
+		};
+		let result = resolve(
+			&mut attacker,
+			&terrain(*attacker_position),
+			&mut defender,
+			&terrain(*defender_position),
+			&content.module.experience,
+			&mut rng,
+		);
+
+		for (entity, unit, position) in [
+			(request.attacker, &*attacker, *attacker_position),
+			(request.defender, &*defender, *defender_position),
+		] {
+			if !unit.is_alive() {
+				info!("{} was defeated.", unit.name);
+				defeated.send(UnitDefeated {
+					entity,
+					identifier: unit.identifier.clone(),
+					alignment: unit.alignment,
+					leader: unit.leader,
+					position,
+				});
+				commands.entity(entity).despawn_recursive();
+				// Anyone being carried is left where their carrier fell.
+				drop_cargo(&mut commands, unit, position);
+			}
+		}
+
+		for (entity, amount) in [
+			(request.attacker, result.attacker_experience),
+			(request.defender, result.defender_experience),
+		] {
+			if amount > 0 {
+				experience.send(GainExperience { entity, amount });
+			}
+		}
+
+		finished.send(BattleFinished {
+			attacker: request.attacker,
+			defender: request.defender,
+			result,
+		});
+	}
+}
+
+/// Resolves `Attack` requests using the `GameRng` resource.
+pub struct BattlePlugin;
+
+impl Plugin for BattlePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_event::<Attack>()
+			.add_event::<BattleFinished>()
+			.add_event::<UnitDefeated>()
+			.add_systems(Update, resolve_battles);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::weapon;
+	use fe_data::{Class, Stats};
+
+	fn unit(stats: Stats, weapon: Option<Item>) -> Unit {
+		let class = Class {
+			bases: stats,
+			..Default::default()
+		};
+		let mut unit = crate::testing::unit(class, Alignment::Player1);
+		unit.weapon = weapon;
+		unit
+	}
+
+	/// A unit which can neither hurt nor be hurt by another `wall`.
+	fn wall(reflexes: i32) -> Unit {
+		unit(
+			Stats {
+				hp: 20,
+				defense: 20,
+				reflexes,
+				..Default::default()
+			},
+			Some(weapon(1, 0)),
+		)
+	}
+
+	fn fight(attacker: &mut Unit, defender: &mut Unit, seed: u64) -> BattleResult {
+		resolve(
+			attacker,
+			&Terrain::default(),
+			defender,
+			&Terrain::default(),
+			&Experience::default(),
+			&mut GameRng::new(seed),
+		)
+	}
+
+	fn sides(result: &BattleResult) -> Vec<Side> {
+		result.strikes.iter().map(|strike| strike.side).collect()
+	}
+
+	#[test]
+	fn battles_are_reproducible() {
+		let attacker = unit(
+			Stats {
+				hp: 30,
+				power: 8,
+				dexterity: 5,
+				reflexes: 5,
+				..Default::default()
+			},
+			Some(weapon(5, 0)),
+		);
+		let defender = attacker.clone();
+
+		for seed in 0..20 {
+			let (mut a, mut b) = (attacker.clone(), defender.clone());
+			let (mut c, mut d) = (attacker.clone(), defender.clone());
+			assert_eq!(fight(&mut a, &mut b, seed), fight(&mut c, &mut d, seed));
+			assert_eq!((a.hp, b.hp), (c.hp, d.hp));
+		}
+	}
+
+	#[test]
+	fn defender_counters_and_the_faster_unit_doubles() {
+		let result = fight(&mut wall(0), &mut wall(0), 0);
+		assert_eq!(sides(&result), [Side::Attacker, Side::Defender]);
+
+		let result = fight(&mut wall(DOUBLING_THRESHOLD), &mut wall(0), 0);
+		assert_eq!(
+			sides(&result),
+			[Side::Attacker, Side::Defender, Side::Attacker]
+		);
+
+		let result = fight(&mut wall(0), &mut wall(DOUBLING_THRESHOLD), 0);
+		assert_eq!(
+			sides(&result),
+			[Side::Attacker, Side::Defender, Side::Defender]
+		);
+	}
+
+	#[test]
+	fn unarmed_units_do_not_counter() {
+		let mut defender = wall(0);
+		defender.weapon = None;
+
+		let result = fight(&mut wall(0), &mut defender, 0);
+		assert_eq!(sides(&result), [Side::Attacker]);
+	}
+
+	#[test]
+	fn weapons_break_when_they_run_out() {
+		let mut attacker = wall(DOUBLING_THRESHOLD);
+		attacker.weapon = Some(weapon(1, 1));
+
+		let result = fight(&mut attacker, &mut wall(0), 0);
+		assert_eq!(sides(&result), [Side::Attacker, Side::Defender]);
+		assert!(attacker.weapon.is_none());
+	}
+
+	#[test]
+	fn battles_end_once_a_unit_is_defeated() {
+		let mut attacker = unit(
+			Stats {
+				hp: 20,
+				power: 50,
+				dexterity: 20,
+				reflexes: DOUBLING_THRESHOLD,
+				..Default::default()
+			},
+			Some(weapon(1, 0)),
+		);
+		let mut defender = wall(0);
+		defender.stats.defense = 0;
+
+		let result = fight(&mut attacker, &mut defender, 0);
+		assert_eq!(sides(&result), [Side::Attacker]);
+		assert!(result.strikes[0].hit);
+		assert!(!defender.is_alive());
+		assert_eq!(result.defender_experience, 0);
+		assert!(result.attacker_experience > 0);
+	}
+
+	fn levelled(level: u32) -> Unit {
+		let mut unit = wall(0);
+		unit.level = level;
+		unit
+	}
+
+	#[test]
+	fn experience_follows_the_level_difference() {
+		let config = Experience::default();
+		let (low, high) = (levelled(1), levelled(5));
+
+		assert_eq!(experience(&low, &high, true, &config), 14);
+		assert_eq!(experience(&high, &low, true, &config), 6);
+	}
+
+	#[test]
+	fn experience_is_at_least_the_minimum() {
+		let config = Experience {
+			minimum: 3,
+			..Default::default()
+		};
+		let (low, high) = (levelled(1), levelled(20));
+
+		assert_eq!(experience(&high, &low, true, &config), 3);
+		// Fights where no damage was dealt only earn the minimum.
+		assert_eq!(experience(&low, &high, false, &config), 3);
+	}
+
+	#[test]
+	fn experience_is_clamped_to_a_level() {
+		let config = Experience {
+			threshold: 50,
+			..Default::default()
+		};
+		let low = levelled(1);
+		let mut boss = levelled(20);
+		boss.leader = true;
+		boss.hp = 0;
+
+		assert_eq!(experience(&low, &boss, true, &config), 50);
+	}
+}
@@ -0,0 +1,154 @@
+use crate::cursor::{self, Cursor};
+use crate::map::{Board, Position, TILE_SIZE};
+use crate::ppcanvas::CanvasCamera;
+use bevy::prelude::*;
+
+/// Speed at which the camera pans, in pixels per second.
+pub const PAN_SPEED: f32 = 128.0;
+/// How close the cursor may come to the edges of the canvas before the camera follows it, in pixels.
+pub const FOLLOW_MARGIN: f32 = 2.0 * TILE_SIZE;
+/// How quickly the camera catches up with the cursor.
+/// Each second, the remaining distance shrinks by a factor of e to this power.
+pub const FOLLOW_RATE: f32 = 12.0;
+
+/// Smoothly moves the camera until it is centered on a tile.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PanCamera(pub Position);
+
+/// Sent once the camera has reached the destination of a `PanCamera` request.
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct PanFinished;
+
+/// Where the camera is currently panning to, if anywhere.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct CameraTarget(pub Option<Vec2>);
+
+/// Where the camera rests while it is not panning, which follows the cursor.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct CameraFocus {
+	pub position: Vec2,
+	/// Where the cursor was when the focus was last updated.
+	cursor: Option<Vec2>,
+}
+
+/// Limits the center of the camera so that it never shows anything beyond the edges of the map.
+///
+/// Along each axis where the map is smaller than the view, the map is centered instead.
+#[must_use]
+pub fn clamp(board: &Board, view: Vec2, position: Vec2) -> Vec2 {
+	let map = Vec2::new(board.width as f32, board.height as f32) * TILE_SIZE;
+	let limit = ((map - view) / 2.0).max(Vec2::ZERO);
+	position.clamp(-limit, limit)
+}
+
+fn view_size(cameras: &Query<&Camera, With<CanvasCamera>>) -> Option<Vec2> {
+	cameras.get_single().ok()?.logical_viewport_size()
+}
+
+fn start_pan(
+	mut requests: EventReader<PanCamera>,
+	board: Option<Res<Board>>,
+	cameras: Query<&Camera, With<CanvasCamera>>,
+	mut target: ResMut<CameraTarget>,
+) {
+	let Some(board) = board else {
+		return;
+	};
+	if let Some(PanCamera(position)) = requests.iter().last() {
+		let destination = board.to_world(*position);
+		target.0 = Some(match view_size(&cameras) {
+			Some(view) => clamp(&board, view, destination),
+			None => destination,
+		});
+	}
+}
+
+/// Moves the focus just far enough to keep the cursor away from the edges of the canvas.
+///
+/// The focus only changes when the cursor moves, so the camera stays where a script left it until then.
+fn follow_cursor(
+	board: Option<Res<Board>>,
+	cursors: Query<&Transform, With<Cursor>>,
+	cameras: Query<&Camera, With<CanvasCamera>>,
+	mut focus: ResMut<CameraFocus>,
+) {
+	let Some(board) = board else {
+		return;
+	};
+	if board.is_added() {
+		focus.cursor = None;
+	}
+	let (Ok(cursor), Some(view)) = (cursors.get_single(), view_size(&cameras)) else {
+		return;
+	};
+	let cursor = cursor.translation.truncate();
+	if focus.cursor == Some(cursor) {
+		return;
+	}
+	focus.cursor = Some(cursor);
+
+	// How far the center of the cursor's tile may be from the center of the canvas.
+	let reach = (view / 2.0 - FOLLOW_MARGIN - TILE_SIZE / 2.0).max(Vec2::ZERO);
+	let offset = cursor - focus.position;
+	let position = focus.position + offset - offset.clamp(-reach, reach);
+	focus.position = clamp(&board, view, position);
+}
+
+fn pan(
+	time: Res<Time>,
+	mut target: ResMut<CameraTarget>,
+	mut focus: ResMut<CameraFocus>,
+	mut cameras: Query<&mut Transform, With<CanvasCamera>>,
+	mut finished: EventWriter<PanFinished>,
+) {
+	let Some(destination) = target.0 else {
+		// Eases towards the focus, so that following the cursor is quick without being jarring.
+		let rate = 1.0 - (-FOLLOW_RATE * time.delta_seconds()).exp();
+		for mut transform in &mut cameras {
+			let offset = focus.position - transform.translation.truncate();
+			let movement = if offset.length() < 0.5 {
+				offset
+			} else {
+				offset * rate
+			};
+			transform.translation += movement.extend(0.0);
+		}
+		return;
+	};
+	let step = PAN_SPEED * time.delta_seconds();
+
+	for mut transform in &mut cameras {
+		let offset = destination - transform.translation.truncate();
+		let movement = if offset.length() <= step {
+			offset
+		} else {
+			offset.normalize() * step
+		};
+		transform.translation += movement.extend(0.0);
+	}
+
+	if cameras
+		.iter()
+		.all(|transform| transform.translation.truncate() == destination)
+	{
+		target.0 = None;
+		focus.position = destination;
+		finished.send(PanFinished);
+	}
+}
+
+/// Moves the map's camera, following the cursor unless a script is panning it.
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<CameraTarget>()
+			.init_resource::<CameraFocus>()
+			.add_event::<PanCamera>()
+			.add_event::<PanFinished>()
+			.add_systems(
+				Update,
+				(start_pan, follow_cursor.after(cursor::movement), pan).chain(),
+			);
+	}
+}
@@ -0,0 +1,260 @@
+use crate::battle::UnitDefeated;
+use crate::map::{load_map, Board, LoadMap, MapLoaded};
+use crate::module::{Content, Module};
+use crate::objectives::{MapFinished, Outcome, UnitEscaped, PLAYER};
+use crate::save::{SaveGame, Slot};
+use crate::script::{
+	dispatch_signals, Command, ScriptEventFinished, ScriptRequest, ScriptRuntime, Variables,
+};
+use crate::unit::Unit;
+use bevy::prelude::*;
+use fe_data::{Chapter, Item};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Progress through a module, carried from one chapter to the next.
+#[derive(Resource, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Campaign {
+	/// Identifier of the chapter being played, if any.
+	pub chapter: Option<String>,
+	/// Chapters which have been cleared, in the order they were played.
+	pub completed: Vec<String>,
+	/// The player's units, indexed by identifier, as they were when last recorded.
+	pub roster: BTreeMap<String, Unit>,
+	/// The player's units which have been defeated, and are no longer placed by maps.
+	#[serde(default)]
+	pub fallen: BTreeSet<String>,
+	/// Items shared by the player's units, which the module's convoy units can reach.
+	#[serde(default)]
+	pub convoy: Vec<Item>,
+	/// Gold the player has to spend in shops.
+	#[serde(default)]
+	pub gold: u32,
+	/// Campaign slot which progress is saved to whenever a chapter is cleared.
+	#[serde(default)]
+	pub slot: u32,
+}
+
+impl Campaign {
+	/// Begins a new campaign with the module's starting gold.
+	#[must_use]
+	pub fn new(module: &Module) -> Self {
+		Self {
+			gold: module.economy.gold,
+			..Default::default()
+		}
+	}
+
+	/// Records the current state of a player's unit.
+	pub fn enlist(&mut self, unit: &Unit) {
+		self.roster.insert(unit.identifier.clone(), unit.clone());
+	}
+
+	/// Returns a unit from the roster, restored to full health for a new chapter.
+	#[must_use]
+	pub fn deploy(&self, identifier: &str) -> Option<Unit> {
+		let mut unit = self.roster.get(identifier)?.clone();
+		unit.hp = unit.stats.hp;
+		unit.carrying = None;
+		Some(unit)
+	}
+
+	/// Returns the current chapter, if it is played on `map`.
+	#[must_use]
+	pub fn current(&self, content: &Content, map: &str) -> Option<Chapter> {
+		self.chapter
+			.as_ref()
+			.and_then(|chapter| content.chapter(chapter))
+			.filter(|chapter| chapter.map.identifier == map)
+	}
+}
+
+/// Starts a chapter from the beginning, loading its map.
+#[derive(Event, Clone, Debug)]
+pub struct StartChapter(pub String);
+
+/// Where the campaign is within the current chapter.
+#[derive(Resource, Clone, Debug, Default)]
+enum Progress {
+	/// The chapter is being played, or none has started.
+	#[default]
+	Playing,
+	/// The chapter's map has been requested, and its pre-battle scripts have not started.
+	Starting,
+	/// The chapter has been won, and its post-battle scripts are running.
+	Cleared { next: Option<String> },
+}
+
+/// Chooses the chapter which follows `identifier`.
+///
+/// Branches and the chapter's own `next` take priority over the campaign's order.
+fn next_chapter(
+	content: &Content,
+	identifier: &str,
+	chapter: &Chapter,
+	flags: &BTreeSet<String>,
+) -> Option<String> {
+	if let Some(next) = chapter.next(flags) {
+		return Some(next.identifier.clone());
+	}
+	let order = content.campaign();
+	order
+		.iter()
+		.position(|other| other == identifier)
+		.and_then(|index| order.get(index + 1))
+		.cloned()
+}
+
+fn start_chapter(
+	mut requests: EventReader<StartChapter>,
+	content: Res<Content>,
+	mut campaign: ResMut<Campaign>,
+	mut progress: ResMut<Progress>,
+	mut maps: EventWriter<LoadMap>,
+) {
+	let Some(StartChapter(identifier)) = requests.iter().last() else {
+		return;
+	};
+	let Some(chapter) = content.chapter(identifier) else {
+		error!("Unknown chapter: {identifier}");
+		return;
+	};
+	info!("Starting chapter: {}", chapter.name);
+	campaign.chapter = Some(identifier.clone());
+	*progress = Progress::Starting;
+	maps.send(LoadMap(chapter.map.identifier));
+}
+
+/// Runs the pre-battle scripts once a chapter's map has been loaded.
+///
+/// Maps restored from a suspended game have already run them.
+fn begin_chapter(
+	mut runtime: NonSendMut<ScriptRuntime>,
+	mut loaded: EventReader<MapLoaded>,
+	mut progress: ResMut<Progress>,
+) {
+	if loaded.iter().count() == 0 {
+		return;
+	}
+	if matches!(*progress, Progress::Starting) {
+		runtime.dispatch("pre_battle", None, Vec::new());
+	}
+	*progress = Progress::Playing;
+}
+
+/// Records the player's units and chooses the next chapter once the current one is won.
+#[allow(clippy::too_many_arguments)]
+fn complete_chapter(
+	mut runtime: NonSendMut<ScriptRuntime>,
+	mut outcomes: EventReader<MapFinished>,
+	content: Res<Content>,
+	variables: Res<Variables>,
+	board: Option<Res<Board>>,
+	mut campaign: ResMut<Campaign>,
+	mut progress: ResMut<Progress>,
+	units: Query<&Unit>,
+) {
+	for MapFinished { outcome, .. } in outcomes.iter() {
+		// Defeats open the game over menu instead, from which the chapter can be retried.
+		if *outcome != Outcome::Victory {
+			continue;
+		}
+		for unit in &units {
+			if unit.alignment == PLAYER && unit.is_alive() {
+				campaign.enlist(unit);
+			}
+		}
+		let Some(identifier) = campaign.chapter.clone() else {
+			continue;
+		};
+		let next = board
+			.as_ref()
+			.and_then(|board| campaign.current(&content, &board.map))
+			.and_then(|chapter| next_chapter(&content, &identifier, &chapter, &variables.flags));
+		campaign.completed.push(identifier);
+		runtime.dispatch("post_battle", None, Vec::new());
+		*progress = Progress::Cleared { next };
+	}
+}
+
+/// Saves the campaign and moves on to the next chapter once every script has finished.
+fn advance(
+	runtime: NonSend<ScriptRuntime>,
+	mut campaign: ResMut<Campaign>,
+	mut progress: ResMut<Progress>,
+	mut chapters: EventWriter<StartChapter>,
+	mut saves: EventWriter<SaveGame>,
+) {
+	// Signals for the map's outcome may only be sent on the frame after it was won.
+	if progress.is_changed() || !runtime.is_idle() {
+		return;
+	}
+	let Progress::Cleared { next } = &*progress else {
+		return;
+	};
+	// Loading the save continues from the next chapter.
+	campaign.chapter = next.clone();
+	saves.send(SaveGame(Slot::Campaign(campaign.slot)));
+	match next {
+		Some(next) => chapters.send(StartChapter(next.clone())),
+		None => info!("The campaign is complete."),
+	}
+	*progress = Progress::Playing;
+}
+
+/// Keeps units which escape the map, so that they are not lost with it.
+fn enlist_escapees(mut escapes: EventReader<UnitEscaped>, mut campaign: ResMut<Campaign>) {
+	for UnitEscaped { unit, .. } in escapes.iter() {
+		campaign.enlist(unit);
+	}
+}
+
+/// Removes the player's defeated units from the roster for good.
+fn retire_fallen(mut defeated: EventReader<UnitDefeated>, mut campaign: ResMut<Campaign>) {
+	for UnitDefeated {
+		identifier,
+		alignment,
+		..
+	} in defeated.iter()
+	{
+		if *alignment == PLAYER {
+			campaign.roster.remove(identifier);
+			campaign.fallen.insert(identifier.clone());
+		}
+	}
+}
+
+/// Changes the player's gold as scripts request.
+fn script_gold(
+	mut requests: EventReader<ScriptRequest>,
+	mut campaign: ResMut<Campaign>,
+	mut finished: EventWriter<ScriptEventFinished>,
+) {
+	for request in requests.iter() {
+		let Command::GiveGold { amount } = request.command else {
+			continue;
+		};
+		let gold = i64::from(campaign.gold).saturating_add(amount);
+		campaign.gold = gold.clamp(0, i64::from(u32::MAX)) as u32;
+		finished.send(ScriptEventFinished(request.handle));
+	}
+}
+
+/// Plays a module's chapters one after another, keeping track of the player's progress.
+pub struct CampaignPlugin;
+
+impl Plugin for CampaignPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<Campaign>()
+			.init_resource::<Progress>()
+			.add_event::<StartChapter>()
+			.add_systems(Update, start_chapter.before(load_map))
+			.add_systems(
+				Update,
+				(begin_chapter, complete_chapter, advance)
+					.chain()
+					.after(dispatch_signals),
+			)
+			.add_systems(Update, (enlist_escapees, retire_fallen, script_gold));
+	}
+}
@@ -0,0 +1,467 @@
+use crate::cursor::UiAction;
+use crate::module::Module;
+use crate::ppcanvas::CanvasCamera;
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The player's settings, relative to the working directory.
+pub const USER_SETTINGS: &str = "settings.toml";
+/// A module's default settings, relative to the module's directory.
+pub const MODULE_SETTINGS: &str = "settings.toml";
+
+/// Space between the menu and the edges of the canvas.
+/// Actions which the controls menu needs, so that keyboards always keep a key for each.
+const MENU_ACTIONS: [UiAction; 3] = [UiAction::Select, UiAction::Back, UiAction::Menu];
+
+const MARGIN: f32 = 4.0;
+const PADDING: f32 = 4.0;
+const FONT_SIZE: f32 = 8.0;
+const MENU_COLOR: Color = Color::rgba(0.05, 0.05, 0.15, 0.95);
+
+/// Inputs which trigger an action.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Binding {
+	pub keys: Vec<KeyCode>,
+	pub gamepad: Vec<GamepadButtonType>,
+}
+
+impl Binding {
+	fn new(keys: &[KeyCode], gamepad: &[GamepadButtonType]) -> Self {
+		Self {
+			keys: keys.to_vec(),
+			gamepad: gamepad.to_vec(),
+		}
+	}
+
+	/// Bindings used when neither the module nor the player has chosen any.
+	#[must_use]
+	pub fn default_for(action: UiAction) -> Self {
+		use GamepadButtonType as Button;
+		match action {
+			UiAction::Up => Self::new(&[KeyCode::Up, KeyCode::W], &[Button::DPadUp]),
+			UiAction::Down => Self::new(&[KeyCode::Down, KeyCode::S], &[Button::DPadDown]),
+			UiAction::Left => Self::new(&[KeyCode::Left, KeyCode::A], &[Button::DPadLeft]),
+			UiAction::Right => Self::new(&[KeyCode::Right, KeyCode::D], &[Button::DPadRight]),
+			UiAction::Select => Self::new(
+				&[KeyCode::Return, KeyCode::Space, KeyCode::Z],
+				&[Button::South],
+			),
+			UiAction::Back => Self::new(&[KeyCode::Back, KeyCode::X], &[Button::East]),
+			UiAction::Menu => Self::new(&[KeyCode::Escape], &[Button::Start]),
+			// Select is left free, since gamepads often use it to go back.
+			UiAction::Fullscreen => Self::new(&[KeyCode::F11], &[]),
+			UiAction::Screenshot => Self::new(&[KeyCode::F12], &[Button::RightThumb]),
+			UiAction::Suspend => Self::new(&[KeyCode::F5], &[]),
+			UiAction::Resume => Self::new(&[KeyCode::F9], &[]),
+		}
+	}
+
+	/// Number of slots shown for this binding in the controls menu,
+	/// including an empty one at the end for adding another input.
+	fn slots(&self) -> usize {
+		self.keys.len().max(self.gamepad.len()) + 1
+	}
+
+	fn remove(&mut self, input: InputKind) {
+		match input {
+			InputKind::Keyboard(key) => self.keys.retain(|i| *i != key),
+			InputKind::GamepadButton(button) => self.gamepad.retain(|i| *i != button),
+			_ => {}
+		}
+	}
+}
+
+/// Contents of a settings file.
+///
+/// Every field is optional, so that a file only needs to contain what it changes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+	/// Replaces the inputs of each listed action.
+	pub bindings: BTreeMap<UiAction, Binding>,
+}
+
+impl Settings {
+	/// # Errors
+	///
+	/// Fails if the file could not be read or parsed.
+	pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+		let path = path.as_ref();
+		let text = fs::read_to_string(path)
+			.with_context(|| format!("failed to read {}", path.display()))?;
+		toml::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))
+	}
+
+	/// # Errors
+	///
+	/// Fails if the file could not be written.
+	pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+		fs::write(path, toml::to_string_pretty(self)?)?;
+		Ok(())
+	}
+
+	/// Reads a settings file if it exists, logging any errors.
+	fn open_or_default(path: &Path) -> Self {
+		if !path.exists() {
+			return Self::default();
+		}
+		Self::open(path).unwrap_or_else(|msg| {
+			error!("Failed to load settings: {msg}");
+			Self::default()
+		})
+	}
+}
+
+/// The inputs bound to each action.
+///
+/// Bindings are taken from the player's settings, then the module's settings,
+/// then the engine's defaults.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Controls {
+	module: Settings,
+	/// The player's settings, which are written to `USER_SETTINGS` by the controls menu.
+	pub user: Settings,
+}
+
+impl Controls {
+	/// Reads the module's default settings and the player's settings.
+	///
+	/// Missing or broken files are skipped, so that the game always has working controls.
+	#[must_use]
+	pub fn load(module: Option<&Module>) -> Self {
+		Self {
+			module: module
+				.map(|module| Settings::open_or_default(&module.path.join(MODULE_SETTINGS)))
+				.unwrap_or_default(),
+			user: Settings::open_or_default(Path::new(USER_SETTINGS)),
+		}
+	}
+
+	#[must_use]
+	pub fn binding(&self, action: UiAction) -> Binding {
+		self.user
+			.bindings
+			.get(&action)
+			.or_else(|| self.module.bindings.get(&action))
+			.cloned()
+			.unwrap_or_else(|| Binding::default_for(action))
+	}
+
+	#[must_use]
+	pub fn input_map(&self) -> InputMap<UiAction> {
+		let mut input_map = InputMap::default();
+		for action in UiAction::variants() {
+			let binding = self.binding(action);
+			for key in binding.keys {
+				input_map.insert(key, action);
+			}
+			for button in binding.gamepad {
+				input_map.insert(button, action);
+			}
+		}
+		input_map
+	}
+
+	/// Replaces one of an action's keys or gamepad buttons with a new input,
+	/// and removes that input from every other action.
+	///
+	/// `slot` indexes the action's keys or buttons, depending on which `input` is.
+	/// The input is added after the others if that slot is empty.
+	///
+	/// Returns false, leaving the bindings unchanged, if this would leave a keyboard
+	/// with no way of opening or using the controls menu.
+	pub fn rebind(&mut self, action: UiAction, slot: usize, input: InputKind) -> bool {
+		if !matches!(input, InputKind::Keyboard(_) | InputKind::GamepadButton(_)) {
+			return false;
+		}
+		let mut bindings = Vec::new();
+		for other in UiAction::variants() {
+			let mut binding = self.binding(other);
+			if other == action {
+				match input {
+					InputKind::Keyboard(key) => place(&mut binding.keys, slot, key),
+					InputKind::GamepadButton(button) => place(&mut binding.gamepad, slot, button),
+					_ => {}
+				}
+			} else {
+				binding.remove(input);
+			}
+			let previous = self.binding(other);
+			if MENU_ACTIONS.contains(&other) && binding.keys.is_empty() && !previous.keys.is_empty()
+			{
+				return false;
+			}
+			if binding != previous {
+				bindings.push((other, binding));
+			}
+		}
+		self.user.bindings.extend(bindings);
+		true
+	}
+
+	/// Forgets the player's bindings, returning to the module's.
+	pub fn reset(&mut self) {
+		self.user.bindings.clear();
+	}
+
+	fn save(&self) {
+		if let Err(msg) = self.user.write(USER_SETTINGS) {
+			error!("Failed to save settings: {msg}");
+		}
+	}
+}
+
+/// Puts an input in a slot of a list, or at the end if that slot is empty, without repeating it.
+fn place<T: Copy + PartialEq>(list: &mut Vec<T>, slot: usize, input: T) {
+	match list.get_mut(slot) {
+		Some(existing) => *existing = input,
+		None => list.push(input),
+	}
+	let slot = slot.min(list.len() - 1);
+	let mut index = 0;
+	list.retain(|other| {
+		let keep = index == slot || *other != input;
+		index += 1;
+		keep
+	});
+}
+
+/// State of the controls menu, which lets the player rebind each action.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct ControlsMenu {
+	open: bool,
+	/// Index of the highlighted row. The row after the last action resets every binding.
+	selected: usize,
+	/// Which of the highlighted action's keys and buttons will be replaced.
+	slot: usize,
+	/// Whether the next key or button pressed will be bound to the highlighted action.
+	waiting: bool,
+}
+
+impl ControlsMenu {
+	#[must_use]
+	pub fn is_open(&self) -> bool {
+		self.open
+	}
+}
+
+/// Run condition which prevents other systems from reading input while the controls menu is open.
+#[must_use]
+pub fn menu_is_closed(menu: Option<Res<ControlsMenu>>) -> bool {
+	menu.is_none_or(|menu| !menu.is_open())
+}
+
+/// Keeps every `InputMap` in sync with the current bindings.
+fn apply_bindings(controls: Res<Controls>, mut input_maps: Query<&mut InputMap<UiAction>>) {
+	if !controls.is_changed() {
+		return;
+	}
+	for mut input_map in &mut input_maps {
+		*input_map = controls.input_map();
+	}
+}
+
+/// Opens and navigates the controls menu, and binds the next input pressed to the selected action.
+fn navigate(
+	mut menu: ResMut<ControlsMenu>,
+	mut controls: ResMut<Controls>,
+	actions: Query<&ActionState<UiAction>>,
+	keys: Res<Input<KeyCode>>,
+	buttons: Res<Input<GamepadButton>>,
+) {
+	let pressed = |action| actions.iter().any(|state| state.just_pressed(action));
+	let rows = UiAction::variants().count() + 1;
+
+	if menu.waiting {
+		// Raw input is read here, so that inputs which are not yet bound can be chosen.
+		let input = keys
+			.get_just_pressed()
+			.next()
+			.map(|key| InputKind::Keyboard(*key))
+			.or_else(|| {
+				buttons
+					.get_just_pressed()
+					.next()
+					.map(|button| InputKind::GamepadButton(button.button_type))
+			});
+		if let Some(input) = input {
+			if let Some(action) = UiAction::variants().nth(menu.selected) {
+				if controls.rebind(action, menu.slot, input) {
+					controls.save();
+				}
+			}
+			menu.waiting = false;
+		}
+		return;
+	}
+
+	if !menu.open {
+		if pressed(UiAction::Menu) {
+			menu.open = true;
+			menu.selected = 0;
+		}
+		return;
+	}
+
+	let slots = UiAction::variants()
+		.nth(menu.selected)
+		.map_or(1, |action| controls.binding(action).slots());
+	if pressed(UiAction::Menu) || pressed(UiAction::Back) {
+		menu.open = false;
+	} else if pressed(UiAction::Up) {
+		menu.selected = (menu.selected + rows - 1) % rows;
+		menu.slot = 0;
+	} else if pressed(UiAction::Down) {
+		menu.selected = (menu.selected + 1) % rows;
+		menu.slot = 0;
+	} else if pressed(UiAction::Left) {
+		menu.slot = (menu.slot + slots - 1) % slots;
+	} else if pressed(UiAction::Right) {
+		menu.slot = (menu.slot + 1) % slots;
+	} else if pressed(UiAction::Select) {
+		if menu.selected + 1 == rows {
+			controls.reset();
+			controls.save();
+		} else {
+			menu.waiting = true;
+		}
+	}
+}
+
+#[derive(Component)]
+struct MenuBox;
+
+#[derive(Component)]
+struct MenuText;
+
+fn describe(menu: &ControlsMenu, controls: &Controls) -> String {
+	let mut text = String::from("Controls");
+	for (i, action) in UiAction::variants().enumerate() {
+		let marker = if i == menu.selected { '>' } else { ' ' };
+		if menu.waiting && i == menu.selected {
+			text += &format!("\n{marker} {action:?}: press a key or button");
+			continue;
+		}
+		let binding = controls.binding(action);
+		// Brackets mark the slot which will be replaced, or the empty slot after the others.
+		let list = |mut inputs: Vec<String>| {
+			if i == menu.selected {
+				match inputs.get_mut(menu.slot) {
+					Some(input) => *input = format!("[{input}]"),
+					None => inputs.push(String::from("[ ]")),
+				}
+			}
+			inputs.join(", ")
+		};
+		text += &format!(
+			"\n{marker} {action:?}: {} | {}",
+			list(binding.keys.iter().map(|key| format!("{key:?}")).collect()),
+			list(
+				binding
+					.gamepad
+					.iter()
+					.map(|button| format!("{button:?}"))
+					.collect()
+			),
+		);
+	}
+	let marker = if menu.selected == UiAction::variants().count() {
+		'>'
+	} else {
+		' '
+	};
+	text += &format!("\n{marker} Reset to defaults");
+	text
+}
+
+/// Shows the controls menu on the pixel-perfect canvas while it is open.
+fn draw(
+	mut commands: Commands,
+	menu: Res<ControlsMenu>,
+	controls: Res<Controls>,
+	cameras: Query<(Entity, &Camera), With<CanvasCamera>>,
+	boxes: Query<Entity, With<MenuBox>>,
+	mut texts: Query<&mut Text, With<MenuText>>,
+) {
+	if !menu.open {
+		for entity in &boxes {
+			commands.entity(entity).despawn_recursive();
+		}
+		return;
+	}
+	if !boxes.is_empty() {
+		if menu.is_changed() || controls.is_changed() {
+			for mut text in &mut texts {
+				text.sections[0].value = describe(&menu, &controls);
+			}
+		}
+		return;
+	}
+
+	let Ok((camera, properties)) = cameras.get_single() else {
+		return;
+	};
+	let Some(size) = properties.logical_viewport_size() else {
+		return;
+	};
+	let menu_size = size - MARGIN * 2.0;
+
+	let menu_box = commands
+		.spawn((
+			MenuBox,
+			SpriteBundle {
+				sprite: Sprite {
+					color: MENU_COLOR,
+					custom_size: Some(menu_size),
+					anchor: Anchor::TopLeft,
+					..Default::default()
+				},
+				// In front of the dialogue box.
+				transform: Transform::from_xyz(-menu_size.x / 2.0, menu_size.y / 2.0, -5.0),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(camera).add_child(menu_box);
+
+	let text = commands
+		.spawn((
+			MenuText,
+			Text2dBundle {
+				text: Text::from_section(
+					describe(&menu, &controls),
+					TextStyle {
+						font_size: FONT_SIZE,
+						color: Color::WHITE,
+						..Default::default()
+					},
+				),
+				text_anchor: Anchor::TopLeft,
+				transform: Transform::from_xyz(PADDING, -PADDING, 1.0),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(menu_box).add_child(text);
+}
+
+/// Binds input from the settings files to `UiAction`, and adds a menu for rebinding it.
+///
+/// Bindings are read from `Controls` if it was inserted beforehand,
+/// or the engine's defaults otherwise.
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<Controls>()
+			.init_resource::<ControlsMenu>()
+			.add_systems(Update, (apply_bindings, navigate, draw).chain());
+	}
+}
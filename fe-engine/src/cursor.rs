@@ -1,7 +1,22 @@
+use crate::controls::Controls;
 use bevy::prelude::*;
 use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect)]
+#[derive(
+	Actionlike,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	Clone,
+	Copy,
+	Hash,
+	Debug,
+	Reflect,
+	Deserialize,
+	Serialize,
+)]
 pub enum UiAction {
 	Up,
 	Down,
@@ -9,6 +24,15 @@ pub enum UiAction {
 	Right,
 	Select,
 	Back,
+	/// Opens the controls menu.
+	Menu,
+	Fullscreen,
+	/// Saves the canvas to an image at its native resolution.
+	Screenshot,
+	/// Saves the chapter in progress so that it can be resumed later.
+	Suspend,
+	/// Replaces the game with the suspended chapter.
+	Resume,
 }
 
 #[derive(Component)]
@@ -20,21 +44,14 @@ pub fn rotate(time: Res<Time>, mut cursors: Query<&mut Transform, With<Cursor>>)
 	}
 }
 
-pub fn spawn(mut commands: Commands, asset_server: Res<AssetServer>) {
-	let texture = asset_server.load("example-game/cursor.png");
+pub fn spawn(mut commands: Commands, asset_server: Res<AssetServer>, controls: Res<Controls>) {
+	let texture = asset_server.load("cursor.png");
 
 	commands
 		.spawn((
 			InputManagerBundle {
 				action_state: ActionState::default(),
-				input_map: InputMap::new([
-					(KeyCode::Up, UiAction::Up),
-					(KeyCode::Down, UiAction::Down),
-					(KeyCode::Left, UiAction::Left),
-					(KeyCode::Right, UiAction::Right),
-					(KeyCode::Return, UiAction::Select),
-					(KeyCode::Space, UiAction::Select),
-				]),
+				input_map: controls.input_map(),
 			},
 			Transform::default(),
 			GlobalTransform::default(),
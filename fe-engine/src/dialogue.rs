@@ -0,0 +1,308 @@
+use crate::controls;
+use crate::cursor::UiAction;
+use crate::module::Content;
+use crate::ppcanvas::CanvasCamera;
+use crate::script::{
+	self, Command, ScriptEventFinished, ScriptEventResult, ScriptRequest, Variable,
+};
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::text::Text2dBounds;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Speed at which dialogue is printed.
+pub const CHARACTERS_PER_SECOND: f32 = 40.0;
+
+const BOX_HEIGHT: f32 = 48.0;
+/// Space between the dialogue box and the edges of the canvas.
+const MARGIN: f32 = 4.0;
+const PADDING: f32 = 4.0;
+const FONT_SIZE: f32 = 8.0;
+const BOX_COLOR: Color = Color::rgba(0.05, 0.05, 0.15, 0.9);
+const NAME_COLOR: Color = Color::rgb(1.0, 0.85, 0.4);
+
+/// Which side of the dialogue box a speaker's portrait is shown on.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Side {
+	#[default]
+	Left,
+	Right,
+}
+
+/// A line of dialogue waiting to be shown.
+#[derive(Clone, Debug)]
+struct Line {
+	handle: script::Handle,
+	speaker: Option<String>,
+	text: String,
+	side: Side,
+	portrait: Option<Handle<Image>>,
+	choices: Vec<String>,
+}
+
+/// Lines of dialogue requested by scripts, shown one at a time.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Dialogue {
+	lines: VecDeque<Line>,
+	/// Number of characters of the current line which have been printed.
+	printed: f32,
+	/// Index of the highlighted choice.
+	selected: usize,
+}
+
+impl Dialogue {
+	#[must_use]
+	pub fn is_open(&self) -> bool {
+		!self.lines.is_empty()
+	}
+
+	fn is_printed(&self) -> bool {
+		self.lines
+			.front()
+			.is_none_or(|line| self.printed as usize >= line.text.chars().count())
+	}
+
+	fn next(&mut self) {
+		self.lines.pop_front();
+		self.printed = 0.0;
+		self.selected = 0;
+	}
+}
+
+/// Run condition which prevents other systems from reading input while dialogue is shown.
+#[must_use]
+pub fn is_closed(dialogue: Res<Dialogue>) -> bool {
+	!dialogue.is_open()
+}
+
+/// The dialogue box, and the line which it is showing.
+#[derive(Component)]
+struct DialogueBox(script::Handle);
+
+#[derive(Component)]
+struct DialogueText;
+
+fn queue_lines(
+	mut requests: EventReader<ScriptRequest>,
+	mut dialogue: ResMut<Dialogue>,
+	content: Res<Content>,
+	asset_server: Res<AssetServer>,
+) {
+	for request in requests.iter() {
+		let Command::Say {
+			speaker,
+			text,
+			side,
+			choices,
+		} = &request.command
+		else {
+			continue;
+		};
+		// Speakers may be named by their unit's file or by their display name.
+		let portrait = speaker
+			.as_ref()
+			.and_then(|speaker| {
+				content
+					.units
+					.get(speaker)
+					.or_else(|| content.units.values().find(|unit| unit.name == *speaker))
+			})
+			.and_then(|unit| unit.portrait.as_ref())
+			.map(|portrait| asset_server.load(portrait.path.as_path()));
+		dialogue.lines.push_back(Line {
+			handle: request.handle,
+			speaker: speaker.clone(),
+			text: text.clone(),
+			side: *side,
+			portrait,
+			choices: choices.clone(),
+		});
+	}
+}
+
+/// Prints the current line, and moves on to the next once the player presses `Select`.
+fn advance(
+	time: Res<Time>,
+	mut dialogue: ResMut<Dialogue>,
+	actions: Query<&ActionState<UiAction>>,
+	mut finished: EventWriter<ScriptEventFinished>,
+	mut results: EventWriter<ScriptEventResult>,
+) {
+	let Some(line) = dialogue.lines.front() else {
+		return;
+	};
+	let (handle, choices) = (line.handle, line.choices.len());
+	let pressed = |action| actions.iter().any(|state| state.just_pressed(action));
+
+	if !dialogue.is_printed() {
+		dialogue.printed += time.delta_seconds() * CHARACTERS_PER_SECOND;
+		if pressed(UiAction::Select) {
+			dialogue.printed = f32::MAX;
+		}
+		return;
+	}
+
+	if choices > 0 {
+		if pressed(UiAction::Up) {
+			dialogue.selected = (dialogue.selected + choices - 1) % choices;
+		}
+		if pressed(UiAction::Down) {
+			dialogue.selected = (dialogue.selected + 1) % choices;
+		}
+	}
+	if pressed(UiAction::Select) {
+		if choices > 0 {
+			// Choices are numbered from 1, like Lua tables.
+			results.send(ScriptEventResult {
+				handle,
+				value: Variable::Number((dialogue.selected + 1) as f64),
+			});
+		} else {
+			finished.send(ScriptEventFinished(handle));
+		}
+		dialogue.next();
+	}
+}
+
+/// Keeps the dialogue box in sync with the current line.
+fn draw(
+	mut commands: Commands,
+	dialogue: Res<Dialogue>,
+	cameras: Query<(Entity, &Camera), With<CanvasCamera>>,
+	boxes: Query<(Entity, &DialogueBox)>,
+	mut texts: Query<&mut Text, With<DialogueText>>,
+) {
+	let line = dialogue.lines.front();
+	for (entity, dialogue_box) in &boxes {
+		if line.is_none_or(|line| line.handle != dialogue_box.0) {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+	let Some(line) = line else {
+		return;
+	};
+
+	let mut printed: String = line.text.chars().take(dialogue.printed as usize).collect();
+	if dialogue.is_printed() {
+		for (i, choice) in line.choices.iter().enumerate() {
+			let marker = if i == dialogue.selected { '>' } else { ' ' };
+			printed += &format!("\n{marker} {choice}");
+		}
+	}
+
+	if boxes
+		.iter()
+		.any(|(_, dialogue_box)| dialogue_box.0 == line.handle)
+	{
+		for mut text in &mut texts {
+			text.sections[0].value.clone_from(&printed);
+		}
+		return;
+	}
+
+	let Ok((camera, properties)) = cameras.get_single() else {
+		return;
+	};
+	let Some(size) = properties.logical_viewport_size() else {
+		return;
+	};
+
+	let width = size.x - MARGIN * 2.0;
+	// Positions are relative to the camera, so that the box stays in place while it pans.
+	let top = -size.y / 2.0 + MARGIN + BOX_HEIGHT;
+	let left = -width / 2.0;
+	let style = TextStyle {
+		font_size: FONT_SIZE,
+		color: Color::WHITE,
+		..Default::default()
+	};
+
+	let mut text_top = top - PADDING;
+	let dialogue_box = commands
+		.spawn((
+			DialogueBox(line.handle),
+			SpriteBundle {
+				sprite: Sprite {
+					color: BOX_COLOR,
+					custom_size: Some(Vec2::new(width, BOX_HEIGHT)),
+					anchor: Anchor::TopLeft,
+					..Default::default()
+				},
+				transform: Transform::from_xyz(left, top, -10.0),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(camera).add_child(dialogue_box);
+
+	let (anchor, x) = match line.side {
+		Side::Left => (Anchor::BottomLeft, PADDING),
+		Side::Right => (Anchor::BottomRight, width - PADDING),
+	};
+	if let Some(portrait) = &line.portrait {
+		// Portraits stand on top of the box.
+		let portrait = commands
+			.spawn(SpriteBundle {
+				sprite: Sprite {
+					anchor,
+					..Default::default()
+				},
+				texture: portrait.clone(),
+				transform: Transform::from_xyz(x, 0.0, 1.0),
+				..Default::default()
+			})
+			.id();
+		commands.entity(dialogue_box).add_child(portrait);
+	}
+	if let Some(speaker) = &line.speaker {
+		let (anchor, x) = match line.side {
+			Side::Left => (Anchor::TopLeft, PADDING),
+			Side::Right => (Anchor::TopRight, width - PADDING),
+		};
+		let name = commands
+			.spawn(Text2dBundle {
+				text: Text::from_section(
+					speaker.clone(),
+					TextStyle {
+						color: NAME_COLOR,
+						..style.clone()
+					},
+				),
+				text_anchor: anchor,
+				transform: Transform::from_xyz(x, -PADDING, 1.0),
+				..Default::default()
+			})
+			.id();
+		commands.entity(dialogue_box).add_child(name);
+		text_top -= FONT_SIZE + PADDING;
+	}
+	let text = commands
+		.spawn((
+			DialogueText,
+			Text2dBundle {
+				text: Text::from_section(printed, style),
+				text_anchor: Anchor::TopLeft,
+				text_2d_bounds: Text2dBounds {
+					size: Vec2::new(width - PADDING * 2.0, f32::INFINITY),
+				},
+				transform: Transform::from_xyz(PADDING, text_top - top, 1.0),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(dialogue_box).add_child(text);
+}
+
+/// Shows dialogue requested by scripts on the pixel-perfect canvas.
+pub struct DialoguePlugin;
+
+impl Plugin for DialoguePlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<Dialogue>().add_systems(
+			Update,
+			(queue_lines, advance.run_if(controls::menu_is_closed), draw).chain(),
+		);
+	}
+}
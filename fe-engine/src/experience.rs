@@ -0,0 +1,343 @@
+use crate::battle::resolve_battles;
+use crate::cursor::UiAction;
+use crate::module::Content;
+use crate::objectives::PLAYER;
+use crate::ppcanvas::CanvasCamera;
+use crate::rng::GameRng;
+use crate::script::dispatch_signals;
+use crate::unit::Unit;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use fe_data::{Alignment, Experience, Stats};
+use leafwing_input_manager::prelude::*;
+use std::collections::VecDeque;
+
+/// How long the level-up screen is shown for, unless it is dismissed first.
+pub const LEVEL_UP_SECONDS: f32 = 2.5;
+
+const PADDING: f32 = 4.0;
+const FONT_SIZE: f32 = 8.0;
+const BOX_WIDTH: f32 = 96.0;
+const BOX_COLOR: Color = Color::rgba(0.05, 0.05, 0.15, 0.9);
+const GAIN_COLOR: Color = Color::rgb(1.0, 0.85, 0.4);
+
+/// Gives a unit experience, which may level it up.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct GainExperience {
+	pub entity: Entity,
+	pub amount: u32,
+}
+
+/// Sent when a unit gains a level.
+#[derive(Event, Clone, Debug)]
+pub struct LevelUp {
+	pub entity: Entity,
+	pub identifier: String,
+	pub name: String,
+	pub alignment: Alignment,
+	/// The unit's new level.
+	pub level: u32,
+	/// The unit's stats once it has levelled up.
+	pub stats: Stats,
+	/// How much each stat increased by.
+	pub gains: Stats,
+}
+
+/// Adds experience to a unit, levelling it up each time it reaches the threshold.
+///
+/// Returns how much each stat increased by, for each level gained.
+/// Units at the maximum level gain no more experience.
+pub fn gain(unit: &mut Unit, amount: u32, config: &Experience, rng: &mut GameRng) -> Vec<Stats> {
+	let mut levels = Vec::new();
+	if unit.level < config.max_level {
+		unit.experience += amount;
+	}
+	while unit.experience >= config.threshold.max(1) && unit.level < config.max_level {
+		unit.experience -= config.threshold.max(1);
+		unit.level += 1;
+		let gains = roll_growths(&unit.growths, rng);
+		unit.stats = &unit.stats + &gains;
+		unit.hp += gains.hp;
+		levels.push(gains);
+	}
+	if unit.level >= config.max_level {
+		unit.experience = 0;
+	}
+	levels
+}
+
+/// Rolls each growth stat against its growth rate, returning how much each one increases.
+///
+/// A unit's growths already include those of its class.
+/// Every 100% of a rate guarantees a point, and the remainder is rolled for another.
+pub fn roll_growths(growths: &Stats, rng: &mut GameRng) -> Stats {
+	let mut roll = |rate: i32| {
+		let rate = rate.max(0);
+		rate / 100 + i32::from(rng.roll(rate % 100))
+	};
+	Stats {
+		hp: roll(growths.hp),
+		power: roll(growths.power),
+		defense: roll(growths.defense),
+		resistance: roll(growths.resistance),
+		dexterity: roll(growths.dexterity),
+		..Default::default()
+	}
+}
+
+/// Applies experience to units, rolling their growths when they level up.
+fn award_experience(
+	mut requests: EventReader<GainExperience>,
+	content: Res<Content>,
+	mut rng: ResMut<GameRng>,
+	mut units: Query<&mut Unit>,
+	mut level_ups: EventWriter<LevelUp>,
+) {
+	for GainExperience { entity, amount } in requests.iter() {
+		let Ok(mut unit) = units.get_mut(*entity) else {
+			continue;
+		};
+		for gains in gain(&mut unit, *amount, &content.module.experience, &mut rng) {
+			info!("{} reached level {}.", unit.name, unit.level);
+			level_ups.send(LevelUp {
+				entity: *entity,
+				identifier: unit.identifier.clone(),
+				name: unit.name.clone(),
+				alignment: unit.alignment,
+				level: unit.level,
+				stats: unit.stats.clone(),
+				gains,
+			});
+		}
+	}
+}
+
+/// Level-ups of the player's units, shown one at a time.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct LevelUpScreen {
+	queue: VecDeque<LevelUp>,
+	/// Seconds the current level-up has been shown for.
+	shown: f32,
+}
+
+impl LevelUpScreen {
+	#[must_use]
+	pub fn is_open(&self) -> bool {
+		!self.queue.is_empty()
+	}
+}
+
+/// Run condition which prevents units from being given orders while a level-up is shown.
+#[must_use]
+pub fn is_closed(screen: Res<LevelUpScreen>) -> bool {
+	!screen.is_open()
+}
+
+/// Queues the player's level-ups, and dismisses each once it has been read.
+fn advance(
+	mut level_ups: EventReader<LevelUp>,
+	mut screen: ResMut<LevelUpScreen>,
+	actions: Query<&ActionState<UiAction>>,
+	time: Res<Time>,
+) {
+	let open = screen.is_open();
+	screen.queue.extend(
+		level_ups
+			.iter()
+			.filter(|level_up| level_up.alignment == PLAYER)
+			.cloned(),
+	);
+	if !open {
+		return;
+	}
+	screen.shown += time.delta_seconds();
+	let dismissed = actions
+		.iter()
+		.any(|state| state.just_pressed(UiAction::Select) || state.just_pressed(UiAction::Back));
+	if dismissed || screen.shown >= LEVEL_UP_SECONDS {
+		screen.queue.pop_front();
+		screen.shown = 0.0;
+	}
+}
+
+/// The level-up box, and the unit and level it is showing.
+#[derive(Component)]
+struct LevelUpBox(Entity, u32);
+
+/// Shows the current level-up in the middle of the canvas, listing each growth stat and what it gained.
+fn draw(
+	mut commands: Commands,
+	screen: Res<LevelUpScreen>,
+	cameras: Query<(Entity, &Camera), With<CanvasCamera>>,
+	boxes: Query<(Entity, &LevelUpBox)>,
+) {
+	let current = screen.queue.front();
+	let shown = |LevelUpBox(unit, level): &LevelUpBox| {
+		current.is_some_and(|level_up| level_up.entity == *unit && level_up.level == *level)
+	};
+	for (entity, level_up_box) in &boxes {
+		if !shown(level_up_box) {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+	let Some(level_up) = current else {
+		return;
+	};
+	if boxes.iter().any(|(_, level_up_box)| shown(level_up_box)) {
+		return;
+	}
+	let Ok((camera, _)) = cameras.get_single() else {
+		return;
+	};
+
+	let rows = [
+		("HP", level_up.stats.hp, level_up.gains.hp),
+		("Pow", level_up.stats.power, level_up.gains.power),
+		("Def", level_up.stats.defense, level_up.gains.defense),
+		("Res", level_up.stats.resistance, level_up.gains.resistance),
+		("Dex", level_up.stats.dexterity, level_up.gains.dexterity),
+	];
+	let style = |color| TextStyle {
+		font_size: FONT_SIZE,
+		color,
+		..Default::default()
+	};
+	let mut sections = vec![TextSection::new(
+		format!("{} Lv {}", level_up.name, level_up.level),
+		style(Color::WHITE),
+	)];
+	for (name, value, gain) in rows {
+		sections.push(TextSection::new(
+			format!("\n{name} {value}"),
+			style(Color::WHITE),
+		));
+		if gain > 0 {
+			sections.push(TextSection::new(format!(" +{gain}"), style(GAIN_COLOR)));
+		}
+	}
+
+	let size = Vec2::new(
+		BOX_WIDTH,
+		(rows.len() + 1) as f32 * FONT_SIZE + PADDING * 2.0,
+	);
+	let level_up_box = commands
+		.spawn((
+			LevelUpBox(level_up.entity, level_up.level),
+			SpriteBundle {
+				sprite: Sprite {
+					color: BOX_COLOR,
+					custom_size: Some(size),
+					anchor: Anchor::TopLeft,
+					..Default::default()
+				},
+				// Positions are relative to the camera, so that the box stays in place while it pans.
+				transform: Transform::from_xyz(-size.x / 2.0, size.y / 2.0, -10.0),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(camera).add_child(level_up_box);
+
+	let text = commands
+		.spawn(Text2dBundle {
+			text: Text::from_sections(sections),
+			text_anchor: Anchor::TopLeft,
+			transform: Transform::from_xyz(PADDING, -PADDING, 1.0),
+			..Default::default()
+		})
+		.id();
+	commands.entity(level_up_box).add_child(text);
+}
+
+/// Levels units up as they earn experience, showing the player what their units gained.
+pub struct ExperiencePlugin;
+
+impl Plugin for ExperiencePlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<LevelUpScreen>()
+			.add_event::<GainExperience>()
+			.add_event::<LevelUp>()
+			.add_systems(
+				Update,
+				(
+					// Healing items replace the unit which used them through commands.
+					apply_deferred,
+					award_experience,
+					advance,
+					draw,
+				)
+					.chain()
+					.after(resolve_battles)
+					// Lets the last battle of a map count before the chapter's units are recorded.
+					.before(dispatch_signals),
+			);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use fe_data::Class;
+
+	fn unit(growths: Stats) -> Unit {
+		let class = Class {
+			growths,
+			..Default::default()
+		};
+		crate::testing::unit(class, Alignment::Player1)
+	}
+
+	#[test]
+	fn experience_carries_over_into_the_next_level() {
+		let config = Experience::default();
+		let mut unit = unit(Stats::default());
+		unit.experience = 90;
+
+		let levels = gain(&mut unit, 30, &config, &mut GameRng::new(0));
+		assert_eq!(levels.len(), 1);
+		assert_eq!(unit.level, 2);
+		assert_eq!(unit.experience, 20);
+
+		let levels = gain(&mut unit, 250, &config, &mut GameRng::new(0));
+		assert_eq!(levels.len(), 2);
+		assert_eq!(unit.level, 4);
+		assert_eq!(unit.experience, 70);
+	}
+
+	#[test]
+	fn units_stop_at_the_max_level() {
+		let config = Experience {
+			max_level: 3,
+			..Default::default()
+		};
+		let mut unit = unit(Stats::default());
+		unit.level = 2;
+		unit.experience = 90;
+
+		let levels = gain(&mut unit, 500, &config, &mut GameRng::new(0));
+		assert_eq!(levels.len(), 1);
+		assert_eq!(unit.level, 3);
+		assert_eq!(unit.experience, 0);
+
+		assert!(gain(&mut unit, 500, &config, &mut GameRng::new(0)).is_empty());
+		assert_eq!(unit.experience, 0);
+	}
+
+	#[test]
+	fn growths_of_100_or_more_are_guaranteed() {
+		let growths = Stats {
+			hp: 250,
+			power: 100,
+			defense: 0,
+			resistance: -50,
+			..Default::default()
+		};
+		for seed in 0..100 {
+			let gains = roll_growths(&growths, &mut GameRng::new(seed));
+			assert!((2..=3).contains(&gains.hp));
+			assert_eq!(gains.power, 1);
+			assert_eq!(gains.defense, 0);
+			assert_eq!(gains.resistance, 0);
+		}
+	}
+}
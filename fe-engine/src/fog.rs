@@ -0,0 +1,210 @@
+use crate::map::{load_map, Board, MapLoaded, Position};
+use crate::module::Content;
+use crate::turn::PHASE_ORDER;
+use crate::unit::Unit;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use fe_data::Alignment;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The side whose view of the map is shown on screen.
+pub const VIEWER: Alignment = Alignment::Player1;
+
+/// Tint applied to tiles which the viewer cannot see.
+const FOG_COLOR: Color = Color::rgb(0.45, 0.45, 0.55);
+
+/// Tiles which each side can currently see.
+///
+/// Only present while the current map has fog of war.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct FogOfWar {
+	/// Distance every unit can see, before bonuses.
+	pub vision: u32,
+	visible: HashMap<Alignment, HashSet<Position>>,
+}
+
+impl FogOfWar {
+	#[must_use]
+	pub fn new(vision: u32) -> Self {
+		Self {
+			vision,
+			visible: HashMap::new(),
+		}
+	}
+
+	/// Returns true if any unit on `alignment`'s side can see a tile.
+	#[must_use]
+	pub fn is_visible(&self, alignment: Alignment, position: Position) -> bool {
+		self.visible
+			.get(&alignment)
+			.is_some_and(|tiles| tiles.contains(&position))
+	}
+
+	/// Recalculates what each side can see.
+	///
+	/// Alignments share vision with every alignment they are not hostile to.
+	pub fn update<'a>(
+		&mut self,
+		board: &Board,
+		units: impl IntoIterator<Item = (&'a Unit, Position)>,
+	) {
+		let mut seen: HashMap<Alignment, HashSet<Position>> = HashMap::new();
+		for (unit, position) in units {
+			let range = vision(unit, board, position, self.vision);
+			seen.entry(unit.alignment)
+				.or_default()
+				.extend(sight(board, position, range));
+		}
+		self.visible = PHASE_ORDER
+			.iter()
+			.map(|alignment| {
+				let tiles = seen
+					.iter()
+					.filter(|(other, _)| !alignment.is_hostile(**other))
+					.flat_map(|(_, tiles)| tiles.iter().copied())
+					.collect();
+				(*alignment, tiles)
+			})
+			.collect();
+	}
+}
+
+/// How far a unit can see from a tile, including bonuses from its class, items, and terrain.
+#[must_use]
+pub fn vision(unit: &Unit, board: &Board, position: Position, base: u32) -> u32 {
+	let items: u32 = unit
+		.weapon
+		.iter()
+		.chain(&unit.items)
+		.map(|item| item.vision)
+		.sum();
+	let terrain = board.terrain(position).map_or(0, |terrain| terrain.vision);
+	(base + unit.class.vision + items).saturating_add_signed(terrain)
+}
+
+/// Finds every tile within `range` of `from`, without looking past tiles which block vision.
+///
+/// Vision spreads outwards one tile at a time,
+/// so a blocking tile is visible itself, but hides whatever can only be seen through it.
+#[must_use]
+pub fn sight(board: &Board, from: Position, range: u32) -> HashSet<Position> {
+	let mut seen = HashSet::from([from]);
+	let mut queue = VecDeque::from([(from, 0)]);
+
+	while let Some((position, distance)) = queue.pop_front() {
+		let blocked = position != from
+			&& board
+				.terrain(position)
+				.is_some_and(|terrain| terrain.blocks_vision);
+		if distance >= range || blocked {
+			continue;
+		}
+		for next in position.neighbours() {
+			if board.contains(next) && seen.insert(next) {
+				queue.push_back((next, distance + 1));
+			}
+		}
+	}
+
+	seen
+}
+
+/// Enables fog of war for maps which ask for it.
+fn configure(mut commands: Commands, mut loaded: EventReader<MapLoaded>, content: Res<Content>) {
+	let Some(MapLoaded(identifier)) = loaded.iter().last() else {
+		return;
+	};
+	match content
+		.maps
+		.get(identifier)
+		.and_then(|map| map.fog.as_ref())
+	{
+		Some(fog) => commands.insert_resource(FogOfWar::new(fog.vision)),
+		None => commands.remove_resource::<FogOfWar>(),
+	}
+}
+
+/// Units which have moved or changed since the last frame.
+type ChangedUnits<'w, 's> = Query<'w, 's, (), Or<(Changed<Unit>, Changed<Position>)>>;
+
+/// Recalculates vision whenever a unit moves, changes, or leaves the map.
+fn update(
+	fog: Option<ResMut<FogOfWar>>,
+	board: Option<Res<Board>>,
+	units: Query<(&Unit, &Position)>,
+	changed: ChangedUnits,
+	mut removed: RemovedComponents<Unit>,
+) {
+	let (Some(mut fog), Some(board)) = (fog, board) else {
+		return;
+	};
+	let removed = removed.iter().count() > 0;
+	if !fog.is_added() && !board.is_changed() && changed.is_empty() && !removed {
+		return;
+	}
+	fog.update(
+		&board,
+		units.iter().map(|(unit, position)| (unit, *position)),
+	);
+}
+
+/// Darkens tiles which the viewer cannot see, and hides the enemies standing on them.
+fn draw(
+	fog: Option<Res<FogOfWar>>,
+	board: Option<Res<Board>>,
+	storages: Query<&TileStorage>,
+	mut tiles: Query<&mut TileColor>,
+	mut units: Query<(&Unit, &Position, &mut Visibility)>,
+) {
+	let (Some(fog), Some(board)) = (fog, board) else {
+		return;
+	};
+	if !fog.is_changed() {
+		return;
+	}
+
+	for storage in &storages {
+		for y in 0..board.height {
+			for x in 0..board.width {
+				// Tilemaps count rows from the bottom, while maps count from the top.
+				let tile_pos = TilePos {
+					x: x as u32,
+					y: (board.height - 1 - y) as u32,
+				};
+				let Some(mut color) = storage
+					.get(&tile_pos)
+					.and_then(|entity| tiles.get_mut(entity).ok())
+				else {
+					continue;
+				};
+				color.0 = if fog.is_visible(VIEWER, Position::new(x, y)) {
+					Color::WHITE
+				} else {
+					FOG_COLOR
+				};
+			}
+		}
+	}
+
+	for (unit, position, mut visibility) in &mut units {
+		*visibility = if VIEWER.is_hostile(unit.alignment) && !fog.is_visible(VIEWER, *position) {
+			Visibility::Hidden
+		} else {
+			Visibility::Inherited
+		};
+	}
+}
+
+/// Hides parts of the map from each side on maps with fog of war.
+pub struct FogPlugin;
+
+impl Plugin for FogPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(
+			Update,
+			(configure, apply_deferred, update, draw)
+				.chain()
+				.after(load_map),
+		);
+	}
+}
@@ -0,0 +1,109 @@
+use crate::ai::Ai;
+use crate::cursor::UiAction;
+use crate::objectives::{MapFinished, Outcome};
+use crate::replay::FRAME_DURATION;
+use crate::turn::{PhaseStarted, Turn, PHASE_ORDER};
+use crate::unit::Unit;
+use bevy::app::AppExit;
+use bevy::input::InputPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::*;
+
+/// How a headless session is played, and when it should stop.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Headless {
+	/// Lets the AI control the player's units, and skips through dialogue.
+	pub autoplay: bool,
+	/// Stops once this turn has ended.
+	pub turns: Option<u32>,
+}
+
+/// Gives the AI control of every unit without it.
+fn take_control(mut commands: Commands, units: Query<Entity, (Added<Unit>, Without<Ai>)>) {
+	for entity in &units {
+		commands.entity(entity).insert(Ai::default());
+	}
+}
+
+/// Presses `Select` every other frame, so that dialogue advances and the first choice is picked.
+fn skip_dialogue(mut actions: Query<&mut ActionState<UiAction>>) {
+	for mut state in &mut actions {
+		if state.pressed(UiAction::Select) {
+			state.release(UiAction::Select);
+		} else {
+			state.press(UiAction::Select);
+		}
+	}
+}
+
+/// Exits once the turn limit is reached, once only one side remains on the map,
+/// or once the player has lost, rather than retrying the chapter forever.
+fn stop(
+	headless: Res<Headless>,
+	turn: Res<Turn>,
+	mut phases: EventReader<PhaseStarted>,
+	mut outcomes: EventReader<MapFinished>,
+	units: Query<&Unit>,
+	mut exit: EventWriter<AppExit>,
+) {
+	let over_time = phases
+		.iter()
+		.any(|phase| headless.turns.is_some_and(|turns| phase.turn > turns));
+	let lost = outcomes
+		.iter()
+		.any(|finished| finished.outcome == Outcome::Defeat);
+	let decided = !units.is_empty()
+		&& !units.iter().any(|unit| {
+			units
+				.iter()
+				.any(|other| unit.alignment.is_hostile(other.alignment))
+		});
+	if !over_time && !decided && !lost {
+		return;
+	}
+
+	info!("Finished on turn {}", turn.number);
+	for alignment in PHASE_ORDER {
+		let count = units
+			.iter()
+			.filter(|unit| unit.alignment == alignment)
+			.count();
+		if count > 0 {
+			info!("{alignment:?}: {count} units remaining");
+		}
+	}
+	exit.send(AppExit);
+}
+
+/// Runs the game without a window or renderer, as fast as possible.
+///
+/// This replaces `DefaultPlugins`, `PixelPerfectCanvas` and `TilemapPlugin`.
+/// Sprites and tiles are still spawned, but never drawn.
+pub struct HeadlessPlugin(pub Headless);
+
+impl Plugin for HeadlessPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_plugins((
+			MinimalPlugins,
+			LogPlugin::default(),
+			TransformPlugin,
+			HierarchyPlugin,
+			InputPlugin,
+			AssetPlugin::default(),
+		))
+		.add_asset::<Image>()
+		.add_asset::<TextureAtlas>()
+		// Every frame advances the game by the same amount, however quickly it runs.
+		.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_DURATION))
+		.insert_resource(self.0.clone())
+		.add_systems(Update, stop);
+
+		if self.0.autoplay {
+			app.add_systems(Update, take_control)
+				.add_systems(PreUpdate, skip_dialogue.after(InputManagerSystem::Update));
+		}
+	}
+}
@@ -0,0 +1,247 @@
+use crate::dialogue::Dialogue;
+use crate::map::{load_map, Board, MapState, RestoreMap, SavedUnitQuery, Walking};
+use crate::module::Content;
+use crate::ppcanvas::CanvasCamera;
+use crate::script::{ScriptLoadFailed, ScriptRuntime};
+use crate::turn::Turn;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::text::Text2dBounds;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often the module's files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const PADDING: f32 = 4.0;
+const FONT_SIZE: f32 = 8.0;
+const ERROR_COLOR: Color = Color::rgb(1.0, 0.4, 0.4);
+
+/// Watches the active module's files, so that changes made in the editor appear without a restart.
+#[derive(Resource, Debug)]
+pub struct HotReload {
+	timer: Timer,
+	/// Directory of the module being watched.
+	root: PathBuf,
+	/// When each file was last modified.
+	modified: HashMap<PathBuf, SystemTime>,
+	/// Files which have changed since they were last loaded, waiting for a safe point.
+	changed: BTreeSet<PathBuf>,
+	/// Errors from the most recent reload, shown on screen until the next.
+	pub errors: Vec<String>,
+}
+
+impl Default for HotReload {
+	fn default() -> Self {
+		Self {
+			timer: Timer::new(POLL_INTERVAL, TimerMode::Repeating),
+			root: PathBuf::new(),
+			modified: HashMap::new(),
+			changed: BTreeSet::new(),
+			errors: Vec::new(),
+		}
+	}
+}
+
+/// Records when every data file and script within a directory was last modified.
+fn scan(directory: &Path, modified: &mut HashMap<PathBuf, SystemTime>) {
+	let Ok(entries) = fs::read_dir(directory) else {
+		return;
+	};
+	for entry in entries.flatten() {
+		let path = entry.path();
+		if path.is_dir() {
+			scan(&path, modified);
+		} else if path
+			.extension()
+			.is_some_and(|extension| extension == "toml" || extension == "lua")
+		{
+			if let Ok(time) = entry.metadata().and_then(|metadata| metadata.modified()) {
+				modified.insert(path, time);
+			}
+		}
+	}
+}
+
+/// Finds files which have been created or modified since the last check.
+fn poll(time: Res<Time>, content: Res<Content>, mut hot_reload: ResMut<HotReload>) {
+	// Only errors are shown, so the rest of this state is changed without notifying anything.
+	let hot_reload = hot_reload.bypass_change_detection();
+	if !hot_reload.timer.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	let mut modified = HashMap::new();
+	scan(&content.module.path, &mut modified);
+	if hot_reload.root != content.module.path {
+		// Another module was loaded, so every file is already up to date.
+		hot_reload.root = content.module.path.clone();
+		hot_reload.changed.clear();
+	} else {
+		let changed: Vec<PathBuf> = modified
+			.iter()
+			.filter(|(path, time)| hot_reload.modified.get(*path) != Some(*time))
+			.map(|(path, _)| path.clone())
+			.collect();
+		hot_reload.changed.extend(changed);
+	}
+	hot_reload.modified = modified;
+}
+
+/// Re-reads changed files, and recreates the current map with them.
+///
+/// This waits until no scripts are running and nothing is moving,
+/// so that nothing refers to entities which are about to be replaced.
+/// Recreating the map also reloads every script.
+/// Units keep their current stats and items, but take on changes to their class.
+/// Changes to units, items, and where units are placed only apply the next time the map is started,
+/// since the units already on the map were created from the old versions.
+#[allow(clippy::too_many_arguments)]
+fn reload(
+	mut hot_reload: ResMut<HotReload>,
+	mut content: ResMut<Content>,
+	runtime: NonSend<ScriptRuntime>,
+	dialogue: Res<Dialogue>,
+	board: Option<Res<Board>>,
+	turn: Res<Turn>,
+	units: SavedUnitQuery,
+	walking: Query<(), With<Walking>>,
+	mut restores: EventWriter<RestoreMap>,
+) {
+	if hot_reload.changed.is_empty()
+		|| !runtime.is_idle()
+		|| dialogue.is_open()
+		|| !walking.is_empty()
+	{
+		return;
+	}
+
+	let changed = std::mem::take(&mut hot_reload.changed);
+	let old_classes = content.classes.clone();
+	let mut errors = Vec::new();
+	let mut reloaded = false;
+	for path in &changed {
+		match content.load_file(path) {
+			Ok(true) => {
+				info!("Reloaded {}", path.display());
+				reloaded = true;
+			}
+			Ok(false) => {}
+			Err(msg) => {
+				error!("Failed to reload {}: {msg:#}", path.display());
+				errors.push(format!("{msg:#}"));
+			}
+		}
+	}
+	// Scripts are checked after data files, in case a changed map now refers to another script.
+	let scripts = content.scripts();
+	for path in changed.iter().filter(|path| scripts.contains(*path)) {
+		info!("Reloaded {}", path.display());
+		reloaded = true;
+	}
+	// Files such as settings are not part of the module's content, and leave everything alone.
+	if reloaded || !errors.is_empty() {
+		hot_reload.errors = errors;
+	}
+
+	let Some(board) = board.filter(|_| reloaded) else {
+		return;
+	};
+	let mut state = MapState::capture(&board, &turn, &units);
+	for (identifier, old) in &old_classes {
+		let Some(new) = content.classes.get(identifier).filter(|new| *new != old) else {
+			continue;
+		};
+		for saved in &mut state.units {
+			if saved.unit.class_identifier.as_ref() == Some(identifier) {
+				saved.unit.class = new.clone();
+			}
+		}
+	}
+	restores.send(RestoreMap(state));
+}
+
+fn collect_script_errors(
+	mut failures: EventReader<ScriptLoadFailed>,
+	mut hot_reload: ResMut<HotReload>,
+) {
+	for failure in failures.iter() {
+		hot_reload
+			.errors
+			.push(format!("{}: {}", failure.path.display(), failure.message));
+	}
+}
+
+#[derive(Component)]
+struct ErrorOverlay;
+
+/// Shows reload errors in the corner of the canvas until the next successful reload.
+fn draw(
+	mut commands: Commands,
+	hot_reload: Res<HotReload>,
+	cameras: Query<(Entity, &Camera), With<CanvasCamera>>,
+	mut overlays: Query<(Entity, &mut Text), With<ErrorOverlay>>,
+) {
+	if !hot_reload.is_changed() {
+		return;
+	}
+	let text = hot_reload.errors.join("\n");
+	if let Ok((entity, mut overlay)) = overlays.get_single_mut() {
+		if text.is_empty() {
+			commands.entity(entity).despawn_recursive();
+		} else {
+			overlay.sections[0].value = text;
+		}
+		return;
+	}
+	if text.is_empty() {
+		return;
+	}
+
+	let Ok((camera, properties)) = cameras.get_single() else {
+		return;
+	};
+	let Some(size) = properties.logical_viewport_size() else {
+		return;
+	};
+	let overlay = commands
+		.spawn((
+			ErrorOverlay,
+			Text2dBundle {
+				text: Text::from_section(
+					text,
+					TextStyle {
+						font_size: FONT_SIZE,
+						color: ERROR_COLOR,
+						..Default::default()
+					},
+				),
+				text_anchor: Anchor::TopLeft,
+				text_2d_bounds: Text2dBounds {
+					size: Vec2::new(size.x - PADDING * 2.0, f32::INFINITY),
+				},
+				// In front of every menu.
+				transform: Transform::from_xyz(
+					-size.x / 2.0 + PADDING,
+					size.y / 2.0 - PADDING,
+					-1.0,
+				),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(camera).add_child(overlay);
+}
+
+/// Reloads the active module's data files and scripts whenever they change on disk.
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<HotReload>()
+			.add_systems(Update, (poll, reload).chain().before(load_map))
+			.add_systems(Update, (collect_script_errors, draw).chain());
+	}
+}
@@ -0,0 +1,332 @@
+use crate::controls::{self, Controls};
+use crate::cursor::UiAction;
+use crate::module::{Content, Module, ModuleError};
+use crate::ppcanvas::CanvasCamera;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::text::Text2dBounds;
+use bevy::window::PrimaryWindow;
+use bevy::winit::WinitWindows;
+use leafwing_input_manager::prelude::*;
+use winit::window::Icon;
+
+/// Title of the window while no module is loaded.
+pub const DEFAULT_TITLE: &str = "Furry Emblem Engine";
+/// Icon of the window while the loaded module has none of its own.
+const DEFAULT_ICON: &[u8] = include_bytes!("../icon.png");
+
+const ROW_HEIGHT: f32 = 18.0;
+const ICON_SIZE: f32 = 16.0;
+const PADDING: f32 = 4.0;
+const FONT_SIZE: f32 = 8.0;
+const BACKGROUND_COLOR: Color = Color::rgb(0.05, 0.05, 0.15);
+const ERROR_COLOR: Color = Color::rgb(1.0, 0.4, 0.4);
+
+/// Lists every module which was found, so that the player can choose one to play.
+///
+/// The launcher is open for as long as this resource exists.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Launcher {
+	pub modules: Vec<Module>,
+	/// Modules which could not be opened, shown beneath the others.
+	pub errors: Vec<ModuleError>,
+	selected: usize,
+	/// Why the highlighted module failed to load after it was chosen.
+	failure: Option<String>,
+}
+
+impl Launcher {
+	#[must_use]
+	pub fn new(modules: Vec<Module>, errors: Vec<ModuleError>) -> Self {
+		Self {
+			modules,
+			errors,
+			..Default::default()
+		}
+	}
+}
+
+/// Run condition which prevents other systems from reading input while the launcher is open.
+#[must_use]
+pub fn is_closed(launcher: Option<Res<Launcher>>) -> bool {
+	launcher.is_none()
+}
+
+/// Loads the highlighted module once the player presses `Select`.
+fn choose(
+	mut commands: Commands,
+	mut launcher: ResMut<Launcher>,
+	actions: Query<&ActionState<UiAction>>,
+) {
+	let pressed = |action| actions.iter().any(|state| state.just_pressed(action));
+	let count = launcher.modules.len();
+	if count == 0 {
+		return;
+	}
+
+	if pressed(UiAction::Up) {
+		launcher.selected = (launcher.selected + count - 1) % count;
+		launcher.failure = None;
+	}
+	if pressed(UiAction::Down) {
+		launcher.selected = (launcher.selected + 1) % count;
+		launcher.failure = None;
+	}
+	if pressed(UiAction::Select) {
+		let module = &launcher.modules[launcher.selected];
+		match Content::load(module) {
+			Ok(content) => {
+				info!("Loaded module: {}", module.name);
+				commands.insert_resource(Controls::load(Some(module)));
+				commands.insert_resource(content);
+				commands.remove_resource::<Launcher>();
+			}
+			Err(msg) => {
+				error!("Failed to load {}: {msg:#}", module.name);
+				launcher.failure = Some(format!("{msg:#}"));
+			}
+		}
+	}
+}
+
+#[derive(Component)]
+struct LauncherScreen;
+
+#[derive(Component)]
+struct LauncherMarker;
+
+#[derive(Component)]
+struct LauncherFailure;
+
+/// Shows the launcher on the pixel-perfect canvas, and removes it once a module is chosen.
+fn draw(
+	mut commands: Commands,
+	launcher: Option<Res<Launcher>>,
+	mut images: ResMut<Assets<Image>>,
+	cameras: Query<(Entity, &Camera), With<CanvasCamera>>,
+	screens: Query<Entity, With<LauncherScreen>>,
+	mut markers: Query<&mut Transform, With<LauncherMarker>>,
+	mut failures: Query<&mut Text, With<LauncherFailure>>,
+) {
+	let Some(launcher) = launcher else {
+		for entity in &screens {
+			commands.entity(entity).despawn_recursive();
+		}
+		return;
+	};
+	let row = |i: usize| -PADDING * 2.0 - FONT_SIZE - ROW_HEIGHT * i as f32;
+
+	if !screens.is_empty() {
+		if launcher.is_changed() {
+			for mut transform in &mut markers {
+				transform.translation.y = row(launcher.selected) - ICON_SIZE / 2.0;
+			}
+			for mut text in &mut failures {
+				text.sections[0].value = launcher.failure.clone().unwrap_or_default();
+			}
+		}
+		return;
+	}
+
+	let Ok((camera, properties)) = cameras.get_single() else {
+		return;
+	};
+	let Some(size) = properties.logical_viewport_size() else {
+		return;
+	};
+	let style = TextStyle {
+		font_size: FONT_SIZE,
+		color: Color::WHITE,
+		..Default::default()
+	};
+	let error_style = TextStyle {
+		color: ERROR_COLOR,
+		..style.clone()
+	};
+	let bounds = Text2dBounds {
+		size: Vec2::new(size.x - PADDING * 2.0, f32::INFINITY),
+	};
+
+	let screen = commands
+		.spawn((
+			LauncherScreen,
+			SpriteBundle {
+				sprite: Sprite {
+					color: BACKGROUND_COLOR,
+					custom_size: Some(size),
+					anchor: Anchor::TopLeft,
+					..Default::default()
+				},
+				transform: Transform::from_xyz(-size.x / 2.0, size.y / 2.0, -5.0),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(camera).add_child(screen);
+
+	let title = if launcher.modules.is_empty() {
+		"No modules were found"
+	} else {
+		"Choose a module"
+	};
+	let mut children = vec![commands
+		.spawn(Text2dBundle {
+			text: Text::from_section(title, style.clone()),
+			text_anchor: Anchor::TopLeft,
+			transform: Transform::from_xyz(PADDING, -PADDING, 1.0),
+			..Default::default()
+		})
+		.id()];
+	children.push(
+		commands
+			.spawn((
+				LauncherMarker,
+				Text2dBundle {
+					text: Text::from_section(">", style.clone()),
+					text_anchor: Anchor::CenterLeft,
+					transform: Transform::from_xyz(
+						PADDING,
+						row(launcher.selected) - ICON_SIZE / 2.0,
+						1.0,
+					),
+					visibility: if launcher.modules.is_empty() {
+						Visibility::Hidden
+					} else {
+						Visibility::Inherited
+					},
+					..Default::default()
+				},
+			))
+			.id(),
+	);
+
+	let icon_x = PADDING * 2.0 + FONT_SIZE;
+	for (i, module) in launcher.modules.iter().enumerate() {
+		if let Some(icon) = &module.icon {
+			let texture = images.add(Image::from_dynamic(icon.clone(), true));
+			children.push(
+				commands
+					.spawn(SpriteBundle {
+						sprite: Sprite {
+							custom_size: Some(Vec2::splat(ICON_SIZE)),
+							anchor: Anchor::TopLeft,
+							..Default::default()
+						},
+						texture,
+						transform: Transform::from_xyz(icon_x, row(i), 1.0),
+						..Default::default()
+					})
+					.id(),
+			);
+		}
+		children.push(
+			commands
+				.spawn(Text2dBundle {
+					text: Text::from_section(module.name.clone(), style.clone()),
+					text_anchor: Anchor::CenterLeft,
+					transform: Transform::from_xyz(
+						icon_x + ICON_SIZE + PADDING,
+						row(i) - ICON_SIZE / 2.0,
+						1.0,
+					),
+					..Default::default()
+				})
+				.id(),
+		);
+	}
+
+	let errors: Vec<String> = launcher
+		.errors
+		.iter()
+		.map(|error| format!("{}: {}", error.path.display(), error.message))
+		.collect();
+	children.push(
+		commands
+			.spawn(Text2dBundle {
+				text: Text::from_section(errors.join("\n"), error_style.clone()),
+				text_anchor: Anchor::TopLeft,
+				text_2d_bounds: bounds,
+				transform: Transform::from_xyz(PADDING, row(launcher.modules.len()), 1.0),
+				..Default::default()
+			})
+			.id(),
+	);
+	children.push(
+		commands
+			.spawn((
+				LauncherFailure,
+				Text2dBundle {
+					text: Text::from_section(
+						launcher.failure.clone().unwrap_or_default(),
+						error_style,
+					),
+					text_anchor: Anchor::BottomLeft,
+					text_2d_bounds: bounds,
+					transform: Transform::from_xyz(PADDING, -size.y + PADDING, 1.0),
+					..Default::default()
+				},
+			))
+			.id(),
+	);
+	commands.entity(screen).push_children(&children);
+}
+
+/// Names the window after the loaded module, and uses its icon.
+fn apply_module(
+	content: Res<Content>,
+	winit_windows: NonSend<WinitWindows>,
+	mut windows: Query<(Entity, &mut Window), With<PrimaryWindow>>,
+) {
+	if !content.is_changed() {
+		return;
+	}
+	let Ok((entity, mut window)) = windows.get_single_mut() else {
+		return;
+	};
+
+	window.title = if content.module.name.is_empty() {
+		DEFAULT_TITLE.to_string()
+	} else {
+		content.module.name.clone()
+	};
+
+	let Some(icon) = content
+		.module
+		.icon
+		.clone()
+		.or_else(|| image::load_from_memory(DEFAULT_ICON).ok())
+	else {
+		return;
+	};
+	let Some(winit_window) = winit_windows.get_window(entity) else {
+		return;
+	};
+	let icon = icon.into_rgba8();
+	let (width, height) = icon.dimensions();
+	match Icon::from_rgba(icon.into_raw(), width, height) {
+		Ok(icon) => winit_window.set_window_icon(Some(icon)),
+		Err(msg) => error!("Failed to use window icon: {msg}"),
+	}
+}
+
+/// Lets the player choose a module while `Launcher` exists,
+/// and applies the loaded module's name and icon to the window.
+///
+/// Requires a window, so this is not used when running headless.
+pub struct LauncherPlugin;
+
+impl Plugin for LauncherPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(
+			Update,
+			(
+				choose
+					.run_if(resource_exists::<Launcher>())
+					.run_if(controls::menu_is_closed),
+				apply_deferred,
+				(draw, apply_module),
+			)
+				.chain(),
+		);
+	}
+}
@@ -1,4 +1,31 @@
+pub mod action;
+pub mod ai;
+pub mod assets;
+pub mod battle;
+pub mod camera;
+pub mod campaign;
+pub mod controls;
 pub mod cursor;
+pub mod dialogue;
 pub mod direction;
+pub mod experience;
+pub mod fog;
+pub mod headless;
+pub mod hotreload;
+pub mod launcher;
+pub mod map;
 pub mod module;
+pub mod music;
+pub mod objectives;
 pub mod ppcanvas;
+pub mod replay;
+pub mod rng;
+pub mod save;
+pub mod script;
+pub mod shop;
+pub mod skills;
+#[cfg(test)]
+mod testing;
+pub mod title;
+pub mod turn;
+pub mod unit;
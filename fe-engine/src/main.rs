@@ -1,84 +1,248 @@
 use bevy::prelude::*;
 use bevy::window::WindowMode;
+use bevy::winit::WinitSettings;
 use bevy_ecs_tilemap::prelude::*;
-use fe_engine::cursor;
-use fe_engine::module;
+use fe_engine::action::{self, ActionPlugin, Selection};
+use fe_engine::ai::AiPlugin;
+use fe_engine::assets::ModuleAssetsPlugin;
+use fe_engine::battle::BattlePlugin;
+use fe_engine::camera::CameraPlugin;
+use fe_engine::campaign::CampaignPlugin;
+use fe_engine::controls::{self, Controls, ControlsPlugin};
+use fe_engine::cursor::{self, UiAction};
+use fe_engine::dialogue::{self, Dialogue, DialoguePlugin};
+use fe_engine::experience::{self, ExperiencePlugin};
+use fe_engine::fog::FogPlugin;
+use fe_engine::headless::{Headless, HeadlessPlugin};
+use fe_engine::hotreload::HotReloadPlugin;
+use fe_engine::launcher::{self, Launcher, LauncherPlugin};
+use fe_engine::map::MapPlugin;
+use fe_engine::module::{self, Content, ModuleError};
+use fe_engine::music::MusicPlugin;
+use fe_engine::objectives::ObjectivesPlugin;
 use fe_engine::ppcanvas::PixelPerfectCanvas;
+use fe_engine::replay::{Diverged, Recording, ReplayPlugin};
+use fe_engine::rng::GameRng;
+use fe_engine::save::{self, LoadGame, SaveGame, SavePlugin, Slot};
+use fe_engine::script::{ScriptPlugin, ScriptRuntime};
+use fe_engine::title::{self, TitleMenu, TitlePlugin};
+use fe_engine::turn::TurnPlugin;
 use leafwing_input_manager::prelude::*;
-use mlua::chunk;
-use mlua::prelude::*;
-
-const DEFAULT_TITLE: &str = "Furry Emblem Engine";
-const WINDOW_SIZE: UVec2 = UVec2::new(240, 160);
-
-/*
-fn set_icon(window: &PistonWindow, icon: DynamicImage) -> anyhow::Result<()> {
-	// TODO: embed the default icon into the binary (like fe-editor) so that it is always available.
-	// TODO: allow the icon to be overwritten by projects.
-	let width = icon.width();
-	let height = icon.height();
-	let icon = icon.to_rgba8().into_vec();
-	let icon = Icon::from_rgba(icon, width, height)?;
-	// So many wrapper types...
-	window.window.window.set_window_icon(Some(icon));
-	Ok(())
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Environment variable which provides a default for `--module`.
+const MODULE_VAR: &str = "FE_MODULE";
+/// Environment variable which provides a default for `--project-dir`.
+const PROJECT_DIR_VAR: &str = "FE_PROJECT_DIR";
+
+/// Options given on the command line.
+#[derive(Debug, Default)]
+struct Options {
+	/// Plays this module, without searching for others.
+	module: Option<PathBuf>,
+	/// Directory which is searched for modules.
+	project_dir: PathBuf,
+	/// Records the session's input to a file.
+	record: Option<PathBuf>,
+	/// Replays a recorded session.
+	replay: Option<PathBuf>,
+	/// Runs without a window or renderer.
+	headless: bool,
+	/// Lets the AI play every side. Only used when headless.
+	autoplay: bool,
+	/// Stops after a number of turns. Only used when headless.
+	turns: Option<u32>,
 }
 
-fn open_icon(window: &PistonWindow, path: impl AsRef<Path>) -> anyhow::Result<()> {
-	let icon = image::open(path.as_ref())?;
-	set_icon(window, icon)
+fn parse_options() -> Options {
+	let mut options = Options {
+		module: env::var_os(MODULE_VAR).map(PathBuf::from),
+		project_dir: env::var_os(PROJECT_DIR_VAR).map_or_else(|| PathBuf::from("."), PathBuf::from),
+		..Default::default()
+	};
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--module" => options.module = args.next().map(PathBuf::from),
+			"--project-dir" => {
+				if let Some(path) = args.next() {
+					options.project_dir = PathBuf::from(path);
+				}
+			}
+			"--record" => options.record = args.next().map(PathBuf::from),
+			"--replay" => options.replay = args.next().map(PathBuf::from),
+			"--headless" => options.headless = true,
+			"--autoplay" => options.autoplay = true,
+			"--turns" => options.turns = args.next().and_then(|turns| turns.parse().ok()),
+			_ => eprintln!("Unknown argument: {arg}"),
+		}
+	}
+	options
 }
-*/
 
-fn main() {
-	let modules = module::load_all();
+fn main() -> ExitCode {
+	let options = parse_options();
+	let (modules, mut errors) = match &options.module {
+		Some(path) => match module::load(path) {
+			Ok(module) => (vec![module], Vec::new()),
+			Err(msg) => (
+				Vec::new(),
+				vec![ModuleError {
+					path: path.clone(),
+					message: format!("{msg:#}"),
+				}],
+			),
+		},
+		None => module::load_all(&options.project_dir),
+	};
 
 	for module in &modules {
-		info!("Loaded module: {}", module.name);
+		info!("Found module: {}", module.name);
 	}
 
-	let primary_module = module::get_primary(&modules);
-	if let Some(primary_module) = primary_module {
-		if let Some(_icon) = &primary_module.icon {
-			//if let Err(msg) = set_icon(&window, icon.clone()) {
-			//	error!("Failed to use module icon: {msg}");
-			//}
-		}
-	}
+	// A module chosen on the command line is played even if it is not primary.
+	let chosen = if options.module.is_some() {
+		modules.first()
+	} else {
+		module::get_primary(&modules)
+	};
+	let content = chosen.and_then(|module| {
+		Content::load(module)
+			.map_err(|msg| {
+				error!("Failed to load {}: {msg:#}", module.name);
+				errors.push(ModuleError {
+					path: module.path.clone(),
+					message: format!("{msg:#}"),
+				});
+			})
+			.ok()
+	});
 
 	info!("Engine Initialized.");
 
-	App::new()
-		.add_plugins((
+	let mut app = App::new();
+	// Inserted before any plugins, which may read or replace them.
+	let launch = content.is_none();
+	app.insert_resource(GameRng::from_time())
+		.insert_resource(Controls::load(
+			content.as_ref().map(|content| &content.module),
+		))
+		.insert_resource(content.unwrap_or_default());
+
+	// Must be added before the asset server is created by `DefaultPlugins` or `HeadlessPlugin`.
+	app.add_plugins(ModuleAssetsPlugin);
+
+	if options.headless {
+		if launch {
+			eprintln!("No module was chosen to run");
+			return ExitCode::FAILURE;
+		}
+		app.add_plugins(HeadlessPlugin(Headless {
+			// Replays provide their own input.
+			autoplay: options.autoplay && options.replay.is_none(),
+			turns: options.turns,
+		}));
+	} else {
+		app.add_plugins((
 			DefaultPlugins
 				.set(WindowPlugin {
 					primary_window: Some(Window {
-						title: DEFAULT_TITLE.to_string(),
+						title: launcher::DEFAULT_TITLE.to_string(),
 						..Default::default()
 					}),
 					..Default::default()
 				})
-				.set(ImagePlugin::default_nearest())
-				.set(AssetPlugin {
-					asset_folder: String::from("../"),
-					..Default::default()
-				}),
-			PixelPerfectCanvas::<{ WINDOW_SIZE.x }, { WINDOW_SIZE.y }>,
-			InputManagerPlugin::<cursor::UiAction>::default(),
+				.set(ImagePlugin::default_nearest()),
+			PixelPerfectCanvas,
 			TilemapPlugin,
+			LauncherPlugin,
+			HotReloadPlugin,
 		))
-		.add_systems(
-			Startup,
-			(cursor::spawn, spawn_unit, startup, luau_scripting),
-		)
-		.add_systems(Update, (cursor::movement, cursor::rotate))
-		.add_systems(Update, fullscreen)
-		.run();
+		.add_systems(Update, fullscreen);
+
+		if launch {
+			app.insert_resource(Launcher::new(modules, errors));
+		}
+	}
+
+	app.add_plugins((
+		InputManagerPlugin::<UiAction>::default(),
+		ControlsPlugin,
+		MapPlugin,
+		CameraPlugin,
+		TurnPlugin,
+		(BattlePlugin, ExperiencePlugin),
+		AiPlugin,
+		ActionPlugin,
+		FogPlugin,
+		ObjectivesPlugin,
+		ScriptPlugin,
+		DialoguePlugin,
+		CampaignPlugin,
+		MusicPlugin,
+		(SavePlugin, TitlePlugin),
+	))
+	.add_systems(Startup, cursor::spawn)
+	.add_systems(
+		Update,
+		(
+			cursor::movement
+				.run_if(dialogue::is_closed)
+				.run_if(experience::is_closed)
+				.run_if(controls::menu_is_closed)
+				.run_if(launcher::is_closed)
+				.run_if(title::is_closed)
+				.run_if(action::cursor_is_free),
+			cursor::rotate,
+		),
+	)
+	.add_systems(
+		Update,
+		quick_save
+			.run_if(experience::is_closed)
+			.run_if(controls::menu_is_closed)
+			.run_if(launcher::is_closed),
+	);
+
+	let diverged = Diverged::default();
+	if let Some(path) = options.replay {
+		let module = &app.world.resource::<Content>().module;
+		match Recording::open(&path)
+			.and_then(|recording| recording.check_module(module).map(|()| recording))
+		{
+			Ok(recording) => {
+				app.add_plugins(ReplayPlugin::Replay(recording, diverged.clone()));
+			}
+			Err(msg) => {
+				eprintln!("Failed to open replay: {msg:#}");
+				return ExitCode::FAILURE;
+			}
+		}
+		// The exit code is only known once the replay has finished.
+		app.insert_resource(WinitSettings {
+			return_from_run: true,
+			..WinitSettings::game()
+		});
+	} else if let Some(path) = options.record {
+		let seed = app.world.resource::<GameRng>().seed();
+		app.add_plugins(ReplayPlugin::Record { path, seed });
+	}
+
+	app.run();
+	if diverged.get() {
+		ExitCode::FAILURE
+	} else {
+		ExitCode::SUCCESS
+	}
 }
 
-fn fullscreen(mut windows: Query<&mut Window>, keys: Res<Input<KeyCode>>) {
-	// TODO: Use an event for this.
-	if keys.just_pressed(KeyCode::F11) {
+fn fullscreen(mut windows: Query<&mut Window>, actions: Query<&ActionState<UiAction>>) {
+	if actions
+		.iter()
+		.any(|state| state.just_pressed(UiAction::Fullscreen))
+	{
 		// Borderless is th eonly fullscreen mode that works on Wayland.
 		const FULLSCREEN: WindowMode = WindowMode::BorderlessFullscreen;
 
@@ -91,150 +255,29 @@ fn fullscreen(mut windows: Query<&mut Window>, keys: Res<Input<KeyCode>>) {
 	}
 }
 
-fn spawn_unit(
+/// Suspends the game, or resumes a suspended game, without going through the title menu.
+#[allow(clippy::too_many_arguments)]
+fn quick_save(
 	mut commands: Commands,
-	asset_server: Res<AssetServer>,
-	mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+	actions: Query<&ActionState<UiAction>>,
+	content: Res<Content>,
+	dialogue: Res<Dialogue>,
+	selection: Res<Selection>,
+	runtime: NonSend<ScriptRuntime>,
+	mut saves: EventWriter<SaveGame>,
+	mut loads: EventWriter<LoadGame>,
 ) {
-	let texture_handle = asset_server.load("example-game/classes/icons/cat.png");
-	let texture_atlas =
-		TextureAtlas::from_grid(texture_handle, Vec2::new(16.0, 16.0), 6, 1, None, None);
-	let texture_atlas = texture_atlases.add(texture_atlas);
-
-	commands.spawn(SpriteSheetBundle {
-		texture_atlas,
-		sprite: TextureAtlasSprite::new(0),
-		..Default::default()
-	});
-}
-
-fn startup(
-	mut commands: Commands,
-	asset_server: Res<AssetServer>,
-	array_texture_loader: Res<ArrayTextureLoader>,
-) {
-	let texture_handle: Handle<Image> = asset_server.load("example-game/tileset.png");
-	let map_size = TilemapSize { x: 15, y: 11 };
-	let tilemap_entity = commands.spawn_empty().id();
-	let mut tile_storage = TileStorage::empty(map_size);
-
-	for x in 0..map_size.x {
-		for y in 0..map_size.y {
-			let tile_pos = TilePos { x, y };
-			let tile_entity = commands
-				.spawn(TileBundle {
-					position: tile_pos,
-					tilemap_id: TilemapId(tilemap_entity),
-					..Default::default()
-				})
-				.id();
-			tile_storage.set(&tile_pos, tile_entity);
-		}
+	let pressed = |action| actions.iter().any(|state| state.just_pressed(action));
+	// Running scripts and half-finished moves can't be saved, so only suspend between actions.
+	if pressed(UiAction::Suspend)
+		&& runtime.is_idle()
+		&& !dialogue.is_open()
+		&& selection.0.is_none()
+	{
+		saves.send(SaveGame(Slot::Suspend));
 	}
-
-	let tile_size = TilemapTileSize { x: 16.0, y: 16.0 };
-	let grid_size = tile_size.into();
-	let map_type = TilemapType::default();
-	let mut transform = get_tilemap_center_transform(&map_size, &grid_size, &map_type, 0.0);
-	transform.translation.z -= 16.0;
-
-	commands.entity(tilemap_entity).insert(TilemapBundle {
-		grid_size,
-		map_type,
-		size: map_size,
-		storage: tile_storage,
-		texture: TilemapTexture::Single(texture_handle.clone()),
-		tile_size,
-		transform,
-		..Default::default()
-	});
-
-	// Add atlas to array texture loader so it's preprocessed before we need to use it.
-	// Only used when the atlas feature is off and we are using array textures.
-	array_texture_loader.add(TilemapArrayTexture {
-		texture: TilemapTexture::Single(texture_handle),
-		tile_size,
-		..Default::default()
-	});
-}
-
-fn luau_scripting() {
-	let lua = Lua::new();
-	let globals = lua.globals();
-
-	#[derive(Copy, Clone, Debug, FromLua)]
-	enum Event {
-		Immediately,
-		WaitPrint,
-		WaitMove,
-	}
-
-	impl mlua::UserData for Event {}
-
-	globals
-		.set(
-			"Event",
-			lua.create_table_from([
-				("Immediately", Event::Immediately),
-				("WaitPrint", Event::WaitPrint),
-				("WaitMove", Event::WaitMove),
-			])
-			.unwrap(),
-		)
-		.unwrap();
-
-	globals
-		.set(
-			"say",
-			lua.create_function(move |_, s: String| {
-				println!("{s}");
-				Ok(Event::WaitPrint)
-			})
-			.unwrap(),
-		)
-		.unwrap();
-
-	globals
-		.set(
-			"move",
-			lua.create_function(move |_, (x, y): (i32, i32)| {
-				println!("- Moved by ({x}, {y})");
-				Ok(Event::WaitMove)
-			})
-			.unwrap(),
-		)
-		.unwrap();
-
-	let chunk = lua.load(chunk! {
-		local yield = coroutine.yield
-
-		local function onEvent()
-			yield(say("Hello!"))
-			say("I'm moving")
-			move(0, 2)
-			// Explicit yield type.
-			// You only need this when multiple events have been started and you only want to wait for one.
-			yield(Event.WaitMove)
-			// Alternatively, wait for both to complete:
-			yield(say("Moving again!"))
-		end
-
-		signal = {}
-		signal.interact = coroutine.create(onEvent)
-	});
-	chunk.exec().unwrap();
-	let event = globals
-		.get::<&str, mlua::Table>("signal")
-		.and_then(|t| t.get::<&str, mlua::Thread>("interact"));
-
-	if let Ok(event) = event {
-		while event.status() == LuaThreadStatus::Resumable {
-			if let Ok(Some(event)) = event.resume::<(), Option<Event>>(()) {
-				println!("Thread is yielding until: {event:?}",);
-			} else {
-				println!("Thread exited without requesting event.");
-			}
-		}
-		println!("Thread complete.");
+	if pressed(UiAction::Resume) && save::slots(&content.module).contains(&Slot::Suspend) {
+		loads.send(LoadGame(Slot::Suspend));
+		commands.remove_resource::<TitleMenu>();
 	}
 }
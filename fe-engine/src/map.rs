@@ -0,0 +1,922 @@
+use crate::action::CustomAction;
+use crate::ai::Ai;
+use crate::campaign::Campaign;
+use crate::module::Content;
+use crate::script::UnitScript;
+use crate::turn::{Acted, PhaseStarted, Turn};
+use crate::unit::Unit;
+use bevy::ecs::query::Has;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use fe_data::{Alignment, Behaviour, Class, Map, Terrain, TileEntry, Tileset, UnitPlacement};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, VecDeque};
+use std::time::Duration;
+
+/// Width and height of a single tile, in pixels.
+pub const TILE_SIZE: f32 = 16.0;
+/// Time taken for a unit to walk across a single tile.
+pub const STEP_DURATION: Duration = Duration::from_millis(100);
+
+/// Location of a unit on the map, measured in tiles from the top left corner.
+#[derive(
+	Component,
+	Copy,
+	Clone,
+	Debug,
+	Default,
+	Deserialize,
+	Eq,
+	Hash,
+	Ord,
+	PartialEq,
+	PartialOrd,
+	Serialize,
+)]
+pub struct Position {
+	pub x: i32,
+	pub y: i32,
+}
+
+impl Position {
+	#[must_use]
+	pub fn new(x: i32, y: i32) -> Self {
+		Self { x, y }
+	}
+
+	/// Number of steps between two tiles, ignoring obstacles.
+	#[must_use]
+	pub fn distance(self, other: Self) -> u32 {
+		self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
+	}
+
+	/// Returns the next tile on a straight path towards `destination`,
+	/// moving horizontally first.
+	#[must_use]
+	pub fn step_towards(self, destination: Self) -> Self {
+		if self.x != destination.x {
+			Self::new(self.x + (destination.x - self.x).signum(), self.y)
+		} else {
+			Self::new(self.x, self.y + (destination.y - self.y).signum())
+		}
+	}
+
+	#[must_use]
+	pub fn neighbours(self) -> [Self; 4] {
+		[
+			Self::new(self.x, self.y - 1),
+			Self::new(self.x, self.y + 1),
+			Self::new(self.x - 1, self.y),
+			Self::new(self.x + 1, self.y),
+		]
+	}
+}
+
+/// Terrain layout of the active map.
+///
+/// This only describes the static parts of a map;
+/// units are tracked separately using their `Position`.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Board {
+	/// Identifier of the map this board was created from.
+	pub map: String,
+	pub width: i32,
+	pub height: i32,
+	terrain: Vec<Terrain>,
+	/// Tiles which have been replaced since the map was loaded, in order.
+	pub changes: Vec<ChangeTile>,
+	/// Triggers which a player's unit has already entered.
+	pub triggered: BTreeSet<String>,
+	/// Units which have left the map by reaching an `Escape` objective.
+	pub escaped: BTreeSet<String>,
+	/// Actions which scripts have added to the unit menu.
+	pub actions: Vec<CustomAction>,
+}
+
+impl Board {
+	/// Resolves each tile of a map to its terrain.
+	///
+	/// Tiles without a terrain, or whose terrain is missing from `terrain`, are treated as clear.
+	#[must_use]
+	pub fn new(map: &Map, tileset: &Tileset, terrain: &HashMap<String, Terrain>) -> Self {
+		let width = map.tiles.cols() as i32;
+		let height = map.tiles.rows() as i32;
+		let mut tiles = Vec::with_capacity((width * height) as usize);
+
+		for y in 0..map.tiles.rows() {
+			for x in 0..map.tiles.cols() {
+				let name = map
+					.tiles
+					.get(y, x)
+					.and_then(|id| tileset.atlas.get(*id as usize))
+					.and_then(|entry| match entry {
+						TileEntry::Tile(info) => info.terrain.as_ref(),
+						TileEntry::Gap => None,
+					});
+				tiles.push(
+					name.and_then(|name| terrain.get(name))
+						.cloned()
+						.unwrap_or_default(),
+				);
+			}
+		}
+
+		Self {
+			map: String::new(),
+			width,
+			height,
+			terrain: tiles,
+			..Default::default()
+		}
+	}
+
+	/// Creates a board of clear terrain.
+	#[must_use]
+	pub fn clear(width: i32, height: i32) -> Self {
+		Self {
+			map: String::new(),
+			width,
+			height,
+			terrain: vec![Terrain::default(); (width * height).max(0) as usize],
+			..Default::default()
+		}
+	}
+
+	#[must_use]
+	pub fn contains(&self, position: Position) -> bool {
+		(0..self.width).contains(&position.x) && (0..self.height).contains(&position.y)
+	}
+
+	#[must_use]
+	pub fn terrain(&self, position: Position) -> Option<&Terrain> {
+		if self.contains(position) {
+			self.terrain
+				.get((position.y * self.width + position.x) as usize)
+		} else {
+			None
+		}
+	}
+
+	pub fn set_terrain(&mut self, position: Position, terrain: Terrain) {
+		if self.contains(position) {
+			self.terrain[(position.y * self.width + position.x) as usize] = terrain;
+		}
+	}
+
+	/// Movement points a unit of the given class needs to enter a tile, if it can at all.
+	#[must_use]
+	pub fn cost(&self, position: Position, class: &Class) -> Option<u32> {
+		let terrain = self.terrain(position)?;
+		if terrain.impassable {
+			None
+		} else if class.flying {
+			Some(1)
+		} else {
+			Some(terrain.cost.max(1))
+		}
+	}
+
+	/// Finds every tile a unit could walk to, and the cost of reaching it.
+	///
+	/// `passable` decides whether a tile may be walked through,
+	/// which is used to prevent units from moving through their enemies.
+	/// Tiles that may be walked through are not necessarily valid destinations.
+	#[must_use]
+	pub fn reachable(
+		&self,
+		from: Position,
+		movement: u32,
+		class: &Class,
+		passable: impl Fn(Position) -> bool,
+	) -> HashMap<Position, u32> {
+		let mut costs = HashMap::from([(from, 0)]);
+		let mut queue = BinaryHeap::from([Reverse((0, from))]);
+
+		while let Some(Reverse((cost, position))) = queue.pop() {
+			if costs.get(&position).is_some_and(|best| *best < cost) {
+				continue;
+			}
+			for next in position.neighbours() {
+				let Some(step) = self.cost(next, class) else {
+					continue;
+				};
+				let next_cost = cost + step;
+				if next_cost > movement || !passable(next) {
+					continue;
+				}
+				if costs.get(&next).is_none_or(|best| next_cost < *best) {
+					costs.insert(next, next_cost);
+					queue.push(Reverse((next_cost, next)));
+				}
+			}
+		}
+
+		costs
+	}
+
+	/// Finds the cheapest way for a unit to walk to `to`, given the same rules as `reachable`.
+	///
+	/// The path lists every tile entered, ending at `to`, or is `None` if `to` is out of reach.
+	#[must_use]
+	pub fn path(
+		&self,
+		from: Position,
+		to: Position,
+		movement: u32,
+		class: &Class,
+		passable: impl Fn(Position) -> bool,
+	) -> Option<Vec<Position>> {
+		let costs = self.reachable(from, movement, class, passable);
+		let mut path = Vec::new();
+		let mut current = to;
+		while current != from {
+			let cost = costs.get(&current)?;
+			let step = self.cost(current, class)?;
+			path.push(current);
+			current = current
+				.neighbours()
+				.into_iter()
+				.find(|previous| costs.get(previous).is_some_and(|best| best + step == *cost))?;
+		}
+		path.reverse();
+		Some(path)
+	}
+
+	/// Converts a tile position to the world space coordinates of its center.
+	#[must_use]
+	pub fn to_world(&self, position: Position) -> Vec2 {
+		Vec2::new(
+			(position.x as f32 - (self.width - 1) as f32 / 2.0) * TILE_SIZE,
+			((self.height - 1) as f32 / 2.0 - position.y as f32) * TILE_SIZE,
+		)
+	}
+
+	/// Converts world space coordinates to the position of the tile they fall within.
+	#[must_use]
+	pub fn tile_at(&self, world: Vec2) -> Position {
+		Position::new(
+			(world.x / TILE_SIZE + (self.width - 1) as f32 / 2.0).round() as i32,
+			((self.height - 1) as f32 / 2.0 - world.y / TILE_SIZE).round() as i32,
+		)
+	}
+}
+
+/// Replaces the current map with one from the module's content.
+#[derive(Event, Clone, Debug)]
+pub struct LoadMap(pub String);
+
+/// A unit as it was when its map was saved.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SavedUnit {
+	pub unit: Unit,
+	pub position: Position,
+	pub acted: bool,
+	/// Present if the unit is computer-controlled.
+	pub behaviour: Option<Behaviour>,
+	pub script: Option<UnitScript>,
+	/// Whether the unit is being carried, in which case `position` is its carrier's.
+	#[serde(default)]
+	pub carried: bool,
+	/// Index of the unit being carried within the map's saved units, if any.
+	#[serde(default)]
+	pub carrying: Option<usize>,
+}
+
+/// Everything needed to recreate the current map exactly as it is.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MapState {
+	pub map: String,
+	pub turn: Turn,
+	pub units: Vec<SavedUnit>,
+	pub changes: Vec<ChangeTile>,
+	pub triggered: BTreeSet<String>,
+	#[serde(default)]
+	pub escaped: BTreeSet<String>,
+	#[serde(default)]
+	pub actions: Vec<CustomAction>,
+}
+
+/// The components of each unit which are recorded by `MapState`.
+pub type SavedUnitQuery<'w, 's> = Query<
+	'w,
+	's,
+	(
+		Entity,
+		&'static Unit,
+		Option<&'static Position>,
+		Has<Acted>,
+		Option<&'static Ai>,
+		Option<&'static UnitScript>,
+	),
+>;
+
+impl MapState {
+	/// Records the current map, so that it can be recreated with `RestoreMap`.
+	#[must_use]
+	pub fn capture(board: &Board, turn: &Turn, units: &SavedUnitQuery) -> Self {
+		let mut entities = Vec::new();
+		let mut saved = Vec::new();
+		for (entity, unit, position, acted, ai, script) in units {
+			// Carried units are off the map, so they are recorded where their carrier stands.
+			let carrier = || {
+				units
+					.iter()
+					.find(|(_, carrier, ..)| carrier.carrying == Some(entity))
+					.and_then(|(_, _, position, ..)| position.copied())
+			};
+			let Some(saved_position) = position.copied().or_else(carrier) else {
+				continue;
+			};
+			entities.push(entity);
+			saved.push(SavedUnit {
+				unit: unit.clone(),
+				position: saved_position,
+				acted,
+				behaviour: ai.map(|ai| ai.0.clone()),
+				script: script.cloned(),
+				carried: position.is_none(),
+				carrying: None,
+			});
+		}
+		for saved in &mut saved {
+			saved.carrying = saved
+				.unit
+				.carrying
+				.and_then(|cargo| entities.iter().position(|entity| *entity == cargo));
+		}
+
+		Self {
+			map: board.map.clone(),
+			turn: turn.clone(),
+			units: saved,
+			changes: board.changes.clone(),
+			triggered: board.triggered.clone(),
+			escaped: board.escaped.clone(),
+			actions: board.actions.clone(),
+		}
+	}
+}
+
+/// Replaces the current map with one recreated from a `MapState`,
+/// instead of from its placement data.
+///
+/// Unlike `LoadMap`, this does not start a new phase.
+#[derive(Event, Clone, Debug)]
+pub struct RestoreMap(pub MapState);
+
+/// Sent once a map has been loaded, along with its identifier.
+#[derive(Event, Clone, Debug)]
+pub struct MapLoaded(pub String);
+
+/// Replaces a tile of the current map with another from its tileset.
+#[derive(Event, Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ChangeTile {
+	pub position: Position,
+	/// Index of the new tile within the tileset's atlas.
+	pub tile: u32,
+}
+
+/// Walks a unit to a tile one step at a time.
+#[derive(Event, Clone, Debug)]
+pub struct MoveUnit {
+	pub entity: Entity,
+	pub destination: Position,
+	/// Tiles to step onto in order, such as one found by `Board::path`.
+	/// If this is empty, the unit walks straight to its destination, ignoring terrain and other units.
+	pub path: Vec<Position>,
+}
+
+/// Sent once a unit has reached the destination of a `MoveUnit` request.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct MoveFinished {
+	pub entity: Entity,
+}
+
+/// A unit which is currently walking.
+#[derive(Component, Clone, Debug)]
+pub struct Walking {
+	pub destination: Position,
+	path: VecDeque<Position>,
+	timer: Timer,
+}
+
+/// Marks entities that belong to the current map, and are removed along with it.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct MapEntity;
+
+/// Spawns a unit's sprite, without the unit itself.
+fn spawn_sprite(
+	commands: &mut Commands,
+	asset_server: &AssetServer,
+	texture_atlases: &mut Assets<TextureAtlas>,
+	content: &Content,
+	board: &Board,
+	unit: &Unit,
+	position: Position,
+) -> Entity {
+	let icon_path = unit.class.icon.path.as_path();
+	let frames = image::image_dimensions(content.file(icon_path))
+		.map_or(1, |(width, _)| (width as usize / TILE_SIZE as usize).max(1));
+	let texture_atlas = TextureAtlas::from_grid(
+		asset_server.load(icon_path),
+		Vec2::new(TILE_SIZE, TILE_SIZE),
+		frames,
+		1,
+		None,
+		None,
+	);
+
+	commands
+		.spawn((
+			SpriteSheetBundle {
+				texture_atlas: texture_atlases.add(texture_atlas),
+				sprite: TextureAtlasSprite::new(0),
+				transform: Transform::from_translation(board.to_world(position).extend(1.0)),
+				..Default::default()
+			},
+			position,
+			MapEntity,
+		))
+		.id()
+}
+
+/// Spawns a unit described by placement data, along with its sprite.
+///
+/// # Errors
+///
+/// Fails if the placement references missing content.
+pub fn spawn_unit(
+	commands: &mut Commands,
+	asset_server: &AssetServer,
+	texture_atlases: &mut Assets<TextureAtlas>,
+	content: &Content,
+	board: &Board,
+	placement: &UnitPlacement,
+) -> anyhow::Result<Entity> {
+	let unit = Unit::from_placement(placement, content)?;
+	let position = Position::new(placement.x as i32, placement.y as i32);
+
+	let entity = spawn_sprite(
+		commands,
+		asset_server,
+		texture_atlases,
+		content,
+		board,
+		&unit,
+		position,
+	);
+	let mut entity = commands.entity(entity);
+	if placement.alignment != Alignment::Player1 {
+		entity.insert(Ai(placement.behaviour.clone()));
+	}
+	if let Some(script) = &placement.script {
+		entity.insert(UnitScript {
+			path: script.clone(),
+			triggers: placement.triggers.clone(),
+		});
+	}
+	entity.insert(unit);
+	Ok(entity.id())
+}
+
+/// Spawns a unit exactly as it was saved.
+fn spawn_saved_unit(
+	commands: &mut Commands,
+	asset_server: &AssetServer,
+	texture_atlases: &mut Assets<TextureAtlas>,
+	content: &Content,
+	board: &Board,
+	saved: &SavedUnit,
+) -> Entity {
+	let entity = spawn_sprite(
+		commands,
+		asset_server,
+		texture_atlases,
+		content,
+		board,
+		&saved.unit,
+		saved.position,
+	);
+	let mut entity = commands.entity(entity);
+	if let Some(behaviour) = &saved.behaviour {
+		entity.insert(Ai(behaviour.clone()));
+	}
+	if let Some(script) = &saved.script {
+		entity.insert(script.clone());
+	}
+	if saved.acted {
+		entity.insert(Acted);
+	}
+	if saved.carried {
+		entity.remove::<Position>().insert(Visibility::Hidden);
+	}
+	// Cargo is linked up by the caller, since the entity it was saved with is gone.
+	let mut unit = saved.unit.clone();
+	unit.carrying = None;
+	entity.insert(unit).id()
+}
+
+/// Spawns a map's units at the start of a chapter.
+///
+/// The player's units which are in the campaign's roster are carried over instead of created anew,
+/// and those which have fallen are left out.
+/// Roster units which the map does not place fill the chapter's deployment slots, in order.
+fn place_units(
+	commands: &mut Commands,
+	asset_server: &AssetServer,
+	texture_atlases: &mut Assets<TextureAtlas>,
+	content: &Content,
+	board: &Board,
+	map: &Map,
+	campaign: &Campaign,
+) {
+	let mut placed = BTreeSet::new();
+	for placement in &map.units {
+		let player = placement.alignment == Alignment::Player1;
+		placed.insert(placement.identifier.clone());
+		if player && campaign.fallen.contains(&placement.identifier) {
+			continue;
+		}
+		if let Some(mut unit) = campaign.deploy(&placement.identifier).filter(|_| player) {
+			// Leaders are chosen by each map, rather than the chapter a unit joined in.
+			unit.leader = placement.leader;
+			let saved = SavedUnit {
+				unit,
+				position: Position::new(placement.x as i32, placement.y as i32),
+				acted: false,
+				behaviour: None,
+				script: placement.script.as_ref().map(|path| UnitScript {
+					path: path.clone(),
+					triggers: placement.triggers.clone(),
+				}),
+				carried: false,
+				carrying: None,
+			};
+			spawn_saved_unit(
+				commands,
+				asset_server,
+				texture_atlases,
+				content,
+				board,
+				&saved,
+			);
+		} else if let Err(msg) = spawn_unit(
+			commands,
+			asset_server,
+			texture_atlases,
+			content,
+			board,
+			placement,
+		) {
+			error!("Failed to place {}: {msg}", placement.identifier);
+		}
+	}
+
+	let Some(chapter) = campaign.current(content, &board.map) else {
+		return;
+	};
+	let occupied: BTreeSet<Position> = map
+		.units
+		.iter()
+		.map(|placement| Position::new(placement.x as i32, placement.y as i32))
+		.collect();
+	let slots = chapter
+		.deployment
+		.iter()
+		.map(|slot| Position::new(slot.x as i32, slot.y as i32))
+		.filter(|position| !occupied.contains(position));
+	let benched = campaign
+		.roster
+		.keys()
+		.filter(|identifier| !placed.contains(*identifier))
+		.filter_map(|identifier| campaign.deploy(identifier));
+	for (mut unit, position) in benched.zip(slots) {
+		unit.leader = false;
+		let saved = SavedUnit {
+			unit,
+			position,
+			acted: false,
+			behaviour: None,
+			script: None,
+			carried: false,
+			carrying: None,
+		};
+		spawn_saved_unit(
+			commands,
+			asset_server,
+			texture_atlases,
+			content,
+			board,
+			&saved,
+		);
+	}
+}
+
+/// Replaces the current map whenever `LoadMap` or `RestoreMap` is sent.
+#[allow(clippy::too_many_arguments)]
+pub fn load_map(
+	mut commands: Commands,
+	mut requests: EventReader<LoadMap>,
+	mut restores: EventReader<RestoreMap>,
+	content: Res<Content>,
+	asset_server: Res<AssetServer>,
+	// Missing when running headless.
+	array_texture_loader: Option<Res<ArrayTextureLoader>>,
+	mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+	existing: Query<Entity, With<MapEntity>>,
+	campaign: Option<Res<Campaign>>,
+	mut turn: ResMut<Turn>,
+	mut loaded: EventWriter<MapLoaded>,
+	mut phases: EventWriter<PhaseStarted>,
+	mut changes: EventWriter<ChangeTile>,
+) {
+	let (identifier, restore) = match (requests.iter().last(), restores.iter().last()) {
+		(_, Some(RestoreMap(state))) => (&state.map, Some(state)),
+		(Some(LoadMap(identifier)), None) => (identifier, None),
+		(None, None) => return,
+	};
+	let Some(map) = content.maps.get(identifier) else {
+		error!("Unknown map: {identifier}");
+		return;
+	};
+	let Some(tileset) = content.tilesets.get(&map.tileset.identifier) else {
+		error!("Unknown tileset: {}", map.tileset.identifier);
+		return;
+	};
+
+	for entity in &existing {
+		commands.entity(entity).despawn_recursive();
+	}
+
+	let mut board = Board::new(map, tileset, &content.module.terrain);
+	board.map = identifier.clone();
+
+	let texture_path = tileset.texture.path.as_path();
+	let columns = image::image_dimensions(content.file(texture_path))
+		.map_or(1, |(width, _)| (width / tileset.tile_width.max(1)).max(1));
+	let texture_handle: Handle<Image> = asset_server.load(texture_path);
+	let map_size = TilemapSize {
+		x: board.width as u32,
+		y: board.height as u32,
+	};
+	let tilemap_entity = commands.spawn(MapEntity).id();
+	let mut tile_storage = TileStorage::empty(map_size);
+
+	for y in 0..map.tiles.rows() {
+		for x in 0..map.tiles.cols() {
+			let Some(TileEntry::Tile(info)) = map
+				.tiles
+				.get(y, x)
+				.and_then(|id| tileset.atlas.get(*id as usize))
+			else {
+				continue;
+			};
+			// Tilemaps count rows from the bottom, while maps count from the top.
+			let tile_pos = TilePos {
+				x: x as u32,
+				y: map_size.y - 1 - y as u32,
+			};
+			let tile_entity = commands
+				.spawn(TileBundle {
+					position: tile_pos,
+					tilemap_id: TilemapId(tilemap_entity),
+					texture_index: TileTextureIndex(info.y * columns + info.x),
+					..Default::default()
+				})
+				.id();
+			commands.entity(tilemap_entity).add_child(tile_entity);
+			tile_storage.set(&tile_pos, tile_entity);
+		}
+	}
+
+	let tile_size = TilemapTileSize {
+		x: TILE_SIZE,
+		y: TILE_SIZE,
+	};
+	let origin = board.to_world(Position::new(0, board.height - 1));
+
+	commands.entity(tilemap_entity).insert(TilemapBundle {
+		grid_size: tile_size.into(),
+		map_type: TilemapType::default(),
+		size: map_size,
+		storage: tile_storage,
+		texture: TilemapTexture::Single(texture_handle.clone()),
+		tile_size,
+		transform: Transform::from_translation(origin.extend(-16.0)),
+		..Default::default()
+	});
+
+	// Add atlas to array texture loader so it's preprocessed before we need to use it.
+	// Only used when the atlas feature is off and we are using array textures.
+	if let Some(array_texture_loader) = array_texture_loader {
+		array_texture_loader.add(TilemapArrayTexture {
+			texture: TilemapTexture::Single(texture_handle),
+			tile_size,
+			..Default::default()
+		});
+	}
+
+	if let Some(state) = restore {
+		let entities: Vec<Entity> = state
+			.units
+			.iter()
+			.map(|saved| {
+				spawn_saved_unit(
+					&mut commands,
+					&asset_server,
+					&mut texture_atlases,
+					&content,
+					&board,
+					saved,
+				)
+			})
+			.collect();
+		// Carriers refer to their cargo's entity, which only exists once every unit has been spawned.
+		for (saved, entity) in state.units.iter().zip(&entities) {
+			if let Some(cargo) = saved.carrying.and_then(|index| entities.get(index)) {
+				let mut unit = saved.unit.clone();
+				unit.carrying = Some(*cargo);
+				commands.entity(*entity).insert(unit);
+			}
+		}
+		board.triggered = state.triggered.clone();
+		board.escaped = state.escaped.clone();
+		board.actions = state.actions.clone();
+		// Replayed once the board exists, so that they are recorded again.
+		changes.send_batch(state.changes.iter().copied());
+		*turn = state.turn.clone();
+	} else {
+		place_units(
+			&mut commands,
+			&asset_server,
+			&mut texture_atlases,
+			&content,
+			&board,
+			map,
+			campaign.as_deref().unwrap_or(&Campaign::default()),
+		);
+		*turn = Turn::default();
+		phases.send(PhaseStarted {
+			turn: turn.number,
+			phase: turn.phase,
+		});
+	}
+
+	info!("Loaded map: {}", map.name);
+	commands.insert_resource(board);
+	loaded.send(MapLoaded(identifier.clone()));
+}
+
+fn change_tiles(
+	mut commands: Commands,
+	mut requests: EventReader<ChangeTile>,
+	content: Res<Content>,
+	board: Option<ResMut<Board>>,
+	mut tilemaps: Query<(Entity, &mut TileStorage)>,
+	mut tiles: Query<&mut TileTextureIndex>,
+) {
+	let Some(mut board) = board else {
+		return;
+	};
+	let Some(tileset) = content
+		.maps
+		.get(&board.map)
+		.and_then(|map| content.tilesets.get(&map.tileset.identifier))
+	else {
+		return;
+	};
+	let columns = image::image_dimensions(content.file(&tileset.texture.path))
+		.map_or(1, |(width, _)| (width / tileset.tile_width.max(1)).max(1));
+
+	for ChangeTile { position, tile } in requests.iter() {
+		let Some(TileEntry::Tile(info)) = tileset.atlas.get(*tile as usize) else {
+			warn!("No tile at index {tile} in {}", tileset.name);
+			continue;
+		};
+		if !board.contains(*position) {
+			continue;
+		}
+		let terrain = info
+			.terrain
+			.as_ref()
+			.and_then(|name| content.module.terrain.get(name))
+			.cloned()
+			.unwrap_or_default();
+		board.set_terrain(*position, terrain);
+		board.changes.push(ChangeTile {
+			position: *position,
+			tile: *tile,
+		});
+
+		let Ok((tilemap_entity, mut storage)) = tilemaps.get_single_mut() else {
+			continue;
+		};
+		let tile_pos = TilePos {
+			x: position.x as u32,
+			y: (board.height - 1 - position.y) as u32,
+		};
+		let texture_index = TileTextureIndex(info.y * columns + info.x);
+		if let Some(mut index) = storage
+			.get(&tile_pos)
+			.and_then(|entity| tiles.get_mut(entity).ok())
+		{
+			*index = texture_index;
+		} else {
+			let tile_entity = commands
+				.spawn(TileBundle {
+					position: tile_pos,
+					tilemap_id: TilemapId(tilemap_entity),
+					texture_index,
+					..Default::default()
+				})
+				.id();
+			commands.entity(tilemap_entity).add_child(tile_entity);
+			storage.set(&tile_pos, tile_entity);
+		}
+	}
+}
+
+fn start_walking(
+	mut commands: Commands,
+	mut requests: EventReader<MoveUnit>,
+	mut finished: EventWriter<MoveFinished>,
+) {
+	for request in requests.iter() {
+		if let Some(mut entity) = commands.get_entity(request.entity) {
+			entity.insert(Walking {
+				destination: request.destination,
+				path: request.path.iter().copied().collect(),
+				timer: Timer::new(STEP_DURATION, TimerMode::Repeating),
+			});
+		} else {
+			// Don't leave anyone waiting on a unit that no longer exists.
+			finished.send(MoveFinished {
+				entity: request.entity,
+			});
+		}
+	}
+}
+
+fn walk(
+	mut commands: Commands,
+	time: Res<Time>,
+	mut walkers: Query<(Entity, &mut Position, &mut Walking)>,
+	mut finished: EventWriter<MoveFinished>,
+) {
+	for (entity, mut position, mut walking) in &mut walkers {
+		if *position == walking.destination {
+			commands.entity(entity).remove::<Walking>();
+			finished.send(MoveFinished { entity });
+			continue;
+		}
+		for _ in 0..walking.timer.tick(time.delta()).times_finished_this_tick() {
+			*position = walking
+				.path
+				.pop_front()
+				.unwrap_or_else(|| position.step_towards(walking.destination));
+		}
+	}
+}
+
+/// Moves entities to match their `Position` on the board.
+pub fn sync_positions(
+	board: Option<Res<Board>>,
+	mut positions: Query<(&Position, &mut Transform), Changed<Position>>,
+) {
+	let Some(board) = board else {
+		return;
+	};
+	for (position, mut transform) in &mut positions {
+		let world = board.to_world(*position);
+		transform.translation.x = world.x;
+		transform.translation.y = world.y;
+	}
+}
+
+/// Loads maps and keeps units aligned to the grid.
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_event::<LoadMap>()
+			.add_event::<RestoreMap>()
+			.add_event::<MapLoaded>()
+			.add_event::<ChangeTile>()
+			.add_event::<MoveUnit>()
+			.add_event::<MoveFinished>()
+			.add_systems(
+				Update,
+				(
+					load_map,
+					// Tiles restored by `load_map` need its board to exist.
+					apply_deferred,
+					change_tiles,
+					start_walking,
+					walk,
+					sync_positions,
+				)
+					.chain(),
+			);
+	}
+}
@@ -1,57 +1,307 @@
+use anyhow::{anyhow, Context};
 use bevy::log::*;
+use bevy::prelude::Resource;
+use fe_data::containers::Image;
+use fe_data::{Chapter, Class, Item, Map, MapReference, Tileset, Unit};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub use fe_data::Module;
 
-fn try_load_module(path: PathBuf) -> anyhow::Result<Option<Module>> {
+/// Directories within a module which contain data files, named after their type.
+pub const DIRECTORIES: [&str; 6] = ["chapters", "classes", "items", "maps", "tilesets", "units"];
+
+/// Every data file within a module, indexed by identifier.
+///
+/// A file's identifier is its name without any extensions,
+/// so `classes/Cat.class.toml` is referred to as `Cat`.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Content {
+	pub module: Module,
+	pub chapters: BTreeMap<String, Chapter>,
+	pub classes: BTreeMap<String, Class>,
+	pub items: BTreeMap<String, Item>,
+	pub maps: BTreeMap<String, Map>,
+	pub tilesets: BTreeMap<String, Tileset>,
+	pub units: BTreeMap<String, Unit>,
+}
+
+impl Content {
+	/// Reads all data files within a module's directories.
+	///
+	/// # Errors
+	///
+	/// Fails if any file could not be opened or parsed.
+	pub fn load(module: &Module) -> anyhow::Result<Self> {
+		let mut content = Self {
+			module: module.clone(),
+			..Default::default()
+		};
+
+		for directory in DIRECTORIES {
+			if let Ok(entries) = fs::read_dir(module.path.join(directory)) {
+				for entry in entries {
+					content.load_file(&entry?.path())?;
+				}
+			}
+		}
+		Ok(content)
+	}
+
+	/// Reads a single data file, replacing any previous version of it.
+	///
+	/// Returns `false` if the path is not one of the module's data files.
+	///
+	/// # Errors
+	///
+	/// Fails if the file could not be opened or parsed.
+	pub fn load_file(&mut self, path: &Path) -> anyhow::Result<bool> {
+		let Some(identifier) = identifier(path) else {
+			return Ok(false);
+		};
+		let module = self.module.path.as_path();
+		let directory = path
+			.parent()
+			.and_then(|parent| parent.strip_prefix(module).ok())
+			.and_then(Path::to_str);
+		match directory {
+			Some("chapters") => {
+				self.chapters.insert(identifier, parse(module, path)?);
+			}
+			Some("classes") => {
+				self.classes.insert(identifier, parse(module, path)?);
+			}
+			Some("items") => {
+				self.items.insert(identifier, parse(module, path)?);
+			}
+			Some("maps") => {
+				self.maps.insert(identifier, parse(module, path)?);
+			}
+			Some("tilesets") => {
+				self.tilesets.insert(identifier, parse(module, path)?);
+			}
+			Some("units") => {
+				self.units.insert(identifier, parse(module, path)?);
+			}
+			_ => return Ok(false),
+		}
+		Ok(true)
+	}
+
+	/// Identifiers of the chapters which make up the campaign, in the order they are played.
+	///
+	/// Modules without a campaign play each of their chapters in order,
+	/// or each of their maps if they have no chapters either.
+	#[must_use]
+	pub fn campaign(&self) -> Vec<String> {
+		if !self.module.campaign.is_empty() {
+			self.module
+				.campaign
+				.iter()
+				.map(|chapter| chapter.identifier.clone())
+				.collect()
+		} else if !self.chapters.is_empty() {
+			self.chapters.keys().cloned().collect()
+		} else {
+			self.maps.keys().cloned().collect()
+		}
+	}
+
+	/// Returns a chapter by identifier.
+	///
+	/// Maps can be played as chapters of their own,
+	/// in which case the chapter shares the map's name and identifier.
+	#[must_use]
+	pub fn chapter(&self, identifier: &str) -> Option<Chapter> {
+		if let Some(chapter) = self.chapters.get(identifier) {
+			return Some(chapter.clone());
+		}
+		self.maps.get(identifier).map(|map| Chapter {
+			name: map.name.clone(),
+			map: MapReference {
+				identifier: identifier.to_string(),
+			},
+			..Default::default()
+		})
+	}
+
+	/// Paths of every script which the module's maps and chapters refer to.
+	#[must_use]
+	pub fn scripts(&self) -> BTreeSet<PathBuf> {
+		let maps = self.maps.values().flat_map(|map| {
+			map.script
+				.iter()
+				.chain(map.units.iter().filter_map(|unit| unit.script.as_ref()))
+				.map(|script| self.path("maps", script))
+		});
+		let chapters = self.chapters.values().flat_map(|chapter| {
+			chapter
+				.pre_script
+				.iter()
+				.chain(&chapter.post_script)
+				.map(|script| self.path("chapters", script))
+		});
+		maps.chain(chapters).collect()
+	}
+
+	/// Resolves a path from a data file, relative to the directory of its type.
+	#[must_use]
+	pub fn path(&self, directory: &str, path: impl AsRef<Path>) -> PathBuf {
+		self.module.path.join(directory).join(path)
+	}
+
+	/// Resolves a path relative to the module's directory, such as an image's.
+	#[must_use]
+	pub fn file(&self, path: impl AsRef<Path>) -> PathBuf {
+		self.module.path.join(path)
+	}
+}
+
+/// Data files which refer to images.
+///
+/// Images are written relative to the file which declares them,
+/// and are rewritten to be relative to the module once the file is loaded,
+/// which is where the asset server looks for them.
+trait DataFile {
+	fn images(&mut self) -> Vec<&mut Image>;
+}
+
+impl DataFile for Chapter {
+	fn images(&mut self) -> Vec<&mut Image> {
+		Vec::new()
+	}
+}
+
+impl DataFile for Class {
+	fn images(&mut self) -> Vec<&mut Image> {
+		vec![&mut self.icon]
+	}
+}
+
+impl DataFile for Item {
+	fn images(&mut self) -> Vec<&mut Image> {
+		vec![&mut self.icon]
+	}
+}
+
+impl DataFile for Map {
+	fn images(&mut self) -> Vec<&mut Image> {
+		Vec::new()
+	}
+}
+
+impl DataFile for Tileset {
+	fn images(&mut self) -> Vec<&mut Image> {
+		vec![&mut self.texture]
+	}
+}
+
+impl DataFile for Unit {
+	fn images(&mut self) -> Vec<&mut Image> {
+		self.portrait.iter_mut().collect()
+	}
+}
+
+/// Reads a data file, and resolves its images relative to the module's directory.
+fn parse<T: DataFile + DeserializeOwned>(module: &Path, path: &Path) -> anyhow::Result<T> {
+	let text = fs::read_to_string(path)?;
+	let mut value: T =
+		toml::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))?;
+	let directory = path
+		.parent()
+		.and_then(|parent| parent.strip_prefix(module).ok())
+		.unwrap_or(Path::new(""));
+	for image in value.images() {
+		image.path = directory.join(&image.path);
+	}
+	Ok(value)
+}
+
+/// Returns the identifier of a data file, or `None` if the path is not a data file.
+fn identifier(path: &Path) -> Option<String> {
+	if path.is_dir() || path.extension()? != "toml" {
+		return None;
+	}
+	let name = path.file_name()?.to_str()?;
+	name.split('.').next().map(String::from)
+}
+
+/// A directory which looked like a module, but could not be opened.
+#[derive(Clone, Debug)]
+pub struct ModuleError {
+	pub path: PathBuf,
+	pub message: String,
+}
+
+/// Opens the module in a directory, or returns `None` if the directory is not a module.
+///
+/// # Errors
+///
+/// Fails if the module's `fe-project.toml` or icon could not be opened or parsed.
+pub fn try_load_module(path: PathBuf) -> anyhow::Result<Option<Module>> {
 	let info = path.join("fe-project.toml");
 	if info.exists() {
-		let mut project = toml::from_str::<Module>(&fs::read_to_string(&info)?)?;
-		project.populate(path);
+		let text = fs::read_to_string(&info)
+			.with_context(|| format!("failed to read {}", info.display()))?;
+		let mut project = toml::from_str::<Module>(&text)
+			.with_context(|| format!("failed to parse {}", info.display()))?;
+		project.populate(path)?;
 		Ok(Some(project))
 	} else {
 		Ok(None)
 	}
 }
 
-/// Loads all modules found the current working directory (not recursive).
+/// Opens a single module, such as one chosen on the command line.
 ///
 /// # Errors
 ///
-/// Fails if the module could not be opened or parsed.
+/// Fails if the directory is not a module, or the module could not be opened.
+pub fn load(path: impl Into<PathBuf>) -> anyhow::Result<Module> {
+	let path = path.into();
+	try_load_module(path.clone())?
+		.ok_or_else(|| anyhow!("{} does not contain a fe-project.toml", path.display()))
+}
+
+/// Loads all modules found in a directory (not recursive).
+///
+/// Modules which could not be opened are returned alongside those which could,
+/// so that they can be reported to the player.
 #[must_use]
-pub fn load_all() -> Vec<Module> {
+pub fn load_all(directory: impl AsRef<Path>) -> (Vec<Module>, Vec<ModuleError>) {
 	let mut projects = Vec::new();
+	let mut errors = Vec::new();
 
-	if let Ok(mut dir) = fs::read_dir(".") {
+	if let Ok(mut dir) = fs::read_dir(directory) {
 		while let Some(Ok(entry)) = dir.next() {
 			match try_load_module(entry.path()) {
 				Ok(Some(module)) => projects.push(module),
 				Ok(None) => {}
-				Err(msg) => error!("Failed to open {}: {msg}", entry.path().display()),
+				Err(msg) => {
+					error!("Failed to open {}: {msg:#}", entry.path().display());
+					errors.push(ModuleError {
+						path: entry.path(),
+						message: format!("{msg:#}"),
+					});
+				}
 			}
 		}
 	}
 
-	projects
+	projects.sort_by(|a, b| a.name.cmp(&b.name));
+	(projects, errors)
 }
 
+/// Returns the module marked as primary, if there is exactly one.
 #[must_use]
-pub fn get_primary<'a>(modules: &'a [Module]) -> Option<&'a Module> {
-	let mut result: Option<&'a Module> = None;
-	for i in modules {
-		if i.primary {
-			if let Some(result) = result {
-				warn!(
-					"Multiple primary modules are loaded. Defaulting to {}",
-					result.name
-				);
-				// Stop looking after at least one is found; there's no point in spamming the log.
-				break;
-			}
-			result = Some(i);
-		}
+pub fn get_primary(modules: &[Module]) -> Option<&Module> {
+	let mut primary = modules.iter().filter(|module| module.primary);
+	let result = primary.next();
+	if result.is_some() && primary.next().is_some() {
+		warn!("Multiple primary modules are loaded, so none will be chosen automatically");
+		return None;
 	}
 	result
 }
@@ -0,0 +1,61 @@
+use crate::campaign::Campaign;
+use crate::map::{load_map, MapLoaded};
+use crate::module::Content;
+use bevy::prelude::*;
+use std::path::Path;
+
+/// Marks the entity playing the current chapter's music.
+#[derive(Component, Clone, Debug)]
+struct Music(String);
+
+/// Switches to each chapter's music as its map is loaded.
+///
+/// Music which is already playing carries on uninterrupted,
+/// so consecutive chapters may share a track.
+fn play(
+	mut commands: Commands,
+	mut loaded: EventReader<MapLoaded>,
+	asset_server: Res<AssetServer>,
+	content: Res<Content>,
+	campaign: Res<Campaign>,
+	playing: Query<(Entity, &Music)>,
+) {
+	let Some(MapLoaded(identifier)) = loaded.iter().last() else {
+		return;
+	};
+	let music = campaign
+		.current(&content, identifier)
+		.and_then(|chapter| chapter.music);
+	if playing
+		.iter()
+		.any(|(_, playing)| Some(&playing.0) == music.as_ref())
+	{
+		return;
+	}
+	for (entity, _) in &playing {
+		commands.entity(entity).despawn();
+	}
+	if let Some(music) = music {
+		commands.spawn((
+			AudioBundle {
+				source: asset_server.load(Path::new("chapters").join(&music)),
+				settings: PlaybackSettings::LOOP,
+			},
+			Music(music),
+		));
+	}
+}
+
+/// Plays music during chapters.
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(
+			Update,
+			play.after(load_map)
+				// Missing when running headless.
+				.run_if(resource_exists::<Assets<AudioSource>>()),
+		);
+	}
+}
@@ -0,0 +1,328 @@
+use crate::battle::{resolve_battles, UnitDefeated};
+use crate::campaign::Campaign;
+use crate::map::{load_map, Board, MapLoaded, Position};
+use crate::module::Content;
+use crate::script::UnitRemoved;
+use crate::turn::{PhaseStarted, Turn};
+use crate::unit::Unit;
+use bevy::prelude::*;
+use fe_data::{Alignment, DefeatCondition, Objective};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// The alignment whose objectives are checked.
+pub const PLAYER: Alignment = Alignment::Player1;
+
+/// Whether a map was won or lost.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Outcome {
+	Victory,
+	Defeat,
+}
+
+/// Sent once the current map has been won or lost.
+#[derive(Event, Clone, Debug)]
+pub struct MapFinished {
+	pub outcome: Outcome,
+	/// Name of the objective which was met, or of the condition which lost the map.
+	pub reason: String,
+}
+
+/// Sent when one of the player's units leaves the map through an `Escape` objective.
+#[derive(Event, Clone, Debug)]
+pub struct UnitEscaped {
+	pub entity: Entity,
+	/// The unit as it was when it escaped.
+	pub unit: Unit,
+}
+
+/// How the current map ended, if it has.
+///
+/// Objectives are no longer checked once this is set.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct MapOutcome(pub Option<Outcome>);
+
+/// The state of the map which objectives are checked against.
+pub struct Situation<'a> {
+	pub turn: u32,
+	/// Every unit still on the map.
+	pub units: Vec<(&'a Unit, Position)>,
+	/// Units being carried, which are off the map but still in play.
+	pub carried: Vec<&'a Unit>,
+	pub escaped: &'a BTreeSet<String>,
+	/// Whether the player has any leaders on this map, including ones which have escaped or fallen.
+	pub has_leaders: bool,
+	/// Units defeated since the last check.
+	pub defeated: &'a [&'a UnitDefeated],
+}
+
+fn tile(x: u32, y: u32) -> Position {
+	Position::new(x as i32, y as i32)
+}
+
+impl Situation<'_> {
+	/// Whether the unit is one of the player's leaders,
+	/// or one of the player's units on a map without leaders.
+	fn is_leader(&self, unit: &Unit) -> bool {
+		unit.alignment == PLAYER && (unit.leader || !self.has_leaders)
+	}
+
+	/// The player's leaders which are on the map, and so could seize a tile.
+	fn leaders(&self) -> Vec<(&Unit, Position)> {
+		self.units
+			.iter()
+			.copied()
+			.filter(|(unit, _)| self.is_leader(unit))
+			.collect()
+	}
+
+	#[must_use]
+	pub fn is_met(&self, objective: &Objective) -> bool {
+		match objective {
+			Objective::Rout => !self
+				.units
+				.iter()
+				.map(|(unit, _)| *unit)
+				.chain(self.carried.iter().copied())
+				.any(|unit| PLAYER.is_hostile(unit.alignment)),
+			Objective::Seize { x, y } => self
+				.leaders()
+				.iter()
+				.any(|(_, position)| *position == tile(*x, *y)),
+			Objective::Defeat { unit } => self
+				.defeated
+				.iter()
+				.any(|defeated| defeated.identifier == *unit),
+			Objective::Survive { turns } | Objective::Defend { turns, .. } => self.turn > *turns,
+			Objective::Escape { .. } => {
+				!self.escaped.is_empty()
+					&& self.leaders().is_empty()
+					&& !self.carried.iter().any(|unit| self.is_leader(unit))
+			}
+		}
+	}
+
+	/// Returns the name of the first objective which has been met, if any.
+	#[must_use]
+	pub fn victory(&self, objectives: &[Objective]) -> Option<String> {
+		objectives
+			.iter()
+			.find(|objective| self.is_met(objective))
+			.map(|objective| objective_name(objective).to_string())
+	}
+
+	#[must_use]
+	pub fn is_lost(&self, condition: &DefeatCondition) -> bool {
+		match condition {
+			DefeatCondition::LeaderDefeated => self
+				.defeated
+				.iter()
+				.any(|defeated| defeated.alignment == PLAYER && defeated.leader),
+			DefeatCondition::UnitDefeated { unit } => self
+				.defeated
+				.iter()
+				.any(|defeated| defeated.identifier == *unit),
+			DefeatCondition::TurnLimit { turns } => self.turn > *turns,
+		}
+	}
+
+	/// Returns the name of the first reason the map has been lost, if any.
+	///
+	/// Besides `conditions`, a map is lost once the last of the player's units is defeated,
+	/// and a `Defend` objective is lost once an enemy stands on its tile.
+	#[must_use]
+	pub fn defeat(
+		&self,
+		conditions: &[DefeatCondition],
+		objectives: &[Objective],
+	) -> Option<String> {
+		if let Some(condition) = conditions.iter().find(|condition| self.is_lost(condition)) {
+			return Some(condition_name(condition).to_string());
+		}
+		let invaded = objectives.iter().any(|objective| match objective {
+			Objective::Defend { x, y, .. } => self.units.iter().any(|(unit, position)| {
+				PLAYER.is_hostile(unit.alignment) && *position == tile(*x, *y)
+			}),
+			_ => false,
+		});
+		if invaded {
+			return Some("Defend".to_string());
+		}
+		let annihilated = self
+			.defeated
+			.iter()
+			.any(|defeated| defeated.alignment == PLAYER)
+			&& !self
+				.units
+				.iter()
+				.map(|(unit, _)| *unit)
+				.chain(self.carried.iter().copied())
+				.any(|unit| unit.alignment == PLAYER);
+		annihilated.then(|| "Annihilated".to_string())
+	}
+}
+
+#[must_use]
+pub fn objective_name(objective: &Objective) -> &'static str {
+	match objective {
+		Objective::Rout => "Rout",
+		Objective::Seize { .. } => "Seize",
+		Objective::Defeat { .. } => "Defeat",
+		Objective::Survive { .. } => "Survive",
+		Objective::Defend { .. } => "Defend",
+		Objective::Escape { .. } => "Escape",
+	}
+}
+
+#[must_use]
+pub fn condition_name(condition: &DefeatCondition) -> &'static str {
+	match condition {
+		DefeatCondition::LeaderDefeated => "LeaderDefeated",
+		DefeatCondition::UnitDefeated { .. } => "UnitDefeated",
+		DefeatCondition::TurnLimit { .. } => "TurnLimit",
+	}
+}
+
+/// Removes the player's units standing on an `Escape` tile as each phase begins, along with anyone they carry,
+/// then checks whether the map has been won or lost.
+#[allow(clippy::too_many_arguments)]
+fn check(
+	mut commands: Commands,
+	content: Res<Content>,
+	campaign: Res<Campaign>,
+	board: Option<ResMut<Board>>,
+	turn: Res<Turn>,
+	mut outcome: ResMut<MapOutcome>,
+	mut loaded: EventReader<MapLoaded>,
+	mut phases: EventReader<PhaseStarted>,
+	mut defeated: EventReader<UnitDefeated>,
+	mut removals: EventReader<UnitRemoved>,
+	units: Query<(Entity, &Unit, Option<&Position>)>,
+	mut escapes: EventWriter<UnitEscaped>,
+	mut finished: EventWriter<MapFinished>,
+) {
+	let new_phase = phases.iter().count() > 0;
+	let defeated: Vec<&UnitDefeated> = defeated.iter().collect();
+	// Removed units may not have been despawned yet.
+	let removed: Vec<Entity> = removals.iter().map(|removal| removal.entity).collect();
+	if loaded.iter().count() > 0 {
+		// The new map's board and units are not in place until the end of the frame.
+		outcome.0 = None;
+		return;
+	}
+	let Some(mut board) = board else {
+		return;
+	};
+	if outcome.0.is_some() || (!new_phase && defeated.is_empty() && removed.is_empty()) {
+		return;
+	}
+	let Some(map) = content.maps.get(&board.map) else {
+		return;
+	};
+	// Chapters may replace the goals of the map they are played on.
+	let chapter = campaign.current(&content, &board.map);
+	let objectives = chapter
+		.as_ref()
+		.and_then(|chapter| chapter.objectives.as_deref())
+		.unwrap_or(&map.objectives);
+	let conditions = chapter
+		.as_ref()
+		.and_then(|chapter| chapter.defeat.as_deref())
+		.unwrap_or(&map.defeat);
+
+	let exits: Vec<Position> = objectives
+		.iter()
+		.filter_map(|objective| match objective {
+			Objective::Escape { x, y } => Some(tile(*x, *y)),
+			_ => None,
+		})
+		.collect();
+	// Anyone being carried escapes along with their carrier.
+	let escaping: Vec<Entity> = units
+		.iter()
+		.filter(|(_, unit, position)| {
+			new_phase
+				&& unit.is_alive()
+				&& unit.alignment == PLAYER
+				&& position.is_some_and(|position| exits.contains(position))
+		})
+		.flat_map(|(entity, unit, _)| [Some(entity), unit.carrying])
+		.flatten()
+		.collect();
+	let mut remaining = Vec::new();
+	let mut carried = Vec::new();
+	for (entity, unit, position) in &units {
+		// Defeated units are only removed at the end of the frame.
+		if !unit.is_alive() || removed.contains(&entity) {
+			continue;
+		}
+		if escaping.contains(&entity) {
+			info!("{} escaped.", unit.name);
+			commands.entity(entity).despawn_recursive();
+			if unit.alignment == PLAYER {
+				board.escaped.insert(unit.identifier.clone());
+				escapes.send(UnitEscaped {
+					entity,
+					unit: unit.clone(),
+				});
+			}
+		} else if let Some(position) = position {
+			remaining.push((unit, *position));
+		} else {
+			carried.push(unit);
+		}
+	}
+
+	let has_leaders = map
+		.units
+		.iter()
+		.any(|placement| placement.alignment == PLAYER && placement.leader)
+		|| remaining
+			.iter()
+			.map(|(unit, _)| *unit)
+			.chain(carried.iter().copied())
+			.any(|unit| unit.alignment == PLAYER && unit.leader);
+	let situation = Situation {
+		turn: turn.number,
+		units: remaining,
+		carried,
+		escaped: &board.escaped,
+		has_leaders,
+		defeated: &defeated,
+	};
+	let result = situation
+		.defeat(conditions, objectives)
+		.map(|reason| (Outcome::Defeat, reason))
+		.or_else(|| {
+			situation
+				.victory(objectives)
+				.map(|reason| (Outcome::Victory, reason))
+		});
+	if let Some((result, reason)) = result {
+		info!("{result:?}: {reason}");
+		outcome.0 = Some(result);
+		finished.send(MapFinished {
+			outcome: result,
+			reason,
+		});
+	}
+}
+
+/// Checks each map's objectives and defeat conditions as phases begin and units fall or are removed.
+pub struct ObjectivesPlugin;
+
+impl Plugin for ObjectivesPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<MapOutcome>()
+			.add_event::<MapFinished>()
+			.add_event::<UnitEscaped>()
+			// Units defeated in battle must be gone, and their cargo dropped, before they are counted.
+			.add_systems(
+				Update,
+				(apply_deferred, check)
+					.chain()
+					.after(resolve_battles)
+					.after(load_map),
+			);
+	}
+}
@@ -1,115 +1,192 @@
+use crate::cursor::UiAction;
+use crate::module::Content;
 use bevy::prelude::*;
 use bevy::render::camera::*;
+use bevy::render::render_asset::RenderAssets;
 use bevy::render::render_resource::*;
+use bevy::render::renderer::{RenderDevice, RenderQueue};
 use bevy::render::view::RenderLayers;
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy::tasks::IoTaskPool;
+use bevy::window::PrimaryWindow;
+use fe_data::{Display, Scaling};
+use leafwing_input_manager::prelude::*;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory which screenshots are saved to, relative to the working directory.
+pub const SCREENSHOTS: &str = "screenshots";
 
 #[derive(Component)]
 struct PixelCanvas;
 
+/// The camera which renders the game world onto a pixel-perfect canvas.
+#[derive(Component)]
+pub struct CanvasCamera;
+
 /// A border shown around a pixel-perfect canvas layer.
 /// Automatically scales to fit as much as possible and fill the screen.
 #[derive(Component)]
 pub struct FullscreenBorder(pub Handle<Image>);
 
-/// Creates a pixel-perfect canvas layer.
-pub struct PixelPerfectCanvas<const X: u32, const Y: u32>;
+/// The texture which the canvas camera renders to, and how it is shown in the window.
+#[derive(Resource, Clone, Debug)]
+pub struct Canvas {
+	pub image: Handle<Image>,
+	pub display: Display,
+}
 
-impl<const X: u32, const Y: u32> Plugin for PixelPerfectCanvas<X, Y> {
-	fn build(&self, app: &mut App) {
-		let resize_canvas =
-			|windows: Query<&Window>, mut canvases: Query<&mut Transform, With<PixelCanvas>>| {
-				let window = windows.single();
-				let (x, y) = (window.resolution.width(), window.resolution.height());
-
-				let x = (x / (X as f32)).floor().max(1.0);
-				let y = (y / (Y as f32)).floor().max(1.0);
-				let scale = x.min(y);
-
-				for mut i in &mut canvases {
-					i.scale.x = scale;
-					i.scale.y = scale;
-				}
-			};
-
-		let add_camera = |mut commands: Commands,
-		                  mut assets: ResMut<Assets<Image>>,
-		                  asset_server: Res<AssetServer>| {
-			let size = Extent3d {
-				width: X,
-				height: Y,
-				..Default::default()
-			};
-
-			// This is the texture that will be rendered to.
-			let mut image = Image {
-				texture_descriptor: TextureDescriptor {
-					label: None,
-					size,
-					dimension: TextureDimension::D2,
-					format: TextureFormat::Bgra8UnormSrgb,
-					mip_level_count: 1,
-					sample_count: 1,
-					usage: TextureUsages::TEXTURE_BINDING
-						| TextureUsages::COPY_DST
-						| TextureUsages::RENDER_ATTACHMENT,
-					view_formats: &[],
-				},
-				..default()
-			};
-
-			// fill image.data with zeroes
-			image.resize(size);
-
-			let image_handle = assets.add(image);
-
-			commands.spawn((Camera2dBundle {
-				camera: Camera {
-					viewport: Some(Viewport {
-						// TODO: adjust this to match the size of the window.
-						physical_position: UVec2::ZERO,
-						physical_size: UVec2::new(X, Y),
-						..default()
-					}),
-					target: RenderTarget::Image(image_handle.clone()),
-					..default()
-				},
+impl Canvas {
+	#[must_use]
+	pub fn size(&self) -> Vec2 {
+		Vec2::new(self.display.width as f32, self.display.height as f32)
+	}
+
+	/// How many window pixels each canvas pixel covers.
+	#[must_use]
+	pub fn scale(&self, window: Vec2) -> f32 {
+		let fit = window / self.size();
+		match self.display.scaling {
+			Scaling::Integer => fit.x.min(fit.y).floor().max(1.0),
+			Scaling::Fill => fit.x.min(fit.y),
+		}
+	}
+
+	/// Distance from the window's top left corner to the canvas's, in window pixels.
+	///
+	/// This is rounded down to a whole pixel, so that the canvas is never drawn between two.
+	#[must_use]
+	pub fn offset(&self, window: Vec2) -> Vec2 {
+		((window - self.size() * self.scale(window)) / 2.0).floor()
+	}
+}
+
+fn canvas_image(display: &Display) -> Image {
+	let size = Extent3d {
+		width: display.width.max(1),
+		height: display.height.max(1),
+		..Default::default()
+	};
+
+	// This is the texture that will be rendered to.
+	let mut image = Image {
+		texture_descriptor: TextureDescriptor {
+			label: None,
+			size,
+			dimension: TextureDimension::D2,
+			format: TextureFormat::Bgra8UnormSrgb,
+			mip_level_count: 1,
+			sample_count: 1,
+			usage: TextureUsages::TEXTURE_BINDING
+				| TextureUsages::COPY_DST
+				| TextureUsages::COPY_SRC
+				| TextureUsages::RENDER_ATTACHMENT,
+			view_formats: &[],
+		},
+		..default()
+	};
+
+	// fill image.data with zeroes
+	image.resize(size);
+	image
+}
+
+fn add_camera(
+	mut commands: Commands,
+	mut assets: ResMut<Assets<Image>>,
+	asset_server: Res<AssetServer>,
+	content: Option<Res<Content>>,
+) {
+	let display = content
+		.map(|content| content.module.display.clone())
+		.unwrap_or_default();
+	let image_handle = assets.add(canvas_image(&display));
+
+	commands.spawn((
+		Camera2dBundle {
+			camera: Camera {
+				target: RenderTarget::Image(image_handle.clone()),
 				..default()
-			},));
-
-			let background_texture = asset_server.load("fe-engine/background.png");
-
-			// Scaler layer
-			// Projects the worldspace onto a statically sized texture.
-			// This allows for proper pixel scaling without breaking the grid effect.
-			let scale_up_layer = RenderLayers::layer(1);
-			commands.spawn((Camera2dBundle::default(), scale_up_layer));
-			commands.spawn((
-				FullscreenBorder(background_texture.clone_weak()),
-				SpriteBundle {
-					texture: background_texture,
-					transform: Transform {
-						translation: Vec3 {
-							z: -1.0,
-							..Default::default()
-						},
-						..Default::default()
-					},
-					..Default::default()
-				},
-				scale_up_layer,
-			));
-			commands.spawn((
-				SpriteBundle {
-					texture: image_handle,
+			},
+			..default()
+		},
+		CanvasCamera,
+	));
+
+	let background_texture = asset_server.load("background.png");
+
+	// Scaler layer
+	// Projects the worldspace onto a statically sized texture.
+	// This allows for proper pixel scaling without breaking the grid effect.
+	let scale_up_layer = RenderLayers::layer(1);
+	commands.spawn((Camera2dBundle::default(), scale_up_layer));
+	commands.spawn((
+		FullscreenBorder(background_texture.clone_weak()),
+		SpriteBundle {
+			texture: background_texture,
+			transform: Transform {
+				translation: Vec3 {
+					z: -1.0,
 					..Default::default()
 				},
-				PixelCanvas,
-				scale_up_layer,
-			));
-		};
+				..Default::default()
+			},
+			..Default::default()
+		},
+		scale_up_layer,
+	));
+	commands.spawn((
+		SpriteBundle {
+			texture: image_handle.clone(),
+			..Default::default()
+		},
+		PixelCanvas,
+		scale_up_layer,
+	));
+
+	commands.insert_resource(Canvas {
+		image: image_handle,
+		display,
+	});
+}
 
-		app.add_systems(Startup, add_camera)
-			.add_systems(Update, (resize_canvas, resize_border));
+/// Resizes the canvas when a module with another resolution is loaded.
+fn configure(content: Res<Content>, mut canvas: ResMut<Canvas>, mut assets: ResMut<Assets<Image>>) {
+	if !content.is_changed() || canvas.display == content.module.display {
+		return;
+	}
+	canvas.display = content.module.display.clone();
+	if let Some(image) = assets.get_mut(&canvas.image) {
+		*image = canvas_image(&canvas.display);
+	}
+}
+
+fn resize_canvas(
+	windows: Query<&Window, With<PrimaryWindow>>,
+	canvas: Res<Canvas>,
+	mut canvases: Query<&mut Transform, With<PixelCanvas>>,
+) {
+	let Ok(window) = windows.get_single() else {
+		return;
+	};
+	// Measured in physical pixels, so that the canvas lines up with the screen's own.
+	let scale_factor = window.resolution.scale_factor() as f32;
+	let physical = Vec2::new(
+		window.physical_width() as f32,
+		window.physical_height() as f32,
+	);
+	let scale = canvas.scale(physical);
+	// The sprite is centred, so an odd amount of space around it would split a pixel in half.
+	let centered = (physical - canvas.size() * scale) / 2.0;
+	let shift = canvas.offset(physical) - centered;
+
+	for mut i in &mut canvases {
+		i.scale.x = scale / scale_factor;
+		i.scale.y = scale / scale_factor;
+		i.translation.x = shift.x / scale_factor;
+		i.translation.y = -shift.y / scale_factor;
 	}
 }
 
@@ -132,3 +209,199 @@ fn resize_border(
 		trans.scale.y = scale;
 	}
 }
+
+/// The canvas is stored as BGRA, with a byte for each channel.
+const PIXEL_SIZE: u32 = 4;
+
+/// Screenshots which have been requested, but not yet copied from the canvas.
+///
+/// This is shared by the main and render worlds, since only the render world can read the canvas.
+#[derive(Resource, Clone, Debug, Default)]
+struct Captures(Arc<Mutex<Vec<Capture>>>);
+
+#[derive(Clone, Debug)]
+struct Capture {
+	image: Handle<Image>,
+	path: PathBuf,
+}
+
+/// A copy of the canvas which is on its way back from the GPU.
+struct Readback {
+	buffer: Buffer,
+	path: PathBuf,
+	width: u32,
+	height: u32,
+	/// Length of each row within the buffer, which is padded to satisfy the GPU.
+	row_length: u32,
+	/// Set once the buffer can be read, to whether it was mapped successfully.
+	mapped: Arc<Mutex<Option<Result<(), String>>>>,
+}
+
+#[derive(Resource, Default)]
+struct Readbacks(Vec<Readback>);
+
+/// Asks for the canvas to be saved once it has been rendered.
+fn screenshot(
+	actions: Query<&ActionState<UiAction>>,
+	canvas: Res<Canvas>,
+	captures: Res<Captures>,
+) {
+	if !actions
+		.iter()
+		.any(|state| state.just_pressed(UiAction::Screenshot))
+	{
+		return;
+	}
+	let time = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_millis();
+	let path = PathBuf::from(SCREENSHOTS).join(format!("{time}.png"));
+	if let Ok(mut captures) = captures.0.lock() {
+		captures.push(Capture {
+			image: canvas.image.clone(),
+			path,
+		});
+	}
+}
+
+/// Copies the canvas into a buffer for each requested screenshot,
+/// and saves each copy once it can be read.
+///
+/// This runs after rendering, so that each copy matches what was shown this frame.
+fn capture(
+	captures: Res<Captures>,
+	mut readbacks: ResMut<Readbacks>,
+	images: Res<RenderAssets<Image>>,
+	device: Res<RenderDevice>,
+	queue: Res<RenderQueue>,
+) {
+	// Buffers are mapped while the render queue is polled, which happens every frame.
+	let (ready, waiting) = std::mem::take(&mut readbacks.0)
+		.into_iter()
+		.partition(|readback| readback.mapped.lock().is_ok_and(|mapped| mapped.is_some()));
+	readbacks.0 = waiting;
+	for readback in ready {
+		save(&readback);
+	}
+
+	let requests = match captures.0.lock() {
+		Ok(mut captures) => std::mem::take(&mut *captures),
+		Err(_) => return,
+	};
+	for Capture { image, path } in requests {
+		let Some(image) = images.get(&image) else {
+			error!("Failed to take screenshot: the canvas has not been rendered yet");
+			continue;
+		};
+		let (width, height) = (image.size.x as u32, image.size.y as u32);
+		let row_length =
+			RenderDevice::align_copy_bytes_per_row((width * PIXEL_SIZE) as usize) as u32;
+		let buffer = device.create_buffer(&BufferDescriptor {
+			label: Some("canvas screenshot"),
+			size: u64::from(row_length * height),
+			usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+		encoder.copy_texture_to_buffer(
+			image.texture.as_image_copy(),
+			ImageCopyBuffer {
+				buffer: &buffer,
+				layout: ImageDataLayout {
+					offset: 0,
+					bytes_per_row: Some(row_length),
+					rows_per_image: None,
+				},
+			},
+			Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+		);
+		queue.submit([encoder.finish()]);
+
+		let mapped = Arc::new(Mutex::new(None));
+		let result = mapped.clone();
+		device.map_buffer(&buffer.slice(..), MapMode::Read, move |mapping| {
+			if let Ok(mut result) = result.lock() {
+				*result = Some(mapping.map_err(|msg| msg.to_string()));
+			}
+		});
+		readbacks.0.push(Readback {
+			buffer,
+			path,
+			width,
+			height,
+			row_length,
+			mapped,
+		});
+	}
+}
+
+/// Removes the padding from a copy of the canvas, and writes it to disk in the background.
+fn save(readback: &Readback) {
+	if let Ok(Some(Err(msg))) = readback.mapped.lock().as_deref() {
+		error!("Failed to capture screenshot: {msg}");
+		return;
+	}
+	let mut pixels = Vec::with_capacity((readback.width * readback.height * PIXEL_SIZE) as usize);
+	{
+		let data = readback.buffer.slice(..).get_mapped_range();
+		for row in data.chunks(readback.row_length as usize) {
+			for pixel in row[..(readback.width * PIXEL_SIZE) as usize].chunks(PIXEL_SIZE as usize) {
+				pixels.extend([pixel[2], pixel[1], pixel[0], pixel[3]]);
+			}
+		}
+	}
+	readback.buffer.unmap();
+
+	let Some(native) = image::RgbaImage::from_raw(readback.width, readback.height, pixels) else {
+		error!("Failed to capture screenshot: the canvas was an unexpected size");
+		return;
+	};
+	let path = readback.path.clone();
+	IoTaskPool::get()
+		.spawn(async move {
+			if let Err(msg) = fs::create_dir_all(SCREENSHOTS) {
+				error!("Failed to create {SCREENSHOTS}: {msg}");
+				return;
+			}
+			match native.save(&path) {
+				Ok(()) => info!("Saved screenshot to {}", path.display()),
+				Err(msg) => error!("Failed to save {}: {msg}", path.display()),
+			}
+		})
+		.detach();
+}
+
+/// Creates a pixel-perfect canvas layer, sized according to the loaded module's display settings.
+///
+/// The canvas is centred in the window, with the background filling the space around it.
+pub struct PixelPerfectCanvas;
+
+impl Plugin for PixelPerfectCanvas {
+	fn build(&self, app: &mut App) {
+		let captures = Captures::default();
+		if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+			render_app
+				.insert_resource(captures.clone())
+				.init_resource::<Readbacks>()
+				.add_systems(Render, capture.in_set(RenderSet::Cleanup));
+		}
+		app.insert_resource(captures)
+			.add_systems(Startup, add_camera)
+			.add_systems(
+				Update,
+				(
+					configure.run_if(resource_exists::<Content>()),
+					resize_canvas,
+					resize_border,
+					screenshot,
+				)
+					.chain(),
+			);
+	}
+}
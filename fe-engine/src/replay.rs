@@ -0,0 +1,355 @@
+use crate::cursor::UiAction;
+use crate::map::Position;
+use crate::module::{Content, Module};
+use crate::rng::GameRng;
+use crate::script::Variables;
+use crate::turn::Turn;
+use crate::unit::Unit;
+use anyhow::{bail, Context};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use leafwing_input_manager::plugin::InputManagerSystem;
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Version of the recording format written by this build.
+pub const RECORDING_VERSION: u32 = 1;
+
+/// Length of every frame while recording or replaying, so that timers advance identically.
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Actions which changed on a single frame.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct InputFrame {
+	pub frame: u64,
+	pub pressed: Vec<UiAction>,
+	pub released: Vec<UiAction>,
+	/// Raw keys pressed, for systems which read input that is not bound to an action yet.
+	#[serde(default)]
+	pub keys: Vec<KeyCode>,
+	/// Raw gamepad buttons pressed, for the same reason as `keys`.
+	#[serde(default)]
+	pub buttons: Vec<GamepadButtonType>,
+}
+
+/// A session which can be replayed exactly, from startup until the game was closed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Recording {
+	pub version: u32,
+	pub module: String,
+	pub seed: u64,
+	/// Number of frames which were recorded.
+	pub frames: u64,
+	/// Only frames where an action changed are stored.
+	pub inputs: Vec<InputFrame>,
+	/// Checksum of the game's state on the final frame.
+	pub checksum: u64,
+}
+
+impl Recording {
+	/// # Errors
+	///
+	/// Fails if the file could not be read or parsed,
+	/// or was written by an incompatible version of the engine.
+	pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+		let path = path.as_ref();
+		let text = fs::read_to_string(path)
+			.with_context(|| format!("failed to read {}", path.display()))?;
+		let recording: Self =
+			ron::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))?;
+		if recording.version != RECORDING_VERSION {
+			bail!(
+				"{} is from version {} of the recording format, but only version {RECORDING_VERSION} is supported",
+				path.display(),
+				recording.version
+			);
+		}
+		Ok(recording)
+	}
+
+	/// # Errors
+	///
+	/// Fails if the recording was made with another module, which it would not replay the same way.
+	pub fn check_module(&self, module: &Module) -> anyhow::Result<()> {
+		if self.module != module.name {
+			bail!(
+				"the recording was made with the module {:?}, but {:?} is loaded",
+				self.module,
+				module.name
+			);
+		}
+		Ok(())
+	}
+
+	/// # Errors
+	///
+	/// Fails if the file could not be written.
+	pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+		let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+		fs::write(path, text)?;
+		Ok(())
+	}
+}
+
+/// Set once a replay has diverged from its recording, so that the engine can exit with an error.
+///
+/// This is shared with whoever added `ReplayPlugin`, since the app is gone once it stops running.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Diverged(Arc<AtomicBool>);
+
+impl Diverged {
+	#[must_use]
+	pub fn get(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+
+	fn set(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+}
+
+/// Everything that a replay must reproduce.
+#[derive(Serialize)]
+struct Snapshot<'a> {
+	turn: &'a Turn,
+	rng: &'a GameRng,
+	variables: &'a Variables,
+	units: Vec<(&'a Unit, &'a Position)>,
+}
+
+/// Hashes the state of the game, so that replays can tell when they have diverged.
+///
+/// The state is hashed in a serialized form using FNV-1a,
+/// so that the result does not depend on the platform or compiler version.
+fn checksum(snapshot: &Snapshot) -> u64 {
+	let text = ron::to_string(snapshot).unwrap_or_default();
+	text.bytes().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+		(hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+	})
+}
+
+/// Whether the current session is being recorded or replayed.
+#[derive(Resource, Clone, Debug)]
+enum Session {
+	Recording {
+		path: PathBuf,
+		recording: Recording,
+		frame: u64,
+	},
+	Replaying {
+		recording: Recording,
+		frame: u64,
+		next: usize,
+	},
+}
+
+/// Writes down which actions changed this frame.
+fn record(
+	mut session: ResMut<Session>,
+	actions: Query<&ActionState<UiAction>>,
+	keys: Res<Input<KeyCode>>,
+	buttons: Res<Input<GamepadButton>>,
+) {
+	let Session::Recording {
+		recording, frame, ..
+	} = &mut *session
+	else {
+		return;
+	};
+	*frame += 1;
+
+	let mut input = InputFrame {
+		frame: *frame,
+		..Default::default()
+	};
+	for state in &actions {
+		input.pressed.extend(state.get_just_pressed());
+		input.released.extend(state.get_just_released());
+	}
+	input.keys.extend(keys.get_just_pressed());
+	input
+		.buttons
+		.extend(buttons.get_just_pressed().map(|button| button.button_type));
+	if !input.pressed.is_empty()
+		|| !input.released.is_empty()
+		|| !input.keys.is_empty()
+		|| !input.buttons.is_empty()
+	{
+		recording.inputs.push(input);
+	}
+}
+
+/// Presses and releases actions as they were recorded, in place of the player's input.
+fn replay(
+	mut commands: Commands,
+	mut session: ResMut<Session>,
+	inputs: Query<Entity, With<InputMap<UiAction>>>,
+	mut actions: Query<&mut ActionState<UiAction>>,
+	mut keys: ResMut<Input<KeyCode>>,
+	mut buttons: ResMut<Input<GamepadButton>>,
+) {
+	let Session::Replaying {
+		recording,
+		frame,
+		next,
+	} = &mut *session
+	else {
+		return;
+	};
+	*frame += 1;
+
+	for entity in &inputs {
+		commands.entity(entity).remove::<InputMap<UiAction>>();
+	}
+	// Recorded keys are pressed for a single frame, so that they can be pressed again later.
+	keys.reset_all();
+	buttons.reset_all();
+	while let Some(input) = recording.inputs.get(*next) {
+		if input.frame > *frame {
+			break;
+		}
+		*next += 1;
+		for mut state in &mut actions {
+			for action in &input.released {
+				state.release(*action);
+			}
+			for action in &input.pressed {
+				state.press(*action);
+			}
+		}
+		for key in &input.keys {
+			keys.press(*key);
+		}
+		for button in &input.buttons {
+			buttons.press(GamepadButton::new(Gamepad::new(0), *button));
+		}
+	}
+}
+
+/// Saves the recording once the game closes,
+/// or checks the final state once a replay has run its course.
+fn finish(
+	mut session: ResMut<Session>,
+	// Read and written through the same parameter, since a system cannot have both.
+	mut exits: ResMut<Events<AppExit>>,
+	turn: Res<Turn>,
+	rng: Res<GameRng>,
+	variables: Res<Variables>,
+	units: Query<(&Unit, &Position)>,
+	diverged: Option<Res<Diverged>>,
+) {
+	let snapshot = || {
+		let mut units: Vec<(&Unit, &Position)> = units.iter().collect();
+		units.sort_by(|(a, a_position), (b, b_position)| {
+			(&a.identifier, a_position).cmp(&(&b.identifier, b_position))
+		});
+		Snapshot {
+			turn: &turn,
+			rng: &rng,
+			variables: &variables,
+			units,
+		}
+	};
+
+	match &mut *session {
+		Session::Recording {
+			path,
+			recording,
+			frame,
+		} => {
+			if exits.is_empty() {
+				return;
+			}
+			recording.frames = *frame;
+			recording.checksum = checksum(&snapshot());
+			match recording.write(path.as_path()) {
+				Ok(()) => info!("Recorded {} frames to {}", frame, path.display()),
+				Err(msg) => error!("Failed to save recording: {msg}"),
+			}
+		}
+		Session::Replaying {
+			recording, frame, ..
+		} => {
+			if *frame < recording.frames {
+				return;
+			}
+			let checksum = checksum(&snapshot());
+			if checksum == recording.checksum {
+				info!("Replay of {frame} frames matched its recording");
+			} else {
+				error!(
+					"Replay diverged after {frame} frames: expected checksum {:016x}, got {checksum:016x}",
+					recording.checksum
+				);
+				if let Some(diverged) = &diverged {
+					diverged.set();
+				}
+			}
+			exits.send(AppExit);
+		}
+	}
+}
+
+/// Records the player's input to a file, or replays a previous recording.
+///
+/// Either way, every frame is treated as lasting exactly `FRAME_DURATION`,
+/// and the game is seeded from the recording.
+/// Sessions must begin from startup.
+pub enum ReplayPlugin {
+	/// Records a new session with the given seed, writing it to a file once the game is closed.
+	Record { path: PathBuf, seed: u64 },
+	/// Replays a session, and exits once it has run its course.
+	///
+	/// `Diverged` is set if the session did not end in the recorded state.
+	Replay(Recording, Diverged),
+}
+
+impl Plugin for ReplayPlugin {
+	fn build(&self, app: &mut App) {
+		let (session, seed) = match self {
+			Self::Record { path, seed } => (
+				Session::Recording {
+					path: path.clone(),
+					recording: Recording {
+						version: RECORDING_VERSION,
+						module: app
+							.world
+							.get_resource::<Content>()
+							.map(|content| content.module.name.clone())
+							.unwrap_or_default(),
+						seed: *seed,
+						..Default::default()
+					},
+					frame: 0,
+				},
+				*seed,
+			),
+			Self::Replay(recording, diverged) => {
+				app.insert_resource(diverged.clone());
+				(
+					Session::Replaying {
+						recording: recording.clone(),
+						frame: 0,
+						next: 0,
+					},
+					recording.seed,
+				)
+			}
+		};
+
+		app.insert_resource(session)
+			.insert_resource(GameRng::new(seed))
+			.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_DURATION))
+			.add_systems(
+				PreUpdate,
+				(record, replay).chain().after(InputManagerSystem::Update),
+			)
+			.add_systems(Last, finish);
+	}
+}
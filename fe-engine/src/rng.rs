@@ -0,0 +1,71 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seeded random number generator used for every gameplay roll.
+///
+/// All randomness must come from this resource,
+/// so that a session can be reproduced from its seed.
+#[derive(Resource, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GameRng {
+	seed: u64,
+	state: u64,
+}
+
+impl GameRng {
+	#[must_use]
+	pub fn new(seed: u64) -> Self {
+		Self { seed, state: seed }
+	}
+
+	/// Seeds the generator from the system clock.
+	#[must_use]
+	pub fn from_time() -> Self {
+		let seed = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |time| time.as_nanos() as u64);
+		Self::new(seed)
+	}
+
+	#[must_use]
+	pub fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	/// Produces the next 64 random bits (SplitMix64).
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^ (z >> 31)
+	}
+
+	/// Returns a random number in `0..max`.
+	pub fn below(&mut self, max: u32) -> u32 {
+		if max == 0 {
+			return 0;
+		}
+		(self.next_u64() % u64::from(max)) as u32
+	}
+
+	/// Rolls a single number against a percent chance.
+	pub fn roll(&mut self, chance: i32) -> bool {
+		(self.below(100) as i32) < chance
+	}
+
+	/// Rolls against a percent chance using the average of two numbers.
+	///
+	/// This makes high hit rates more reliable and low hit rates less so,
+	/// which is what players tend to expect from the displayed number.
+	pub fn roll_2rn(&mut self, chance: i32) -> bool {
+		let average = (self.below(100) + self.below(100)) / 2;
+		(average as i32) < chance
+	}
+}
+
+impl Default for GameRng {
+	fn default() -> Self {
+		Self::new(0)
+	}
+}
@@ -0,0 +1,239 @@
+use crate::campaign::{Campaign, StartChapter};
+use crate::map::{load_map, Board, MapState, RestoreMap, SavedUnitQuery};
+use crate::module::{Content, Module};
+use crate::rng::GameRng;
+use crate::script::Variables;
+use crate::turn::Turn;
+use anyhow::{anyhow, bail, Context};
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Version of the save format written by this build.
+///
+/// Increase this whenever a change to the saved types would break older files.
+pub const SAVE_VERSION: u32 = 1;
+
+/// Where a save is stored.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Slot {
+	/// Progress between chapters. Modules may use any number of these.
+	Campaign(u32),
+	/// The exact state of a chapter in progress, so that it can be resumed later.
+	Suspend,
+}
+
+impl Slot {
+	fn file_name(self) -> String {
+		match self {
+			Self::Campaign(index) => format!("campaign-{index}.ron"),
+			Self::Suspend => String::from("suspend.ron"),
+		}
+	}
+
+	fn from_file_name(name: &str) -> Option<Self> {
+		if name == "suspend.ron" {
+			return Some(Self::Suspend);
+		}
+		name.strip_prefix("campaign-")?
+			.strip_suffix(".ron")?
+			.parse()
+			.ok()
+			.map(Self::Campaign)
+	}
+}
+
+/// Returns the directory which a module's saves are kept in.
+#[must_use]
+pub fn directory(module: &Module) -> PathBuf {
+	PathBuf::from("saves").join(&module.name)
+}
+
+/// Lists every slot which a module has saved to.
+#[must_use]
+pub fn slots(module: &Module) -> Vec<Slot> {
+	let mut slots: Vec<Slot> = fs::read_dir(directory(module))
+		.into_iter()
+		.flatten()
+		.filter_map(|entry| Slot::from_file_name(entry.ok()?.file_name().to_str()?))
+		.collect();
+	slots.sort();
+	slots
+}
+
+/// Identifies the format and module of a save, so that incompatible files can be rejected.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Header {
+	version: u32,
+	module: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct SaveFile<T> {
+	version: u32,
+	module: String,
+	data: T,
+}
+
+/// Progress saved between chapters.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CampaignSave {
+	pub campaign: Campaign,
+	pub variables: Variables,
+}
+
+/// A chapter in progress.
+///
+/// Scripts which are still running are not saved,
+/// so the game should only be suspended between actions.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SuspendSave {
+	pub campaign: Campaign,
+	pub variables: Variables,
+	pub rng: GameRng,
+	pub map: MapState,
+}
+
+/// Writes save data to a slot, replacing any previous save.
+///
+/// # Errors
+///
+/// Fails if the file could not be written.
+pub fn write<T: Serialize>(module: &Module, slot: Slot, data: T) -> anyhow::Result<()> {
+	let file = SaveFile {
+		version: SAVE_VERSION,
+		module: module.name.clone(),
+		data,
+	};
+	let text = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())?;
+	let directory = directory(module);
+	fs::create_dir_all(&directory)?;
+	fs::write(directory.join(slot.file_name()), text)?;
+	Ok(())
+}
+
+/// Reads save data from a slot.
+///
+/// # Errors
+///
+/// Fails if the file could not be read or parsed,
+/// or was written by another module or an incompatible version of the engine.
+pub fn read<T: DeserializeOwned>(module: &Module, slot: Slot) -> anyhow::Result<T> {
+	let path = directory(module).join(slot.file_name());
+	let text =
+		fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+
+	let header: Header = ron::from_str(&text)?;
+	if header.version != SAVE_VERSION {
+		bail!(
+			"{} is from version {} of the save format, but only version {SAVE_VERSION} is supported",
+			path.display(),
+			header.version
+		);
+	}
+	if header.module != module.name {
+		bail!("{} belongs to {}", path.display(), header.module);
+	}
+
+	let file: SaveFile<T> =
+		ron::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))?;
+	Ok(file.data)
+}
+
+/// Saves the game to a slot.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SaveGame(pub Slot);
+
+/// Replaces the game with a save from a slot.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct LoadGame(pub Slot);
+
+#[allow(clippy::too_many_arguments)]
+fn save_game(
+	mut requests: EventReader<SaveGame>,
+	content: Res<Content>,
+	campaign: Res<Campaign>,
+	variables: Res<Variables>,
+	rng: Res<GameRng>,
+	turn: Res<Turn>,
+	board: Option<Res<Board>>,
+	units: SavedUnitQuery,
+) {
+	for SaveGame(slot) in requests.iter() {
+		let result = match slot {
+			Slot::Campaign(_) => write(
+				&content.module,
+				*slot,
+				CampaignSave {
+					campaign: campaign.clone(),
+					variables: variables.clone(),
+				},
+			),
+			Slot::Suspend => board
+				.as_ref()
+				.ok_or_else(|| anyhow!("no map is loaded"))
+				.and_then(|board| {
+					write(
+						&content.module,
+						*slot,
+						SuspendSave {
+							campaign: campaign.clone(),
+							variables: variables.clone(),
+							rng: rng.clone(),
+							map: MapState::capture(board, &turn, &units),
+						},
+					)
+				}),
+		};
+		match result {
+			Ok(()) => info!("Saved to {slot:?}"),
+			Err(msg) => error!("Failed to save to {slot:?}: {msg}"),
+		}
+	}
+}
+
+fn load_game(
+	mut commands: Commands,
+	mut requests: EventReader<LoadGame>,
+	content: Res<Content>,
+	mut campaign: ResMut<Campaign>,
+	mut chapters: EventWriter<StartChapter>,
+	mut restores: EventWriter<RestoreMap>,
+) {
+	let Some(LoadGame(slot)) = requests.iter().last() else {
+		return;
+	};
+	let result = match slot {
+		Slot::Campaign(_) => read(&content.module, *slot).map(|save: CampaignSave| {
+			if let Some(chapter) = &save.campaign.chapter {
+				chapters.send(StartChapter(chapter.clone()));
+			}
+			// Replaced immediately, since the chapter deploys units from the roster.
+			*campaign = save.campaign;
+			commands.insert_resource(save.variables);
+		}),
+		Slot::Suspend => read(&content.module, *slot).map(|save: SuspendSave| {
+			restores.send(RestoreMap(save.map));
+			commands.insert_resource(save.campaign);
+			commands.insert_resource(save.variables);
+			commands.insert_resource(save.rng);
+		}),
+	};
+	match result {
+		Ok(()) => info!("Loaded {slot:?}"),
+		Err(msg) => error!("Failed to load {slot:?}: {msg}"),
+	}
+}
+
+/// Writes and reads save files in response to `SaveGame` and `LoadGame`.
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_event::<SaveGame>()
+			.add_event::<LoadGame>()
+			.add_systems(Update, (save_game, load_game).before(load_map));
+	}
+}
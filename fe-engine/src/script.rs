@@ -0,0 +1,1461 @@
+use crate::action::CustomAction;
+use crate::battle::UnitDefeated;
+use crate::camera::{PanCamera, PanFinished};
+use crate::campaign::Campaign;
+use crate::dialogue::Side;
+use crate::experience::LevelUp;
+use crate::map::{
+	load_map, spawn_unit, Board, ChangeTile, MapLoaded, MoveFinished, MoveUnit, Position,
+};
+use crate::module::Content;
+use crate::objectives::{MapFinished, Outcome, UnitEscaped, PLAYER};
+use crate::turn::{Acted, PhaseStarted, Turn};
+use crate::unit::{drop_cargo, Unit};
+use bevy::ecs::query::Has;
+use bevy::prelude::*;
+use fe_data::{
+	Alignment, ClassReference, ConvoyAccess, ItemReference, Stats, UnitPlacement, UnitReference,
+};
+use mlua::prelude::*;
+use mlua::Variadic;
+use serde::de::value::StrDeserializer;
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Kinds of work that a script may wait on.
+///
+/// Yielding an `Event` waits for every unfinished piece of work of that kind
+/// started by the same script.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Event {
+	/// Resumes on the next frame.
+	Immediately,
+	/// Waits until all dialogue has been printed.
+	WaitPrint,
+	/// Waits until the units which this script moved have finished moving.
+	WaitMove,
+	/// Waits until the camera has finished panning.
+	WaitCamera,
+}
+
+impl LuaUserData for Event {}
+
+impl<'lua> FromLua<'lua> for Event {
+	fn from_lua(value: LuaValue<'lua>, _lua: &'lua Lua) -> LuaResult<Self> {
+		match value {
+			LuaValue::UserData(data) => Ok(*data.borrow::<Self>()?),
+			_ => Err(LuaError::FromLuaConversionError {
+				from: value.type_name(),
+				to: "Event",
+				message: None,
+			}),
+		}
+	}
+}
+
+/// Identifies a running script.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ScriptId(u32);
+
+/// A single piece of work started by a script.
+///
+/// Every script function which affects the game returns a handle,
+/// which can be passed to `coroutine.yield` to wait for that work alone.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Handle {
+	pub script: ScriptId,
+	pub id: u32,
+	pub event: Event,
+}
+
+impl LuaUserData for Handle {
+	fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+		fields.add_field_method_get("event", |_, this| Ok(this.event));
+	}
+
+	fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+		methods.add_method("finished", |lua, this, ()| {
+			Ok(lua
+				.app_data_ref::<Context>()
+				.is_none_or(|context| !context.pending.contains(this)))
+		});
+	}
+}
+
+/// Something a script has yielded to wait on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Wait {
+	Event(Event),
+	Handle(Handle),
+}
+
+impl<'lua> FromLua<'lua> for Wait {
+	fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+		if let LuaValue::UserData(data) = &value {
+			if let Ok(handle) = data.borrow::<Handle>() {
+				return Ok(Self::Handle(*handle));
+			}
+		}
+		Event::from_lua(value, lua).map(Self::Event)
+	}
+}
+
+/// A value stored by a script using `set_var`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Variable {
+	Bool(bool),
+	Number(f64),
+	String(String),
+}
+
+impl<'lua> IntoLua<'lua> for Variable {
+	fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+		match self {
+			Self::Bool(value) => Ok(LuaValue::Boolean(value)),
+			Self::Number(value) => Ok(LuaValue::Number(value)),
+			Self::String(value) => lua.create_string(&value).map(LuaValue::String),
+		}
+	}
+}
+
+impl<'lua> FromLua<'lua> for Variable {
+	fn from_lua(value: LuaValue<'lua>, _lua: &'lua Lua) -> LuaResult<Self> {
+		match value {
+			LuaValue::Boolean(value) => Ok(Self::Bool(value)),
+			LuaValue::Integer(value) => Ok(Self::Number(value as f64)),
+			LuaValue::Number(value) => Ok(Self::Number(value)),
+			LuaValue::String(value) => Ok(Self::String(value.to_str()?.to_owned())),
+			_ => Err(LuaError::FromLuaConversionError {
+				from: value.type_name(),
+				to: "Variable",
+				message: Some("expected a boolean, number, or string".into()),
+			}),
+		}
+	}
+}
+
+/// Flags and variables set by scripts, which persist for the rest of the campaign.
+#[derive(Resource, Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Variables {
+	pub flags: BTreeSet<String>,
+	pub values: BTreeMap<String, Variable>,
+}
+
+/// A copy of the game state which scripts may read from.
+///
+/// This is taken at the start of each frame,
+/// so changes made by a script are not visible until it next resumes.
+#[derive(Clone, Debug, Default)]
+pub struct WorldView {
+	pub turn: Turn,
+	pub units: BTreeMap<String, (Unit, Position)>,
+	/// Identifiers of the units being carried, which are off the map and so not in `units`.
+	pub carried: BTreeMap<Entity, String>,
+	/// The player's gold.
+	pub gold: u32,
+}
+
+/// Work requested by a script, to be carried out by the engine.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+	/// Shows a line of dialogue, and lets the player pick from `choices` if there are any.
+	Say {
+		speaker: Option<String>,
+		text: String,
+		side: Side,
+		choices: Vec<String>,
+	},
+	/// Moves the script's subject by a number of tiles.
+	Move {
+		x: i32,
+		y: i32,
+	},
+	/// Moves a unit to a tile.
+	MoveUnit {
+		unit: String,
+		x: i32,
+		y: i32,
+	},
+	Spawn(UnitPlacement),
+	Remove {
+		unit: String,
+	},
+	GiveItem {
+		unit: String,
+		item: String,
+	},
+	TakeItem {
+		unit: String,
+		item: String,
+	},
+	SetTile {
+		x: i32,
+		y: i32,
+		tile: u32,
+	},
+	PanCamera {
+		x: i32,
+		y: i32,
+	},
+	PanToUnit {
+		unit: String,
+	},
+	/// Adds an action to the unit menu, replacing any other with the same name.
+	AddAction(CustomAction),
+	RemoveAction {
+		name: String,
+	},
+	/// Adds to the player's gold, or takes it away if `amount` is negative.
+	GiveGold {
+		amount: i64,
+	},
+}
+
+/// Sent whenever a script calls a function which affects the game.
+#[derive(Event, Clone, Debug)]
+pub struct ScriptRequest {
+	pub handle: Handle,
+	/// The entity which the script belongs to, if any.
+	pub subject: Option<Entity>,
+	pub command: Command,
+}
+
+/// Sent by the engine once it has finished the work of a `ScriptRequest`.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ScriptEventFinished(pub Handle);
+
+/// Sent instead of `ScriptEventFinished` when the work produced a value,
+/// such as the player's choice in a dialogue.
+///
+/// The value is returned from the `coroutine.yield` which waited on the handle.
+#[derive(Event, Clone, Debug)]
+pub struct ScriptEventResult {
+	pub handle: Handle,
+	pub value: Variable,
+}
+
+/// State shared between the scheduler and the functions it exposes to Lua.
+#[derive(Debug, Default)]
+struct Context {
+	current: Option<(ScriptId, Option<Entity>)>,
+	next_handle: u32,
+	requests: Vec<ScriptRequest>,
+	/// Work which has been started but not yet finished.
+	pending: HashSet<Handle>,
+	/// Values produced by finished work, until they are returned to the script.
+	results: HashMap<Handle, Variable>,
+	world: WorldView,
+	variables: Variables,
+}
+
+impl Context {
+	fn handle(&mut self, event: Event) -> LuaResult<(Handle, Option<Entity>)> {
+		let Some((script, subject)) = self.current else {
+			return Err(LuaError::RuntimeError(
+				"script functions may only be called from within a coroutine".into(),
+			));
+		};
+		let handle = Handle {
+			script,
+			id: self.next_handle,
+			event,
+		};
+		self.next_handle = self.next_handle.wrapping_add(1);
+		Ok((handle, subject))
+	}
+
+	fn start(&mut self, command: Command, event: Event) -> LuaResult<Handle> {
+		let (handle, subject) = self.handle(event)?;
+		self.pending.insert(handle);
+		self.requests.push(ScriptRequest {
+			handle,
+			subject,
+			command,
+		});
+		Ok(handle)
+	}
+
+	fn is_waiting(&self, script: ScriptId, waiting: &[Wait]) -> bool {
+		waiting.iter().any(|wait| match wait {
+			Wait::Event(Event::Immediately) => false,
+			Wait::Event(event) => self
+				.pending
+				.iter()
+				.any(|handle| handle.script == script && handle.event == *event),
+			Wait::Handle(handle) => self.pending.contains(handle),
+		})
+	}
+}
+
+struct Script {
+	id: ScriptId,
+	thread: LuaRegistryKey,
+	subject: Option<Entity>,
+	/// Passed to the coroutine when it is first resumed.
+	arguments: Option<Vec<Variable>>,
+	/// Events which must all finish before the script is resumed.
+	waiting: Vec<Wait>,
+}
+
+/// A loaded script file, with its own globals and `signal` table.
+struct Environment {
+	table: LuaRegistryKey,
+	subject: Option<Entity>,
+	/// Triggers whose signals are sent to this script.
+	/// Scripts without a subject receive every trigger.
+	triggers: Vec<String>,
+}
+
+/// Owns the Lua state and every coroutine running within it.
+///
+/// Coroutines are kept alive across frames,
+/// and are only resumed once the events they yielded have finished.
+/// A coroutine may yield an `Event`, a `Handle`, a table of either,
+/// or nothing to resume on the next frame.
+///
+/// The functions available to scripts are described in `SCRIPTING.md`.
+pub struct ScriptRuntime {
+	lua: Lua,
+	scripts: Vec<Script>,
+	environments: Vec<Environment>,
+	next_id: u32,
+}
+
+impl ScriptRuntime {
+	/// Creates a Lua state with the engine's functions registered.
+	///
+	/// # Errors
+	///
+	/// Fails if the functions could not be registered.
+	pub fn new() -> LuaResult<Self> {
+		let lua = Lua::new();
+		lua.set_app_data(Context::default());
+
+		register(&lua)?;
+
+		Ok(Self {
+			lua,
+			scripts: Vec::new(),
+			environments: Vec::new(),
+			next_id: 0,
+		})
+	}
+
+	#[must_use]
+	pub fn lua(&self) -> &Lua {
+		&self.lua
+	}
+
+	/// Schedules a coroutine to begin on the next frame.
+	///
+	/// The coroutine is passed as a registry key so that it may outlive the borrow of `lua()`
+	/// that created it.
+	pub fn spawn(&mut self, thread: LuaRegistryKey, subject: Option<Entity>) -> ScriptId {
+		self.spawn_with(thread, subject, Vec::new())
+	}
+
+	fn spawn_with(
+		&mut self,
+		thread: LuaRegistryKey,
+		subject: Option<Entity>,
+		arguments: Vec<Variable>,
+	) -> ScriptId {
+		let id = ScriptId(self.next_id);
+		self.next_id += 1;
+		self.scripts.push(Script {
+			id,
+			thread,
+			subject,
+			arguments: Some(arguments),
+			waiting: Vec::new(),
+		});
+		id
+	}
+
+	/// Runs a script file, which may register handlers in its `signal` table.
+	///
+	/// Each file has its own globals, so handlers do not conflict between scripts.
+	/// The top level of a script may not yield or call functions which affect the game.
+	///
+	/// # Errors
+	///
+	/// Fails if the script could not be parsed or raised an error.
+	pub fn load(
+		&mut self,
+		name: impl Into<String>,
+		source: &str,
+		subject: Option<Entity>,
+		triggers: Vec<String>,
+	) -> LuaResult<()> {
+		let table = {
+			let lua = &self.lua;
+			let environment = lua.create_table()?;
+			let metatable = lua.create_table()?;
+			metatable.set("__index", lua.globals())?;
+			environment.set_metatable(Some(metatable));
+			environment.set("signal", lua.create_table()?)?;
+
+			lua.load(source)
+				.set_name(name.into())
+				.set_environment(environment.clone())
+				.exec()?;
+			lua.create_registry_value(environment)?
+		};
+		self.environments.push(Environment {
+			table,
+			subject,
+			triggers,
+		});
+		Ok(())
+	}
+
+	/// Stops every running coroutine and forgets every loaded script.
+	pub fn clear(&mut self) {
+		for script in self.scripts.drain(..) {
+			let _ = self.lua.remove_registry_value(script.thread);
+		}
+		for environment in self.environments.drain(..) {
+			let _ = self.lua.remove_registry_value(environment.table);
+		}
+		if let Some(mut context) = self.lua.app_data_mut::<Context>() {
+			context.pending.clear();
+			context.results.clear();
+		}
+	}
+
+	/// Forgets the scripts attached to an entity, such as a unit that has been defeated.
+	///
+	/// Coroutines that have already started are left to finish.
+	pub fn detach(&mut self, subject: Entity) {
+		let (detached, kept) = self
+			.environments
+			.drain(..)
+			.partition(|environment| environment.subject == Some(subject));
+		self.environments = kept;
+		for environment in detached {
+			let _ = self.lua.remove_registry_value(environment.table);
+		}
+	}
+
+	/// Starts the handler named `signal` in every script which has registered one.
+	///
+	/// If `subject` is set, only scripts attached to that entity are notified.
+	pub fn dispatch(&mut self, signal: &str, subject: Option<Entity>, arguments: Vec<Variable>) {
+		self.dispatch_where(signal, arguments, |environment| {
+			subject.is_none() || environment.subject == subject
+		});
+	}
+
+	/// Sends `signal.trigger` to the scripts listening to a trigger.
+	pub fn trigger(&mut self, identifier: &str, arguments: Vec<Variable>) {
+		self.dispatch_where("trigger", arguments, |environment| {
+			environment.subject.is_none()
+				|| environment
+					.triggers
+					.iter()
+					.any(|trigger| trigger == identifier)
+		});
+	}
+
+	fn dispatch_where(
+		&mut self,
+		signal: &str,
+		arguments: Vec<Variable>,
+		filter: impl Fn(&Environment) -> bool,
+	) {
+		let mut threads = Vec::new();
+		for environment in self
+			.environments
+			.iter()
+			.filter(|environment| filter(environment))
+		{
+			let thread = self
+				.lua
+				.registry_value::<LuaTable>(&environment.table)
+				.and_then(|table| table.get::<_, LuaTable>("signal"))
+				.and_then(|signals| signals.get::<_, Option<LuaFunction>>(signal))
+				.and_then(|handler| {
+					handler
+						.map(|handler| {
+							self.lua
+								.create_thread(handler)
+								.and_then(|thread| self.lua.create_registry_value(thread))
+						})
+						.transpose()
+				});
+			match thread {
+				Ok(Some(thread)) => threads.push((thread, environment.subject)),
+				Ok(None) => {}
+				Err(msg) => error!("Failed to start signal.{signal}: {msg}"),
+			}
+		}
+		for (thread, subject) in threads {
+			self.spawn_with(thread, subject, arguments.clone());
+		}
+	}
+
+	/// Returns true if no coroutines are running.
+	#[must_use]
+	pub fn is_idle(&self) -> bool {
+		self.scripts.is_empty()
+	}
+
+	/// Marks work started by a script as finished.
+	pub fn finish(&mut self, handle: Handle) {
+		if let Some(mut context) = self.lua.app_data_mut::<Context>() {
+			context.pending.remove(&handle);
+		}
+	}
+
+	/// Marks work started by a script as finished,
+	/// returning `value` to the script once it resumes.
+	pub fn finish_with(&mut self, handle: Handle, value: Variable) {
+		if let Some(mut context) = self.lua.app_data_mut::<Context>() {
+			context.pending.remove(&handle);
+			context.results.insert(handle, value);
+		}
+	}
+
+	/// Resumes every coroutine whose events have finished,
+	/// returning the work they requested.
+	///
+	/// `variables` are lent to the scripts while they run.
+	pub fn update(&mut self, world: WorldView, variables: &mut Variables) -> Vec<ScriptRequest> {
+		if let Some(mut context) = self.lua.app_data_mut::<Context>() {
+			context.world = world;
+			std::mem::swap(&mut context.variables, variables);
+		}
+
+		let mut finished = Vec::new();
+
+		for script in &mut self.scripts {
+			let ready = self
+				.lua
+				.app_data_ref::<Context>()
+				.is_some_and(|context| !context.is_waiting(script.id, &script.waiting));
+			if !ready {
+				continue;
+			}
+
+			// A new coroutine receives its arguments,
+			// while one which has yielded receives the results of the handles it waited on.
+			let arguments = match script.arguments.take() {
+				Some(arguments) => arguments,
+				None => self
+					.lua
+					.app_data_mut::<Context>()
+					.map(|mut context| {
+						script
+							.waiting
+							.iter()
+							.filter_map(|wait| match wait {
+								Wait::Handle(handle) => context.results.remove(handle),
+								Wait::Event(_) => None,
+							})
+							.collect()
+					})
+					.unwrap_or_default(),
+			};
+			if let Some(mut context) = self.lua.app_data_mut::<Context>() {
+				context.current = Some((script.id, script.subject));
+			}
+			let result = resume(&self.lua, script, arguments);
+			if let Some(mut context) = self.lua.app_data_mut::<Context>() {
+				context.current = None;
+			}
+
+			match result {
+				Ok(Some(waiting)) => script.waiting = waiting,
+				Ok(None) => finished.push(script.id),
+				Err(msg) => {
+					error!("Script error: {msg}");
+					finished.push(script.id);
+				}
+			}
+		}
+
+		for id in finished {
+			if let Some(index) = self.scripts.iter().position(|script| script.id == id) {
+				let script = self.scripts.remove(index);
+				if let Err(msg) = self.lua.remove_registry_value(script.thread) {
+					error!("Failed to free script: {msg}");
+				}
+			}
+			if let Some(mut context) = self.lua.app_data_mut::<Context>() {
+				context.pending.retain(|handle| handle.script != id);
+				context.results.retain(|handle, _| handle.script != id);
+			}
+		}
+
+		self.lua
+			.app_data_mut::<Context>()
+			.map(|mut context| {
+				std::mem::swap(&mut context.variables, variables);
+				std::mem::take(&mut context.requests)
+			})
+			.unwrap_or_default()
+	}
+}
+
+impl Default for ScriptRuntime {
+	fn default() -> Self {
+		Self::new().expect("failed to initialize Lua")
+	}
+}
+
+/// Exposes the engine's functions to Lua.
+fn register(lua: &Lua) -> LuaResult<()> {
+	let globals = lua.globals();
+
+	globals.set(
+		"Event",
+		lua.create_table_from([
+			("Immediately", Event::Immediately),
+			("WaitPrint", Event::WaitPrint),
+			("WaitMove", Event::WaitMove),
+			("WaitCamera", Event::WaitCamera),
+		])?,
+	)?;
+
+	// Dialogue
+	globals.set(
+		"say",
+		lua.create_function(
+			|lua, (first, second, side): (String, Option<String>, Option<String>)| {
+				let (speaker, text) = match second {
+					Some(text) => (Some(first), text),
+					None => (None, first),
+				};
+				let command = Command::Say {
+					speaker,
+					text,
+					side: side
+						.map(|side| from_name(&side))
+						.transpose()?
+						.unwrap_or_default(),
+					choices: Vec::new(),
+				};
+				start(lua, command, Event::WaitPrint)
+			},
+		)?,
+	)?;
+	globals.set(
+		"choose",
+		lua.create_function(
+			|lua, (first, second, third): (String, LuaValue, Option<Vec<String>>)| {
+				let (speaker, text, choices) = match third {
+					Some(choices) => (Some(first), String::from_lua(second, lua)?, choices),
+					None => (None, first, Vec::from_lua(second, lua)?),
+				};
+				if choices.is_empty() {
+					return Err(LuaError::RuntimeError(
+						"choose requires at least one choice".into(),
+					));
+				}
+				let command = Command::Say {
+					speaker,
+					text,
+					side: Side::default(),
+					choices,
+				};
+				start(lua, command, Event::WaitPrint)
+			},
+		)?,
+	)?;
+
+	// Units
+	globals.set(
+		"move",
+		lua.create_function(|lua, (x, y): (i32, i32)| {
+			start(lua, Command::Move { x, y }, Event::WaitMove)
+		})?,
+	)?;
+	globals.set(
+		"move_unit",
+		lua.create_function(|lua, (unit, x, y): (String, i32, i32)| {
+			start(lua, Command::MoveUnit { unit, x, y }, Event::WaitMove)
+		})?,
+	)?;
+	globals.set(
+		"spawn",
+		lua.create_function(|lua, (identifier, options): (String, LuaTable)| {
+			let name = |key| options.get::<_, Option<String>>(key);
+			let placement = UnitPlacement {
+				identifier,
+				unit: name("unit")?.map(|identifier| UnitReference { identifier }),
+				class: ClassReference {
+					identifier: name("class")?.unwrap_or_default(),
+				},
+				alignment: name("alignment")?
+					.map(|alignment| from_name(&alignment))
+					.transpose()?
+					.unwrap_or(Alignment::Enemy),
+				level: options.get::<_, Option<u32>>("level")?.unwrap_or(1),
+				x: options.get("x")?,
+				y: options.get("y")?,
+				weapon: name("weapon")?.map(|identifier| ItemReference { identifier }),
+				behaviour: name("behaviour")?
+					.map(|behaviour| from_name(&behaviour))
+					.transpose()?
+					.unwrap_or_default(),
+				script: name("script")?,
+				leader: options
+					.get::<_, Option<bool>>("leader")?
+					.unwrap_or_default(),
+				..Default::default()
+			};
+			start(lua, Command::Spawn(placement), Event::Immediately)
+		})?,
+	)?;
+	globals.set(
+		"remove",
+		lua.create_function(|lua, unit: String| {
+			start(lua, Command::Remove { unit }, Event::Immediately)
+		})?,
+	)?;
+	globals.set(
+		"unit",
+		lua.create_function(|lua, identifier: String| {
+			let Some(((unit, position), carrying)) = with_context(lua, |context| {
+				let world = &context.world;
+				Ok(world
+					.units
+					.get(&identifier)
+					.cloned()
+					.map(|(unit, position)| {
+						let carrying = unit
+							.carrying
+							.and_then(|cargo| world.carried.get(&cargo).cloned());
+						((unit, position), carrying)
+					}))
+			})?
+			else {
+				return Ok(None);
+			};
+			let table = lua.create_table()?;
+			table.set("identifier", unit.identifier)?;
+			table.set("name", unit.name)?;
+			table.set("class", unit.class.name)?;
+			table.set("alignment", format!("{:?}", unit.alignment))?;
+			table.set("level", unit.level)?;
+			table.set("experience", unit.experience)?;
+			table.set("hp", unit.hp)?;
+			table.set("leader", unit.leader)?;
+			table.set("carrying", carrying)?;
+			table.set("x", position.x)?;
+			table.set("y", position.y)?;
+			table.set("stats", stats_table(lua, &unit.stats)?)?;
+			table.set("weapon", unit.weapon.map(|item| item.name))?;
+			table.set(
+				"items",
+				lua.create_sequence_from(unit.items.into_iter().map(|item| item.name))?,
+			)?;
+			Ok(Some(table))
+		})?,
+	)?;
+
+	// Items
+	globals.set(
+		"give_item",
+		lua.create_function(|lua, (unit, item): (String, String)| {
+			start(lua, Command::GiveItem { unit, item }, Event::Immediately)
+		})?,
+	)?;
+	globals.set(
+		"take_item",
+		lua.create_function(|lua, (unit, item): (String, String)| {
+			start(lua, Command::TakeItem { unit, item }, Event::Immediately)
+		})?,
+	)?;
+	globals.set(
+		"gold",
+		lua.create_function(|lua, ()| with_context(lua, |context| Ok(context.world.gold)))?,
+	)?;
+	globals.set(
+		"give_gold",
+		lua.create_function(|lua, amount: i64| {
+			start(lua, Command::GiveGold { amount }, Event::Immediately)
+		})?,
+	)?;
+
+	// Map
+	globals.set(
+		"set_tile",
+		lua.create_function(|lua, (x, y, tile): (i32, i32, u32)| {
+			start(lua, Command::SetTile { x, y, tile }, Event::Immediately)
+		})?,
+	)?;
+	globals.set(
+		"pan_camera",
+		lua.create_function(|lua, (x, y): (i32, i32)| {
+			start(lua, Command::PanCamera { x, y }, Event::WaitCamera)
+		})?,
+	)?;
+	globals.set(
+		"pan_to_unit",
+		lua.create_function(|lua, unit: String| {
+			start(lua, Command::PanToUnit { unit }, Event::WaitCamera)
+		})?,
+	)?;
+
+	// Actions
+	globals.set(
+		"add_action",
+		lua.create_function(|lua, (name, options): (String, Option<LuaTable>)| {
+			let options = match options {
+				Some(options) => options,
+				None => lua.create_table()?,
+			};
+			let x = options.get::<_, Option<i32>>("x")?;
+			let y = options.get::<_, Option<i32>>("y")?;
+			let action = CustomAction {
+				name,
+				unit: options.get("unit")?,
+				target: options.get("target")?,
+				tile: x.zip(y).map(|(x, y)| Position::new(x, y)),
+				ends_turn: options.get::<_, Option<bool>>("ends_turn")?.unwrap_or(true),
+			};
+			start(lua, Command::AddAction(action), Event::Immediately)
+		})?,
+	)?;
+	globals.set(
+		"remove_action",
+		lua.create_function(|lua, name: String| {
+			start(lua, Command::RemoveAction { name }, Event::Immediately)
+		})?,
+	)?;
+
+	// Flags and variables
+	globals.set(
+		"set_flag",
+		lua.create_function(|lua, (name, value): (String, Option<bool>)| {
+			with_context(lua, |context| {
+				if value.unwrap_or(true) {
+					context.variables.flags.insert(name);
+				} else {
+					context.variables.flags.remove(&name);
+				}
+				context.handle(Event::Immediately).map(|(handle, _)| handle)
+			})
+		})?,
+	)?;
+	globals.set(
+		"flag",
+		lua.create_function(|lua, name: String| {
+			with_context(lua, |context| Ok(context.variables.flags.contains(&name)))
+		})?,
+	)?;
+	globals.set(
+		"set_var",
+		lua.create_function(|lua, (name, value): (String, Option<Variable>)| {
+			with_context(lua, |context| {
+				if let Some(value) = value {
+					context.variables.values.insert(name, value);
+				} else {
+					context.variables.values.remove(&name);
+				}
+				context.handle(Event::Immediately).map(|(handle, _)| handle)
+			})
+		})?,
+	)?;
+	globals.set(
+		"var",
+		lua.create_function(|lua, name: String| {
+			with_context(lua, |context| {
+				Ok(context.variables.values.get(&name).cloned())
+			})
+		})?,
+	)?;
+
+	// Turns
+	globals.set(
+		"turn",
+		lua.create_function(|lua, ()| with_context(lua, |context| Ok(context.world.turn.number)))?,
+	)?;
+	globals.set(
+		"phase",
+		lua.create_function(|lua, ()| {
+			with_context(lua, |context| Ok(format!("{:?}", context.world.turn.phase)))
+		})?,
+	)?;
+
+	Ok(())
+}
+
+fn with_context<R>(lua: &Lua, f: impl FnOnce(&mut Context) -> LuaResult<R>) -> LuaResult<R> {
+	let mut context = lua
+		.app_data_mut::<Context>()
+		.ok_or_else(|| LuaError::RuntimeError("script context is missing".into()))?;
+	f(&mut context)
+}
+
+fn start(lua: &Lua, command: Command, event: Event) -> LuaResult<Handle> {
+	with_context(lua, |context| context.start(command, event))
+}
+
+/// Parses the name of a unit variant, such as an `Alignment`.
+fn from_name<T: DeserializeOwned>(name: &str) -> LuaResult<T> {
+	let deserializer: StrDeserializer<serde::de::value::Error> = name.into_deserializer();
+	T::deserialize(deserializer)
+		.map_err(|msg| LuaError::RuntimeError(format!("invalid value {name:?}: {msg}")))
+}
+
+fn stats_table<'lua>(lua: &'lua Lua, stats: &Stats) -> LuaResult<LuaTable<'lua>> {
+	lua.create_table_from([
+		("hp", stats.hp),
+		("power", stats.power),
+		("defense", stats.defense),
+		("resistance", stats.resistance),
+		("dexterity", stats.dexterity),
+		("movement", stats.movement),
+		("constitution", stats.constitution),
+		("reflexes", stats.reflexes),
+	])
+}
+
+/// Resumes a script's coroutine,
+/// returning what it is now waiting on, or `None` if it has completed.
+fn resume(lua: &Lua, script: &Script, arguments: Vec<Variable>) -> LuaResult<Option<Vec<Wait>>> {
+	let thread: LuaThread = lua.registry_value(&script.thread)?;
+	if thread.status() != LuaThreadStatus::Resumable {
+		return Ok(None);
+	}
+	let arguments = Variadic::from_iter(arguments);
+	let value: LuaValue = thread.resume(arguments)?;
+	if thread.status() != LuaThreadStatus::Resumable {
+		return Ok(None);
+	}
+
+	match value {
+		LuaValue::Nil => Ok(Vec::new()),
+		LuaValue::Table(table) => table.sequence_values::<Wait>().collect(),
+		value => Wait::from_lua(value, lua).map(|wait| vec![wait]),
+	}
+	.map(Some)
+}
+
+/// Resumes scripts whose events have finished, and forwards their requests to the engine.
+#[allow(clippy::too_many_arguments)]
+fn run_scripts(
+	mut runtime: NonSendMut<ScriptRuntime>,
+	mut finished: EventReader<ScriptEventFinished>,
+	mut results: EventReader<ScriptEventResult>,
+	mut requests: EventWriter<ScriptRequest>,
+	mut variables: ResMut<Variables>,
+	turn: Res<Turn>,
+	campaign: Res<Campaign>,
+	units: Query<(&Unit, &Position)>,
+	carried: Query<(Entity, &Unit), Without<Position>>,
+) {
+	for ScriptEventResult { handle, value } in results.iter() {
+		runtime.finish_with(*handle, value.clone());
+	}
+	for ScriptEventFinished(handle) in finished.iter() {
+		runtime.finish(*handle);
+	}
+	if runtime.is_idle() {
+		return;
+	}
+
+	let world = WorldView {
+		turn: turn.clone(),
+		units: units
+			.iter()
+			.map(|(unit, position)| (unit.identifier.clone(), (unit.clone(), *position)))
+			.collect(),
+		carried: carried
+			.iter()
+			.map(|(entity, unit)| (entity, unit.identifier.clone()))
+			.collect(),
+		gold: campaign.gold,
+	};
+	requests.send_batch(runtime.update(world, &mut variables));
+}
+
+/// Attaches a script file to a unit, relative to the map's directory.
+#[derive(Component, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UnitScript {
+	pub path: String,
+	/// Identifiers of the triggers which this script listens to.
+	pub triggers: Vec<String>,
+}
+
+/// Sent when a unit interacts with another, notifying the target's scripts.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Interact {
+	pub unit: Entity,
+	pub target: Entity,
+}
+
+/// Sent when a script removes a unit from the map without defeating it.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct UnitRemoved {
+	pub entity: Entity,
+}
+
+/// Trigger regions which a unit has walked into while its move could still be taken back.
+///
+/// These fire once the unit has `Acted`, and are forgotten if the component is replaced.
+#[derive(Component, Clone, Debug, Default)]
+pub struct PendingTriggers(Vec<String>);
+
+/// Sent when a script file could not be loaded, so that the error can be shown to the player.
+#[derive(Event, Clone, Debug)]
+pub struct ScriptLoadFailed {
+	pub path: PathBuf,
+	pub message: String,
+}
+
+fn load_file(
+	runtime: &mut ScriptRuntime,
+	failures: &mut EventWriter<ScriptLoadFailed>,
+	path: &Path,
+	subject: Option<Entity>,
+	triggers: Vec<String>,
+) {
+	let result = fs::read_to_string(path)
+		.map_err(|msg| msg.to_string())
+		.and_then(|source| {
+			runtime
+				.load(path.display().to_string(), &source, subject, triggers)
+				.map_err(|msg| msg.to_string())
+		});
+	if let Err(msg) = result {
+		error!("Failed to load {}: {msg}", path.display());
+		failures.send(ScriptLoadFailed {
+			path: path.to_path_buf(),
+			message: msg,
+		});
+	}
+}
+
+/// Loads the scripts of each map, chapter, and unit as they appear.
+fn load_scripts(
+	mut runtime: NonSendMut<ScriptRuntime>,
+	content: Res<Content>,
+	campaign: Res<Campaign>,
+	mut maps: EventReader<MapLoaded>,
+	mut failures: EventWriter<ScriptLoadFailed>,
+	attached: Query<(Entity, &UnitScript), Added<UnitScript>>,
+) {
+	for MapLoaded(identifier) in maps.iter() {
+		runtime.clear();
+		if let Some(script) = content
+			.maps
+			.get(identifier)
+			.and_then(|map| map.script.as_ref())
+		{
+			load_file(
+				&mut runtime,
+				&mut failures,
+				&content.path("maps", script),
+				None,
+				Vec::new(),
+			);
+		}
+		let chapter = campaign.current(&content, identifier);
+		for script in chapter
+			.iter()
+			.flat_map(|chapter| chapter.pre_script.iter().chain(&chapter.post_script))
+		{
+			load_file(
+				&mut runtime,
+				&mut failures,
+				&content.path("chapters", script),
+				None,
+				Vec::new(),
+			);
+		}
+	}
+	for (entity, script) in &attached {
+		load_file(
+			&mut runtime,
+			&mut failures,
+			&content.path("maps", &script.path),
+			Some(entity),
+			script.triggers.clone(),
+		);
+	}
+}
+
+/// The components of each unit which trigger regions read.
+type MovedUnits<'w, 's> = Query<
+	'w,
+	's,
+	(
+		&'static Unit,
+		Ref<'static, Position>,
+		Option<&'static mut PendingTriggers>,
+		Has<Acted>,
+	),
+>;
+
+/// Starts the `signal` handlers of loaded scripts in response to engine events.
+#[allow(clippy::too_many_arguments)]
+pub fn dispatch_signals(
+	mut runtime: NonSendMut<ScriptRuntime>,
+	content: Res<Content>,
+	board: Option<ResMut<Board>>,
+	mut phases: EventReader<PhaseStarted>,
+	mut defeated: EventReader<UnitDefeated>,
+	mut level_ups: EventReader<LevelUp>,
+	mut interactions: EventReader<Interact>,
+	mut escapes: EventReader<UnitEscaped>,
+	mut removals: EventReader<UnitRemoved>,
+	mut outcomes: EventReader<MapFinished>,
+	units: Query<&Unit>,
+	mut moved: MovedUnits,
+) {
+	for phase in phases.iter() {
+		runtime.dispatch(
+			"turn_start",
+			None,
+			vec![
+				Variable::Number(phase.turn.into()),
+				Variable::String(format!("{:?}", phase.phase)),
+			],
+		);
+	}
+
+	for UnitDefeated {
+		entity, identifier, ..
+	} in defeated.iter()
+	{
+		runtime.dispatch(
+			"unit_defeated",
+			None,
+			vec![Variable::String(identifier.clone())],
+		);
+		runtime.detach(*entity);
+	}
+
+	for LevelUp {
+		identifier, level, ..
+	} in level_ups.iter()
+	{
+		runtime.dispatch(
+			"level_up",
+			None,
+			vec![
+				Variable::String(identifier.clone()),
+				Variable::Number((*level).into()),
+			],
+		);
+	}
+
+	for UnitEscaped { entity, unit } in escapes.iter() {
+		runtime.dispatch(
+			"unit_escaped",
+			None,
+			vec![Variable::String(unit.identifier.clone())],
+		);
+		runtime.detach(*entity);
+	}
+
+	for UnitRemoved { entity } in removals.iter() {
+		runtime.detach(*entity);
+	}
+
+	for MapFinished { outcome, reason } in outcomes.iter() {
+		let signal = match outcome {
+			Outcome::Victory => "victory",
+			Outcome::Defeat => "defeat",
+		};
+		runtime.dispatch(signal, None, vec![Variable::String(reason.clone())]);
+	}
+
+	for Interact { unit, target } in interactions.iter() {
+		let Ok(unit) = units.get(*unit) else {
+			continue;
+		};
+		runtime.dispatch(
+			"interact",
+			Some(*target),
+			vec![Variable::String(unit.identifier.clone())],
+		);
+	}
+
+	let Some(mut board) = board else {
+		return;
+	};
+	let Some(triggers) = content.maps.get(&board.map).map(|map| &map.triggers) else {
+		return;
+	};
+	for (unit, position, pending, acted) in &mut moved {
+		let mut entered = Vec::new();
+		// Units spawned or dropped inside a region have not moved into it.
+		if position.is_changed()
+			&& !position.is_added()
+			&& unit.alignment == Alignment::Player1
+			&& position.x >= 0
+			&& position.y >= 0
+		{
+			entered.extend(
+				triggers
+					.iter()
+					.filter(|trigger| trigger.contains(position.x as u32, position.y as u32))
+					.map(|trigger| trigger.identifier.clone()),
+			);
+		}
+		if let Some(mut pending) = pending {
+			if !acted {
+				pending.0.extend(entered);
+				continue;
+			}
+			entered.splice(0..0, pending.0.drain(..));
+		}
+		for identifier in entered {
+			if board.triggered.insert(identifier.clone()) {
+				runtime.trigger(
+					&identifier,
+					vec![
+						Variable::String(identifier.clone()),
+						Variable::String(unit.identifier.clone()),
+					],
+				);
+			}
+		}
+	}
+}
+
+/// Which scripts are waiting on each moving unit.
+#[derive(Resource, Clone, Debug, Default)]
+struct PendingMoves(HashMap<Entity, Vec<Handle>>);
+
+/// Which scripts are waiting on the camera.
+#[derive(Resource, Clone, Debug, Default)]
+struct PendingPans(Vec<Handle>);
+
+fn handle_movement(
+	mut requests: EventReader<ScriptRequest>,
+	units: Query<(Entity, &Unit, &Position)>,
+	mut pending: ResMut<PendingMoves>,
+	mut moves: EventWriter<MoveUnit>,
+	mut finished: EventWriter<ScriptEventFinished>,
+) {
+	for request in requests.iter() {
+		let target = match &request.command {
+			Command::Move { x, y } => request.subject.and_then(|subject| {
+				let (entity, _, position) = units.get(subject).ok()?;
+				Some((entity, Position::new(position.x + x, position.y + y)))
+			}),
+			Command::MoveUnit { unit, x, y } => units
+				.iter()
+				.find(|(_, other, _)| other.identifier == *unit)
+				.map(|(entity, _, _)| (entity, Position::new(*x, *y))),
+			_ => continue,
+		};
+		let Some((entity, destination)) = target else {
+			warn!("Script tried to move a missing unit");
+			finished.send(ScriptEventFinished(request.handle));
+			continue;
+		};
+		pending.0.entry(entity).or_default().push(request.handle);
+		moves.send(MoveUnit {
+			entity,
+			destination,
+			path: Vec::new(),
+		});
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_units(
+	mut commands: Commands,
+	mut requests: EventReader<ScriptRequest>,
+	content: Res<Content>,
+	mut campaign: ResMut<Campaign>,
+	board: Option<Res<Board>>,
+	asset_server: Res<AssetServer>,
+	mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+	mut units: Query<(Entity, &mut Unit)>,
+	positions: Query<&Position>,
+	mut removals: EventWriter<UnitRemoved>,
+	mut finished: EventWriter<ScriptEventFinished>,
+) {
+	for request in requests.iter() {
+		match &request.command {
+			Command::Spawn(placement) => {
+				let Some(board) = &board else {
+					warn!(
+						"Script tried to spawn {} without a map",
+						placement.identifier
+					);
+					finished.send(ScriptEventFinished(request.handle));
+					continue;
+				};
+				if let Err(msg) = spawn_unit(
+					&mut commands,
+					&asset_server,
+					&mut texture_atlases,
+					&content,
+					board,
+					placement,
+				) {
+					error!("Failed to spawn {}: {msg}", placement.identifier);
+				}
+			}
+			Command::Remove { unit } => {
+				if let Some(entity) = find_unit(&units, unit) {
+					commands.entity(entity).despawn_recursive();
+					removals.send(UnitRemoved { entity });
+					// Anyone being carried is left where their carrier stood.
+					if let (Ok((_, unit)), Ok(position)) =
+						(units.get(entity), positions.get(entity))
+					{
+						drop_cargo(&mut commands, unit, *position);
+					}
+					for (_, mut carrier) in &mut units {
+						if carrier.carrying == Some(entity) {
+							carrier.carrying = None;
+						}
+					}
+				}
+			}
+			Command::GiveItem { unit, item } => {
+				if let Some(data) = content.items.get(item) {
+					if let Some(Ok((_, mut unit))) =
+						find_unit(&units, unit).map(|entity| units.get_mut(entity))
+					{
+						if unit.has_room(content.module.inventory.slots) {
+							unit.items.push(data.clone());
+						} else if unit.alignment == PLAYER
+							&& content.module.inventory.convoy != ConvoyAccess::None
+						{
+							info!(
+								"{} has no room for {}, so it was sent to the convoy",
+								unit.name, data.name
+							);
+							campaign.convoy.push(data.clone());
+						} else {
+							warn!("{} has no room for {}", unit.name, data.name);
+						}
+					}
+				} else {
+					warn!("Script tried to give a missing item: {item}");
+				}
+			}
+			Command::TakeItem { unit, item } => {
+				let name = content.items.get(item).map_or(item, |data| &data.name);
+				if let Some(Ok((_, mut unit))) =
+					find_unit(&units, unit).map(|entity| units.get_mut(entity))
+				{
+					if let Some(index) = unit.items.iter().position(|item| item.name == *name) {
+						unit.items.remove(index);
+					} else if unit.weapon.as_ref().is_some_and(|item| item.name == *name) {
+						unit.weapon = None;
+					}
+				}
+			}
+			_ => continue,
+		}
+		finished.send(ScriptEventFinished(request.handle));
+	}
+}
+
+fn find_unit(units: &Query<(Entity, &mut Unit)>, identifier: &str) -> Option<Entity> {
+	let found = units
+		.iter()
+		.find(|(_, unit)| unit.identifier == identifier)
+		.map(|(entity, _)| entity);
+	if found.is_none() {
+		warn!("Script referenced a missing unit: {identifier}");
+	}
+	found
+}
+
+fn handle_map(
+	mut requests: EventReader<ScriptRequest>,
+	units: Query<(&Unit, &Position)>,
+	mut pending: ResMut<PendingPans>,
+	mut tiles: EventWriter<ChangeTile>,
+	mut pans: EventWriter<PanCamera>,
+	mut finished: EventWriter<ScriptEventFinished>,
+) {
+	for request in requests.iter() {
+		match &request.command {
+			Command::SetTile { x, y, tile } => {
+				tiles.send(ChangeTile {
+					position: Position::new(*x, *y),
+					tile: *tile,
+				});
+				finished.send(ScriptEventFinished(request.handle));
+			}
+			Command::PanCamera { x, y } => {
+				pending.0.push(request.handle);
+				pans.send(PanCamera(Position::new(*x, *y)));
+			}
+			Command::PanToUnit { unit } => {
+				if let Some((_, position)) =
+					units.iter().find(|(other, _)| other.identifier == *unit)
+				{
+					pending.0.push(request.handle);
+					pans.send(PanCamera(*position));
+				} else {
+					warn!("Script tried to pan to a missing unit: {unit}");
+					finished.send(ScriptEventFinished(request.handle));
+				}
+			}
+			_ => {}
+		}
+	}
+}
+
+fn finish_moves(
+	mut moves: EventReader<MoveFinished>,
+	mut pending: ResMut<PendingMoves>,
+	mut finished: EventWriter<ScriptEventFinished>,
+) {
+	for MoveFinished { entity } in moves.iter() {
+		for handle in pending.0.remove(entity).unwrap_or_default() {
+			finished.send(ScriptEventFinished(handle));
+		}
+	}
+}
+
+fn finish_pans(
+	mut pans: EventReader<PanFinished>,
+	mut pending: ResMut<PendingPans>,
+	mut finished: EventWriter<ScriptEventFinished>,
+) {
+	if pans.iter().count() > 0 {
+		finished.send_batch(pending.0.drain(..).map(ScriptEventFinished));
+	}
+}
+
+/// Runs Lua coroutines alongside the game.
+pub struct ScriptPlugin;
+
+impl Plugin for ScriptPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_non_send_resource::<ScriptRuntime>()
+			.init_resource::<Variables>()
+			.init_resource::<PendingMoves>()
+			.init_resource::<PendingPans>()
+			.add_event::<ScriptRequest>()
+			.add_event::<ScriptEventFinished>()
+			.add_event::<ScriptEventResult>()
+			.add_event::<Interact>()
+			.add_event::<UnitRemoved>()
+			.add_event::<ScriptLoadFailed>()
+			.add_systems(
+				Update,
+				(
+					// Units spawned with the map must exist before their scripts can be loaded.
+					apply_deferred,
+					load_scripts,
+					dispatch_signals,
+					run_scripts,
+					(handle_movement, handle_units, handle_map),
+					(finish_moves, finish_pans),
+				)
+					.chain()
+					.after(load_map),
+			);
+	}
+}
@@ -0,0 +1,50 @@
+use crate::campaign::Campaign;
+use crate::map::Position;
+use crate::module::Content;
+use fe_data::{Economy, Item, Shop};
+use std::num::NonZeroU32;
+
+/// Gold a shop charges for an item, or `None` if it cannot be bought.
+#[must_use]
+pub fn price(item: &Item) -> Option<u32> {
+	item.value.map(NonZeroU32::get)
+}
+
+/// Gold a shop pays for an item, or `None` if it cannot be sold.
+///
+/// Items without a `value` cannot be sold.
+#[must_use]
+pub fn sell_price(item: &Item, economy: &Economy) -> Option<u32> {
+	let value = u64::from(price(item)?);
+	let price = value * u64::from(economy.sell_ratio) / 100;
+	Some(price.min(u64::from(u32::MAX)) as u32)
+}
+
+/// Returns the shops open on a map: its own, followed by those of the chapter being played on it.
+#[must_use]
+pub fn shops(content: &Content, campaign: &Campaign, map: &str) -> Vec<Shop> {
+	let mut shops = content
+		.maps
+		.get(map)
+		.map(|map| map.shops.clone())
+		.unwrap_or_default();
+	if let Some(chapter) = campaign.current(content, map) {
+		shops.extend(chapter.shops);
+	}
+	shops
+}
+
+/// Returns true if a shop is visited from this tile.
+#[must_use]
+pub fn is_at(shop: &Shop, position: Position) -> bool {
+	Position::new(shop.x as i32, shop.y as i32) == position
+}
+
+/// Looks up the items a shop sells, skipping any which do not exist.
+#[must_use]
+pub fn stock<'a>(shop: &Shop, content: &'a Content) -> Vec<&'a Item> {
+	shop.items
+		.iter()
+		.filter_map(|item| content.items.get(&item.identifier))
+		.collect()
+}
@@ -0,0 +1,354 @@
+use crate::action::destinations;
+use crate::ai::Actor;
+use crate::map::{Board, Position};
+use fe_data::Class;
+use std::collections::HashMap;
+
+/// A class skill which moves units around as a map action.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Skill {
+	/// Pushes an adjacent unit one tile further away.
+	Shove,
+	/// Steps back one tile, bringing an adjacent unit into the vacated tile.
+	Pull,
+	/// Jumps over an adjacent unit, landing on its other side.
+	Leap,
+}
+
+impl Skill {
+	/// The skills which a class has, in the order they are shown in the unit menu.
+	#[must_use]
+	pub fn of(class: &Class) -> Vec<Self> {
+		[
+			(class.shove, Self::Shove),
+			(class.pull, Self::Pull),
+			(class.leap, Self::Leap),
+		]
+		.into_iter()
+		.filter_map(|(has, skill)| has.then_some(skill))
+		.collect()
+	}
+
+	#[must_use]
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Shove => "Shove",
+			Self::Pull => "Pull",
+			Self::Leap => "Leap",
+		}
+	}
+}
+
+/// Where the acting unit and its target stand once a skill has been used.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Displacement {
+	pub actor: Position,
+	pub target: Position,
+}
+
+/// Works out where a skill would move `actor` and `target`,
+/// or returns `None` if it cannot be used on them.
+///
+/// `target` must be adjacent to `actor`.
+/// Each unit which moves must be able to stand on its new tile, which must not be occupied by anyone else.
+/// `units` should contain every unit on the map, and may include both `actor` and `target`.
+#[must_use]
+pub fn displace(
+	skill: Skill,
+	actor: &Actor,
+	target: &Actor,
+	units: &[Actor],
+	board: &Board,
+) -> Option<Displacement> {
+	if actor.position.distance(target.position) != 1 {
+		return None;
+	}
+	let step = Position::new(
+		target.position.x - actor.position.x,
+		target.position.y - actor.position.y,
+	);
+	let beyond = |position: Position| Position::new(position.x + step.x, position.y + step.y);
+	let behind = |position: Position| Position::new(position.x - step.x, position.y - step.y);
+	let displacement = match skill {
+		Skill::Shove => Displacement {
+			actor: actor.position,
+			target: beyond(target.position),
+		},
+		Skill::Pull => Displacement {
+			actor: behind(actor.position),
+			target: actor.position,
+		},
+		Skill::Leap => Displacement {
+			actor: beyond(target.position),
+			target: target.position,
+		},
+	};
+
+	let movers = [(actor, displacement.actor), (target, displacement.target)];
+	let free = |position: Position| {
+		!units.iter().any(|other| {
+			other.position == position
+				&& other.entity != actor.entity
+				&& other.entity != target.entity
+		})
+	};
+	movers
+		.iter()
+		.filter(|(unit, destination)| unit.position != *destination)
+		.all(|(unit, destination)| {
+			board.cost(*destination, &unit.unit.class).is_some() && free(*destination)
+		})
+		.then_some(displacement)
+}
+
+/// Returns true if a unit of this class may move again once it has acted.
+///
+/// `attacked` is whether its action was an attack, which requires `battle_canter` instead of `canter`.
+#[must_use]
+pub fn may_canter(class: &Class, attacked: bool) -> bool {
+	if attacked {
+		class.battle_canter
+	} else {
+		class.canter
+	}
+}
+
+/// Returns the tiles a unit may move to after acting, and what each costs,
+/// given that it has already used `spent` movement this turn.
+///
+/// This is empty if its class does not allow it to move again, or if it has no movement left.
+#[must_use]
+pub fn canter(
+	actor: &Actor,
+	spent: u32,
+	attacked: bool,
+	units: &[Actor],
+	board: &Board,
+) -> HashMap<Position, u32> {
+	let remaining = (actor.unit.stats.movement.max(0) as u32).saturating_sub(spent);
+	if !may_canter(&actor.unit.class, attacked) || remaining == 0 {
+		return HashMap::new();
+	}
+	destinations(actor, remaining, units, board)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{actor, unit, wall};
+	use crate::unit::Unit;
+	use fe_data::Alignment;
+
+	#[test]
+	fn shove_pushes_the_target_away() {
+		let board = Board::clear(5, 5);
+		let shover = unit(Class::default(), Alignment::Player1);
+		let ally = unit(Class::default(), Alignment::Player1);
+		let units = [actor(0, &shover, 1, 2), actor(1, &ally, 2, 2)];
+
+		assert_eq!(
+			displace(Skill::Shove, &units[0], &units[1], &units, &board),
+			Some(Displacement {
+				actor: Position::new(1, 2),
+				target: Position::new(3, 2),
+			})
+		);
+	}
+
+	#[test]
+	fn shove_is_blocked_by_terrain_and_units() {
+		let mut board = Board::clear(5, 5);
+		let shover = unit(Class::default(), Alignment::Player1);
+		let ally = unit(Class::default(), Alignment::Player1);
+		let other = unit(Class::default(), Alignment::Enemy);
+
+		let units = [
+			actor(0, &shover, 1, 2),
+			actor(1, &ally, 2, 2),
+			actor(2, &other, 3, 2),
+		];
+		assert_eq!(
+			displace(Skill::Shove, &units[0], &units[1], &units, &board),
+			None
+		);
+
+		board.set_terrain(Position::new(3, 2), wall());
+		let units = [actor(0, &shover, 1, 2), actor(1, &ally, 2, 2)];
+		assert_eq!(
+			displace(Skill::Shove, &units[0], &units[1], &units, &board),
+			None
+		);
+
+		// Off the edge of the map.
+		let units = [actor(0, &shover, 3, 2), actor(1, &ally, 4, 2)];
+		assert_eq!(
+			displace(Skill::Shove, &units[0], &units[1], &units, &board),
+			None
+		);
+	}
+
+	#[test]
+	fn pull_swaps_ground_with_the_target() {
+		let board = Board::clear(5, 5);
+		let puller = unit(Class::default(), Alignment::Player1);
+		let ally = unit(Class::default(), Alignment::Player1);
+		let units = [actor(0, &puller, 2, 2), actor(1, &ally, 2, 1)];
+
+		assert_eq!(
+			displace(Skill::Pull, &units[0], &units[1], &units, &board),
+			Some(Displacement {
+				actor: Position::new(2, 3),
+				target: Position::new(2, 2),
+			})
+		);
+	}
+
+	#[test]
+	fn pull_needs_room_behind_the_actor() {
+		let mut board = Board::clear(5, 5);
+		let puller = unit(Class::default(), Alignment::Player1);
+		let ally = unit(Class::default(), Alignment::Player1);
+		let other = unit(Class::default(), Alignment::Player1);
+
+		let units = [
+			actor(0, &puller, 2, 2),
+			actor(1, &ally, 2, 1),
+			actor(2, &other, 2, 3),
+		];
+		assert_eq!(
+			displace(Skill::Pull, &units[0], &units[1], &units, &board),
+			None
+		);
+
+		board.set_terrain(Position::new(2, 3), wall());
+		let units = [actor(0, &puller, 2, 2), actor(1, &ally, 2, 1)];
+		assert_eq!(
+			displace(Skill::Pull, &units[0], &units[1], &units, &board),
+			None
+		);
+	}
+
+	#[test]
+	fn leap_lands_beyond_the_target() {
+		let board = Board::clear(5, 5);
+		let leaper = unit(Class::default(), Alignment::Player1);
+		let enemy = unit(Class::default(), Alignment::Enemy);
+		let units = [actor(0, &leaper, 0, 0), actor(1, &enemy, 1, 0)];
+
+		assert_eq!(
+			displace(Skill::Leap, &units[0], &units[1], &units, &board),
+			Some(Displacement {
+				actor: Position::new(2, 0),
+				target: Position::new(1, 0),
+			})
+		);
+	}
+
+	#[test]
+	fn leap_needs_a_free_landing() {
+		let mut board = Board::clear(5, 5);
+		let leaper = unit(Class::default(), Alignment::Player1);
+		let enemy = unit(Class::default(), Alignment::Enemy);
+		let other = unit(Class::default(), Alignment::Enemy);
+
+		let units = [
+			actor(0, &leaper, 0, 0),
+			actor(1, &enemy, 1, 0),
+			actor(2, &other, 2, 0),
+		];
+		assert_eq!(
+			displace(Skill::Leap, &units[0], &units[1], &units, &board),
+			None
+		);
+
+		board.set_terrain(Position::new(2, 0), wall());
+		let units = [actor(0, &leaper, 0, 0), actor(1, &enemy, 1, 0)];
+		assert_eq!(
+			displace(Skill::Leap, &units[0], &units[1], &units, &board),
+			None
+		);
+	}
+
+	#[test]
+	fn skills_need_an_adjacent_target() {
+		let board = Board::clear(5, 5);
+		let user = unit(Class::default(), Alignment::Player1);
+		let ally = unit(Class::default(), Alignment::Player1);
+		let units = [actor(0, &user, 0, 0), actor(1, &ally, 1, 1)];
+
+		for skill in [Skill::Shove, Skill::Pull, Skill::Leap] {
+			assert_eq!(displace(skill, &units[0], &units[1], &units, &board), None);
+		}
+	}
+
+	#[test]
+	fn skills_follow_class_flags() {
+		let class = Class {
+			shove: true,
+			leap: true,
+			..Default::default()
+		};
+		assert_eq!(Skill::of(&class), vec![Skill::Shove, Skill::Leap]);
+		assert!(Skill::of(&Class::default()).is_empty());
+	}
+
+	fn mover(canter: bool, battle_canter: bool) -> Unit {
+		let mut unit = unit(
+			Class {
+				canter,
+				battle_canter,
+				..Default::default()
+			},
+			Alignment::Player1,
+		);
+		unit.stats.movement = 5;
+		unit
+	}
+
+	#[test]
+	fn canter_uses_the_remaining_movement() {
+		let board = Board::clear(9, 9);
+		let rider = mover(true, false);
+		let units = [actor(0, &rider, 4, 4)];
+
+		let tiles = canter(&units[0], 3, false, &units, &board);
+		assert!(tiles.contains_key(&Position::new(4, 2)));
+		assert!(tiles.contains_key(&Position::new(5, 5)));
+		assert!(!tiles.contains_key(&Position::new(4, 1)));
+		assert!(tiles.values().all(|cost| *cost <= 2));
+
+		assert!(canter(&units[0], 5, false, &units, &board).is_empty());
+	}
+
+	#[test]
+	fn canter_only_follows_other_actions() {
+		let board = Board::clear(9, 9);
+		let rider = mover(true, false);
+		let units = [actor(0, &rider, 4, 4)];
+
+		assert!(canter(&units[0], 0, true, &units, &board).is_empty());
+	}
+
+	#[test]
+	fn canter_avoids_other_units() {
+		let board = Board::clear(9, 9);
+		let rider = mover(true, false);
+		let enemy = unit(Class::default(), Alignment::Enemy);
+		let units = [actor(0, &rider, 4, 4), actor(1, &enemy, 4, 3)];
+
+		let tiles = canter(&units[0], 4, false, &units, &board);
+		assert!(!tiles.contains_key(&Position::new(4, 3)));
+		assert!(tiles.contains_key(&Position::new(4, 5)));
+	}
+
+	#[test]
+	fn battle_canter_follows_attacks() {
+		let board = Board::clear(9, 9);
+		let rider = mover(false, true);
+		let units = [actor(0, &rider, 4, 4)];
+
+		let tiles = canter(&units[0], 4, true, &units, &board);
+		assert_eq!(tiles.len(), 5);
+		assert!(canter(&units[0], 4, false, &units, &board).is_empty());
+	}
+}
@@ -0,0 +1,40 @@
+use crate::ai::Actor;
+use crate::map::Position;
+use crate::unit::Unit;
+use bevy::prelude::Entity;
+use fe_data::{Alignment, Class, Item, ItemType, Terrain, WeaponItem};
+
+/// A generic unit of the given class, with its class's base stats.
+pub fn unit(class: Class, alignment: Alignment) -> Unit {
+	Unit::generic("unit", &class, alignment)
+}
+
+/// A weightless weapon, which breaks after `durability` uses, or never if that is 0.
+pub fn weapon(damage: u32, durability: u32) -> Item {
+	Item {
+		ty: ItemType::Weapon(WeaponItem {
+			damage,
+			weight: 0,
+			durability,
+		}),
+		..Default::default()
+	}
+}
+
+/// Places a unit on the board, with `index` standing in for its entity.
+pub fn actor(index: u32, unit: &Unit, x: i32, y: i32) -> Actor<'_> {
+	Actor {
+		entity: Entity::from_raw(index),
+		unit,
+		position: Position::new(x, y),
+		visible: true,
+	}
+}
+
+/// Terrain which no unit can enter.
+pub fn wall() -> Terrain {
+	Terrain {
+		impassable: true,
+		..Default::default()
+	}
+}
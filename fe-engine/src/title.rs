@@ -0,0 +1,303 @@
+use crate::campaign::{Campaign, StartChapter};
+use crate::controls;
+use crate::cursor::UiAction;
+use crate::map::Board;
+use crate::module::Content;
+use crate::objectives::{MapFinished, Outcome};
+use crate::ppcanvas::CanvasCamera;
+use crate::save::{self, CampaignSave, LoadGame, SaveGame, Slot};
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use leafwing_input_manager::prelude::*;
+
+const PADDING: f32 = 4.0;
+const FONT_SIZE: f32 = 8.0;
+const BACKGROUND_COLOR: Color = Color::rgb(0.05, 0.05, 0.15);
+
+/// Something the player can choose from the title menu.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Choice {
+	/// Begins the campaign from its first chapter, in a new slot.
+	NewGame,
+	Load(Slot),
+	/// Shows the title menu in place of the current one.
+	Title,
+}
+
+/// Lets the player start a new campaign, or continue one from a save.
+///
+/// The same menu offers to retry the chapter once the player has been defeated.
+///
+/// The title menu is open for as long as this resource exists.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct TitleMenu {
+	title: String,
+	rows: Vec<(Choice, String)>,
+	selected: usize,
+}
+
+impl TitleMenu {
+	/// Lists each of the module's saves after the option to start a new campaign.
+	///
+	/// Saves of finished campaigns have nothing left to play, so they are not listed.
+	#[must_use]
+	pub fn new(content: &Content) -> Self {
+		let mut rows = vec![(Choice::NewGame, String::from("New game"))];
+		for slot in save::slots(&content.module) {
+			let label = match slot {
+				Slot::Campaign(index) => {
+					let save = match save::read::<CampaignSave>(&content.module, slot) {
+						Ok(save) => save,
+						Err(msg) => {
+							warn!("Skipping {slot:?}: {msg}");
+							continue;
+						}
+					};
+					let Some(chapter) = save.campaign.chapter else {
+						continue;
+					};
+					let name = content
+						.chapter(&chapter)
+						.map_or(chapter, |chapter| chapter.name);
+					format!("Slot {index}: {name}")
+				}
+				Slot::Suspend => String::from("Resume suspended game"),
+			};
+			rows.push((Choice::Load(slot), label));
+		}
+		Self {
+			title: content.module.name.clone(),
+			rows,
+			selected: 0,
+		}
+	}
+
+	/// Offers to retry the current chapter from the campaign's save, or to return to the title menu.
+	#[must_use]
+	pub fn game_over(campaign: &Campaign) -> Self {
+		Self {
+			title: String::from("Game over"),
+			rows: vec![
+				(
+					Choice::Load(Slot::Campaign(campaign.slot)),
+					String::from("Retry chapter"),
+				),
+				(Choice::Title, String::from("Return to title")),
+			],
+			selected: 0,
+		}
+	}
+
+	fn describe(&self) -> String {
+		let mut text = self.title.clone();
+		for (i, (_, label)) in self.rows.iter().enumerate() {
+			let marker = if i == self.selected { '>' } else { ' ' };
+			text += &format!("\n{marker} {label}");
+		}
+		text
+	}
+}
+
+/// Run condition which prevents other systems from reading input while the title menu is open.
+#[must_use]
+pub fn is_closed(menu: Option<Res<TitleMenu>>) -> bool {
+	menu.is_none()
+}
+
+/// Begins the module's campaign in the first unused campaign slot,
+/// which is saved straight away so that the first chapter can be retried.
+fn new_game(
+	content: &Content,
+	campaign: &mut Campaign,
+	chapters: &mut EventWriter<StartChapter>,
+	saves: &mut EventWriter<SaveGame>,
+) {
+	let Some(first) = content.campaign().first().cloned() else {
+		return;
+	};
+	*campaign = Campaign::new(&content.module);
+	campaign.slot = save::slots(&content.module)
+		.into_iter()
+		.filter_map(|slot| match slot {
+			Slot::Campaign(index) => Some(index + 1),
+			Slot::Suspend => None,
+		})
+		.max()
+		.unwrap_or_default();
+	campaign.chapter = Some(first.clone());
+	saves.send(SaveGame(Slot::Campaign(campaign.slot)));
+	chapters.send(StartChapter(first));
+}
+
+/// Opens the title menu once a module has been loaded,
+/// or starts a new campaign straight away if the module has no saves.
+fn open(
+	mut commands: Commands,
+	content: Res<Content>,
+	board: Option<Res<Board>>,
+	mut campaign: ResMut<Campaign>,
+	mut chapters: EventWriter<StartChapter>,
+	mut saves: EventWriter<SaveGame>,
+) {
+	// Content also changes when it is reloaded, which should not restart the chapter.
+	if !content.is_changed() || board.is_some() || content.campaign().is_empty() {
+		return;
+	}
+	let menu = TitleMenu::new(&content);
+	if menu.rows.len() > 1 {
+		commands.insert_resource(menu);
+	} else {
+		new_game(&content, &mut campaign, &mut chapters, &mut saves);
+	}
+}
+
+/// Opens the game over menu once the player loses a map.
+fn game_over(
+	mut commands: Commands,
+	mut outcomes: EventReader<MapFinished>,
+	campaign: Res<Campaign>,
+) {
+	if outcomes
+		.iter()
+		.any(|finished| finished.outcome == Outcome::Defeat)
+	{
+		commands.insert_resource(TitleMenu::game_over(&campaign));
+	}
+}
+
+/// Starts or loads the highlighted campaign once the player presses `Select`.
+#[allow(clippy::too_many_arguments)]
+fn choose(
+	mut commands: Commands,
+	mut menu: ResMut<TitleMenu>,
+	actions: Query<&ActionState<UiAction>>,
+	content: Res<Content>,
+	mut campaign: ResMut<Campaign>,
+	mut chapters: EventWriter<StartChapter>,
+	mut saves: EventWriter<SaveGame>,
+	mut loads: EventWriter<LoadGame>,
+) {
+	let pressed = |action| actions.iter().any(|state| state.just_pressed(action));
+	let count = menu.rows.len();
+	if count == 0 {
+		return;
+	}
+
+	if pressed(UiAction::Up) {
+		menu.selected = (menu.selected + count - 1) % count;
+	}
+	if pressed(UiAction::Down) {
+		menu.selected = (menu.selected + 1) % count;
+	}
+	if pressed(UiAction::Select) {
+		match menu.rows[menu.selected].0 {
+			Choice::NewGame => new_game(&content, &mut campaign, &mut chapters, &mut saves),
+			// The chapter is restarted from the save made before it began.
+			Choice::Load(slot) => loads.send(LoadGame(slot)),
+			Choice::Title => {
+				*menu = TitleMenu::new(&content);
+				return;
+			}
+		}
+		commands.remove_resource::<TitleMenu>();
+	}
+}
+
+#[derive(Component)]
+struct TitleScreen;
+
+#[derive(Component)]
+struct TitleText;
+
+/// Shows the title menu on the pixel-perfect canvas, and removes it once a choice is made.
+fn draw(
+	mut commands: Commands,
+	menu: Option<Res<TitleMenu>>,
+	cameras: Query<(Entity, &Camera), With<CanvasCamera>>,
+	screens: Query<Entity, With<TitleScreen>>,
+	mut texts: Query<&mut Text, With<TitleText>>,
+) {
+	let Some(menu) = menu else {
+		for entity in &screens {
+			commands.entity(entity).despawn_recursive();
+		}
+		return;
+	};
+	if !screens.is_empty() {
+		if menu.is_changed() {
+			for mut text in &mut texts {
+				text.sections[0].value = menu.describe();
+			}
+		}
+		return;
+	}
+
+	let Ok((camera, properties)) = cameras.get_single() else {
+		return;
+	};
+	let Some(size) = properties.logical_viewport_size() else {
+		return;
+	};
+
+	let screen = commands
+		.spawn((
+			TitleScreen,
+			SpriteBundle {
+				sprite: Sprite {
+					color: BACKGROUND_COLOR,
+					custom_size: Some(size),
+					anchor: Anchor::TopLeft,
+					..Default::default()
+				},
+				transform: Transform::from_xyz(-size.x / 2.0, size.y / 2.0, -5.0),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(camera).add_child(screen);
+
+	let text = commands
+		.spawn((
+			TitleText,
+			Text2dBundle {
+				text: Text::from_section(
+					menu.describe(),
+					TextStyle {
+						font_size: FONT_SIZE,
+						color: Color::WHITE,
+						..Default::default()
+					},
+				),
+				text_anchor: Anchor::TopLeft,
+				transform: Transform::from_xyz(PADDING, -PADDING, 1.0),
+				..Default::default()
+			},
+		))
+		.id();
+	commands.entity(screen).add_child(text);
+}
+
+/// Starts the module's campaign once it has been loaded,
+/// first letting the player choose a save to continue from if there are any.
+///
+/// Losing a map opens a game over menu, from which the chapter can be retried.
+pub struct TitlePlugin;
+
+impl Plugin for TitlePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(
+			Update,
+			(
+				game_over,
+				open,
+				apply_deferred,
+				choose
+					.run_if(resource_exists::<TitleMenu>())
+					.run_if(controls::menu_is_closed),
+				apply_deferred,
+				draw,
+			)
+				.chain(),
+		);
+	}
+}
@@ -0,0 +1,134 @@
+use crate::map::{Board, Position};
+use crate::unit::Unit;
+use bevy::ecs::query::Has;
+use bevy::prelude::*;
+use fe_data::Alignment;
+use serde::{Deserialize, Serialize};
+
+/// Order in which each alignment takes its phase.
+pub const PHASE_ORDER: [Alignment; 4] = [
+	Alignment::Player1,
+	Alignment::Player2,
+	Alignment::Ally,
+	Alignment::Enemy,
+];
+
+/// The current turn number and whose phase it is.
+#[derive(Resource, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Turn {
+	pub number: u32,
+	pub phase: Alignment,
+}
+
+impl Default for Turn {
+	fn default() -> Self {
+		Self {
+			number: 1,
+			phase: PHASE_ORDER[0],
+		}
+	}
+}
+
+/// Marks a unit that has finished acting for this phase.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Acted;
+
+/// Sent at the beginning of every phase.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PhaseStarted {
+	pub turn: u32,
+	pub phase: Alignment,
+}
+
+/// Ends the current phase, even if some units have not acted yet.
+#[derive(Event, Clone, Copy, Debug, Default)]
+pub struct EndPhase;
+
+/// Advances to the next phase once every unit in the current one has acted.
+///
+/// Alignments without any units on the map are skipped.
+/// Units being carried by another have no `Position`, and do not need to act.
+fn advance_phase(
+	mut commands: Commands,
+	mut requests: EventReader<EndPhase>,
+	mut turn: ResMut<Turn>,
+	units: Query<(Entity, &Unit, Has<Acted>), With<Position>>,
+	mut started: EventWriter<PhaseStarted>,
+) {
+	let requested = requests.iter().count() > 0;
+
+	if units.is_empty() {
+		return;
+	}
+
+	let finished = units
+		.iter()
+		.filter(|(_, unit, _)| unit.alignment == turn.phase)
+		.all(|(_, _, acted)| acted);
+
+	if !requested && !finished {
+		return;
+	}
+
+	let current = PHASE_ORDER
+		.iter()
+		.position(|phase| *phase == turn.phase)
+		.unwrap_or_default();
+	for offset in 1..=PHASE_ORDER.len() {
+		let index = current + offset;
+		let phase = PHASE_ORDER[index % PHASE_ORDER.len()];
+		if units.iter().any(|(_, unit, _)| unit.alignment == phase) {
+			if index >= PHASE_ORDER.len() {
+				turn.number += 1;
+			}
+			turn.phase = phase;
+			break;
+		}
+	}
+
+	for (entity, _, acted) in &units {
+		if acted {
+			commands.entity(entity).remove::<Acted>();
+		}
+	}
+
+	info!("Turn {}: {:?} phase", turn.number, turn.phase);
+	started.send(PhaseStarted {
+		turn: turn.number,
+		phase: turn.phase,
+	});
+}
+
+/// Restores the hp of units standing on healing terrain at the start of their phase.
+fn heal_on_terrain(
+	mut phases: EventReader<PhaseStarted>,
+	board: Option<Res<Board>>,
+	mut units: Query<(&mut Unit, &Position)>,
+) {
+	let Some(board) = board else {
+		return;
+	};
+	for phase in phases.iter() {
+		for (mut unit, position) in &mut units {
+			if unit.alignment != phase.phase {
+				continue;
+			}
+			let heal = board.terrain(*position).map_or(0, |terrain| terrain.heal);
+			if heal > 0 {
+				unit.hp = (unit.hp + unit.stats.hp * heal / 100).min(unit.stats.hp);
+			}
+		}
+	}
+}
+
+/// Tracks turns and phases.
+pub struct TurnPlugin;
+
+impl Plugin for TurnPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<Turn>()
+			.add_event::<PhaseStarted>()
+			.add_event::<EndPhase>()
+			.add_systems(Update, (advance_phase, heal_on_terrain).chain());
+	}
+}
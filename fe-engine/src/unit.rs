@@ -0,0 +1,203 @@
+use crate::map::Position;
+use crate::module::Content;
+use anyhow::anyhow;
+use bevy::prelude::*;
+use fe_data::{Alignment, Class, Item, ItemType, Stats, UnitPlacement, WeaponItem};
+use serde::{Deserialize, Serialize};
+
+/// Dexterity and reflexes are divided by this while a unit carries another.
+pub const CARRY_PENALTY: i32 = 2;
+
+/// A unit's runtime state on the map.
+///
+/// Created from a `fe_data::Unit` (or just a class, for generic units)
+/// and modified as the chapter progresses.
+#[derive(Component, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Unit {
+	pub name: String,
+	/// Unique identifier used by scripts and map data to refer to this unit.
+	pub identifier: String,
+	pub alignment: Alignment,
+	pub class: Class,
+	/// Identifier of the unit's class within the module, if it was created from one.
+	#[serde(default)]
+	pub class_identifier: Option<String>,
+	pub level: u32,
+	pub experience: u32,
+	/// Maximum stats, with the unit's offsets applied on top of its class bases.
+	pub stats: Stats,
+	/// Growth rates, with the unit's offsets applied on top of its class growths.
+	pub growths: Stats,
+	/// Current hp. The unit is defeated when this reaches 0.
+	pub hp: i32,
+	/// Weapon used in battle, if any.
+	pub weapon: Option<Item>,
+	/// Items carried besides the equipped weapon.
+	pub items: Vec<Item>,
+	/// Losing a leader may lose the map, and only leaders may seize objectives.
+	#[serde(default)]
+	pub leader: bool,
+	/// The unit being carried, which is off the map until it is dropped.
+	///
+	/// Entities do not outlive the map, so saved maps record who carries whom separately.
+	#[serde(skip)]
+	pub carrying: Option<Entity>,
+}
+
+impl Unit {
+	/// Creates a named unit from its data and class.
+	#[must_use]
+	pub fn new(
+		identifier: impl Into<String>,
+		unit: &fe_data::Unit,
+		class: &Class,
+		alignment: Alignment,
+	) -> Self {
+		let stats = &class.bases + &unit.bases;
+		Self {
+			name: unit.name.clone(),
+			identifier: identifier.into(),
+			alignment,
+			class: class.clone(),
+			class_identifier: None,
+			level: 1,
+			experience: 0,
+			hp: stats.hp,
+			stats,
+			growths: &class.growths + &unit.growths,
+			weapon: None,
+			items: Vec::new(),
+			leader: false,
+			carrying: None,
+		}
+	}
+
+	/// Creates a generic unit, which only has a class.
+	#[must_use]
+	pub fn generic(identifier: impl Into<String>, class: &Class, alignment: Alignment) -> Self {
+		Self {
+			name: class.name.clone(),
+			identifier: identifier.into(),
+			alignment,
+			class: class.clone(),
+			class_identifier: None,
+			level: 1,
+			experience: 0,
+			hp: class.bases.hp,
+			stats: class.bases.clone(),
+			growths: class.growths.clone(),
+			weapon: None,
+			items: Vec::new(),
+			leader: false,
+			carrying: None,
+		}
+	}
+
+	/// Creates a unit described by a map's placement data.
+	///
+	/// # Errors
+	///
+	/// Fails if the placement references missing content.
+	pub fn from_placement(placement: &UnitPlacement, content: &Content) -> anyhow::Result<Self> {
+		let (mut unit, class_identifier) = if let Some(reference) = &placement.unit {
+			let data = content
+				.units
+				.get(&reference.identifier)
+				.ok_or_else(|| anyhow!("unknown unit: {}", reference.identifier))?;
+			let class = content
+				.classes
+				.get(&data.class.identifier)
+				.ok_or_else(|| anyhow!("unknown class: {}", data.class.identifier))?;
+			(
+				Self::new(&placement.identifier, data, class, placement.alignment),
+				&data.class.identifier,
+			)
+		} else {
+			let class = content
+				.classes
+				.get(&placement.class.identifier)
+				.ok_or_else(|| anyhow!("unknown class: {}", placement.class.identifier))?;
+			(
+				Self::generic(&placement.identifier, class, placement.alignment),
+				&placement.class.identifier,
+			)
+		};
+
+		unit.class_identifier = Some(class_identifier.clone());
+		unit.level = placement.level.max(1);
+		unit.leader = placement.leader;
+		if let Some(weapon) = &placement.weapon {
+			unit.weapon = Some(
+				content
+					.items
+					.get(&weapon.identifier)
+					.cloned()
+					.ok_or_else(|| anyhow!("unknown item: {}", weapon.identifier))?,
+			);
+		}
+
+		Ok(unit)
+	}
+
+	#[must_use]
+	pub fn is_alive(&self) -> bool {
+		self.hp > 0
+	}
+
+	/// Returns the stats of the equipped weapon, if there is one.
+	#[must_use]
+	pub fn weapon(&self) -> Option<&WeaponItem> {
+		match &self.weapon {
+			Some(Item {
+				ty: ItemType::Weapon(weapon),
+				..
+			}) => Some(weapon),
+			_ => None,
+		}
+	}
+
+	/// Speed used to determine follow-up attacks,
+	/// reduced when a weapon is heavier than the unit's constitution.
+	#[must_use]
+	pub fn attack_speed(&self) -> i32 {
+		let weight = self.weapon().map_or(0, |weapon| weapon.weight as i32);
+		self.battle_stats().reflexes - (weight - self.stats.constitution).max(0)
+	}
+
+	/// Stats used in battle, which are reduced while carrying another unit.
+	#[must_use]
+	pub fn battle_stats(&self) -> Stats {
+		let mut stats = self.stats.clone();
+		if self.carrying.is_some() {
+			stats.dexterity /= CARRY_PENALTY;
+			stats.reflexes /= CARRY_PENALTY;
+		}
+		stats
+	}
+
+	/// Returns true if this unit is able to carry `other`.
+	///
+	/// Units can carry those with less constitution than their own, unless their class can `ferry` anyone.
+	#[must_use]
+	pub fn can_carry(&self, other: &Self) -> bool {
+		self.class.ferry || other.stats.constitution < self.stats.constitution
+	}
+
+	/// Returns true if this unit can hold another item, given how many `slots` each unit has.
+	///
+	/// The equipped weapon takes up a slot like any other item.
+	#[must_use]
+	pub fn has_room(&self, slots: u32) -> bool {
+		usize::from(self.weapon.is_some()) + self.items.len() < slots as usize
+	}
+}
+
+/// Leaves the unit which `carrier` was carrying at `position`, once the carrier has left the map.
+pub fn drop_cargo(commands: &mut Commands, carrier: &Unit, position: Position) {
+	if let Some(mut cargo) = carrier
+		.carrying
+		.and_then(|cargo| commands.get_entity(cargo))
+	{
+		cargo.insert((position, Visibility::Inherited));
+	}
+}
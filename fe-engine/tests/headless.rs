@@ -0,0 +1,37 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a headless session may run before it is assumed to be stuck.
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+#[test]
+fn autoplay_stops_after_its_turn_limit() {
+	let module = Path::new(env!("CARGO_MANIFEST_DIR")).join("../example-game");
+	// Saves and settings are relative to the working directory, so each run starts afresh.
+	let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("autoplay");
+	fs::remove_dir_all(&directory).ok();
+	fs::create_dir_all(&directory).expect("failed to create a working directory");
+
+	let mut engine = Command::new(env!("CARGO_BIN_EXE_fe-engine"))
+		.args(["--headless", "--autoplay", "--turns", "3", "--module"])
+		.arg(module)
+		.current_dir(&directory)
+		.spawn()
+		.expect("failed to start the engine");
+
+	let started = Instant::now();
+	let status = loop {
+		if let Some(status) = engine.try_wait().expect("failed to wait for the engine") {
+			break status;
+		}
+		if started.elapsed() > TIMEOUT {
+			engine.kill().ok();
+			panic!("the engine was still running after {TIMEOUT:?}");
+		}
+		thread::sleep(Duration::from_millis(100));
+	};
+	assert!(status.success(), "the engine exited with {status}");
+}